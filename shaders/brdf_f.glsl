@@ -0,0 +1,63 @@
+#version 330 core
+in vec2 texCoords;
+
+out vec2 FragColor;
+
+const float PI = 3.14159265359;
+const uint SAMPLE_COUNT = 1024u;
+
+// low discrepancy sequence, source: http://holger.dammertz.org/stuff/notes_HammersleyOnHemisphere.html
+vec2 Hammersley(uint i, uint n) {
+    uint bits = i;
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return vec2(float(i) / float(n), float(bits) * 2.3283064365386963e-10);
+}
+
+vec3 ImportanceSampleGGX(vec2 Xi, float roughness) {
+    float a = roughness * roughness;
+    float phi = 2.0 * PI * Xi.x;
+    float cosTheta = sqrt((1.0 - Xi.y) / (1.0 + (a * a - 1.0) * Xi.y));
+    float sinTheta = sqrt(1.0 - cosTheta * cosTheta);
+
+    return vec3(cos(phi) * sinTheta, sin(phi) * sinTheta, cosTheta);
+}
+
+// image based lighting uses a different k than direct lighting
+float GeometrySchlickGGX(float NdotV, float roughness) {
+    float k = (roughness * roughness) / 2.0;
+
+    return NdotV / (NdotV * (1.0 - k) + k);
+}
+
+// scale and bias to F0 of the specular integral, indexed by NdotV and roughness
+void main() {
+    float NdotV = max(texCoords.x, 0.0001);
+    float roughness = texCoords.y;
+    vec3 V = vec3(sqrt(1.0 - NdotV * NdotV), 0.0, NdotV);
+
+    float A = 0.0;
+    float B = 0.0;
+    for (uint i = 0u; i < SAMPLE_COUNT; i++) {
+        // in tangent space, the normal is +z
+        vec3 H = ImportanceSampleGGX(Hammersley(i, SAMPLE_COUNT), roughness);
+        vec3 L = normalize(2.0 * dot(V, H) * H - V);
+
+        float NdotL = max(L.z, 0.0);
+        float NdotH = max(H.z, 0.0);
+        float VdotH = max(dot(V, H), 0.0);
+        if (NdotL > 0.0) {
+            float G = GeometrySchlickGGX(NdotV, roughness) * GeometrySchlickGGX(NdotL, roughness);
+            float visibility = (G * VdotH) / (NdotH * NdotV);
+            float Fc = pow(1.0 - VdotH, 5.0);
+
+            A += (1.0 - Fc) * visibility;
+            B += Fc * visibility;
+        }
+    }
+
+    FragColor = vec2(A, B) / float(SAMPLE_COUNT);
+}
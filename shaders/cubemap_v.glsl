@@ -0,0 +1,13 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+
+out vec3 localPos;
+
+uniform mat4 view;
+uniform mat4 projection;
+
+// renders a cube map face from the center of a unit cube
+void main() {
+    localPos = aPos;
+    gl_Position = projection * view * vec4(aPos, 1.0);
+}
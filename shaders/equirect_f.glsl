@@ -0,0 +1,20 @@
+#version 330 core
+in vec3 localPos;
+
+out vec4 FragColor;
+
+uniform sampler2D equirectMap;
+
+const vec2 invAtan = vec2(0.1591, 0.3183);
+
+vec2 SampleSphericalMap(vec3 v) {
+    vec2 uv = vec2(atan(v.z, v.x), asin(v.y)) * invAtan + 0.5;
+    // image rows start at the top
+    uv.y = 1.0 - uv.y;
+    return uv;
+}
+
+void main() {
+    vec3 color = texture(equirectMap, SampleSphericalMap(normalize(localPos))).rgb;
+    FragColor = vec4(color, 1.0);
+}
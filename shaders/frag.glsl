@@ -1,5 +1,7 @@
-#version 330 core
+#version 430 core
 in vec3 fragNormals;
+in vec3 fragTangent;
+in vec3 fragBitangent;
 in vec3 fragPos;
 in vec2 texCoords;
 
@@ -38,6 +40,10 @@ struct Material {
   sampler2D texture_specular14;
   sampler2D texture_specular15;
   sampler2D texture_specular16;
+  // normal and height maps, shares the uniforms with the pbr shader
+  sampler2D normalMap;
+  bool hasNormalMap;
+  float normalScale;
 
   vec3 ambient;
   vec3 diffuse;
@@ -45,142 +51,193 @@ struct Material {
   float shininess;
 };
 
-struct DirLight {
-  vec3 direction;
+uniform vec3 viewPos;
+uniform Material material;
 
-  vec3 ambient;
-  vec3 diffuse;
-  vec3 specular;
+// same layout as GpuLight in light.rs
+struct Light {
+  vec3 position;
+  // 0 directional, 1 point, 2 spot
+  int type;
+  vec3 direction;
+  // 0 never fades out
+  float range;
+  vec3 color;
+  float intensity;
+  float innerCutOff;
+  float outerCutOff;
+  // layer in the shadow maps of the light's type, -1 without shadows
+  int shadowIndex;
+  float shadowBias;
+  float normalBias;
+  int pcfRadius;
+  vec2 padding;
+  mat4 lightSpace;
 };
 
-struct PointLight {
-  vec3 position;
+layout(std430, binding = 0) readonly buffer Lights {
+  Light lights[];
+};
+uniform int lightCount;
+uniform vec3 ambientLight;
+
+uniform sampler2DArray dirShadowMaps;
+uniform sampler2DArray spotShadowMaps;
+// the maps store the distance to the light divided by pointShadowFar
+uniform samplerCubeArray pointShadowMaps;
+uniform float pointShadowFar;
+
+// sample offsets for filtering cube maps, a 2d kernel doesn't map well onto the faces
+const vec3 cubeSampleOffsets[20] = vec3[](
+  vec3(1, 1, 1), vec3(1, -1, 1), vec3(-1, -1, 1), vec3(-1, 1, 1),
+  vec3(1, 1, -1), vec3(1, -1, -1), vec3(-1, -1, -1), vec3(-1, 1, -1),
+  vec3(1, 1, 0), vec3(1, -1, 0), vec3(-1, -1, 0), vec3(-1, 1, 0),
+  vec3(1, 0, 1), vec3(-1, 0, 1), vec3(1, 0, -1), vec3(-1, 0, -1),
+  vec3(0, 1, 1), vec3(0, -1, 1), vec3(0, -1, -1), vec3(0, 1, -1)
+);
+
+// inverse square falloff that smoothly reaches 0 at the light's range
+float Attenuation(Light light, float distance) {
+  float falloff = 1.0 / max(distance * distance, 0.0001);
+  if (light.range <= 0.0) {
+    return falloff;
+  }
 
-  vec3 ambient;
-  vec3 diffuse;
-  vec3 specular;
+  float ratio = distance / light.range;
+  float window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+  return falloff * window * window;
+}
 
-  float constant;
-  float linear;
-  float quadratic;
-};
+// light that reaches the fragment before shadows, L is the direction towards the light
+vec3 IncomingLight(Light light, out vec3 L) {
+  if (light.type == 0) {
+    L = normalize(-light.direction);
+    return light.color * light.intensity;
+  }
 
-struct SpotLight {
-  vec3 position;
-  vec3 direction;
-  float cutOff;
-  float outerCutOff;
+  vec3 toLight = light.position - fragPos;
+  float distance = length(toLight);
+  L = toLight / max(distance, 0.0001);
+  vec3 radiance = light.color * light.intensity * Attenuation(light, distance);
 
-  vec3 ambient;
-  vec3 diffuse;
-  vec3 specular;
+  if (light.type == 2) {
+    float theta = dot(L, normalize(-light.direction));
+    float epsilon = max(light.innerCutOff - light.outerCutOff, 0.0001);
+    radiance *= clamp((theta - light.outerCutOff) / epsilon, 0.0, 1.0);
+  }
 
-  float constant;
-  float linear;
-  float quadratic;
-};
+  return radiance;
+}
 
-#define NR_POINT_LIGHTS 4
+float MapShadowFactor(sampler2DArray shadowMaps, Light light, vec3 normal) {
+  vec4 lightSpacePos = light.lightSpace * vec4(fragPos + normal * light.normalBias, 1.0);
+  vec3 projected = lightSpacePos.xyz / lightSpacePos.w * 0.5 + 0.5;
+  // nothing outside of the map casts shadows
+  if (lightSpacePos.w <= 0.0 || projected.z > 1.0
+      || any(lessThan(projected.xy, vec2(0.0))) || any(greaterThan(projected.xy, vec2(1.0)))) {
+    return 0.0;
+  }
 
-uniform vec3 viewPos;
-uniform Material material;
-uniform DirLight dirLight;
-uniform PointLight pointLights[NR_POINT_LIGHTS];
-uniform SpotLight spotLight;
+  vec2 texelSize = 1.0 / vec2(textureSize(shadowMaps, 0).xy);
+  float shadowed = 0.0;
+  for (int x = -light.pcfRadius; x <= light.pcfRadius; x++) {
+    for (int y = -light.pcfRadius; y <= light.pcfRadius; y++) {
+      float depth = texture(shadowMaps, vec3(projected.xy + vec2(x, y) * texelSize, light.shadowIndex)).r;
+      shadowed += projected.z - light.shadowBias > depth ? 1.0 : 0.0;
+    }
+  }
 
-vec3 CalculateDirLight(DirLight light, vec3 normal, vec3 viewDir) {
-  vec3 lightDir = normalize(-light.direction);
+  float width = float(light.pcfRadius * 2 + 1);
+  return shadowed / (width * width);
+}
 
-  float diff = max(dot(lightDir, normal), 0.0);
+float PointShadowFactor(Light light, vec3 normal) {
+  vec3 toFrag = fragPos + normal * light.normalBias - light.position;
+  float distance = length(toFrag) / pointShadowFar;
+  if (distance > 1.0) {
+    return 0.0;
+  }
 
-  vec3 reflectDir = reflect(-lightDir, normal);
-  float spec = pow(max(dot(viewDir, reflectDir), 0.0), material.shininess);
+  if (light.pcfRadius == 0) {
+    return distance - light.shadowBias > texture(pointShadowMaps, vec4(toFrag, light.shadowIndex)).r ? 1.0 : 0.0;
+  }
 
-  //vec3 ambient = light.ambient * vec3(texture(material.texture_diffuse1, texCoords)) * material.ambient;
-  //vec3 diffuse = light.diffuse * diff * vec3(texture(material.texture_diffuse1, texCoords)) * material.diffuse;
-  //vec3 specular = light.specular * spec * vec3(texture(material.texture_specular1, texCoords)) * material.specular;
-  vec3 ambient = light.ambient * material.ambient;
-  vec3 diffuse = light.diffuse * diff * material.diffuse;
-  vec3 specular = light.specular * spec * material.specular;
+  // keeps the same angular size at any distance
+  float diskRadius = float(light.pcfRadius) * 0.005 * length(toFrag);
+  float shadowed = 0.0;
+  for (int i = 0; i < 20; i++) {
+    float stored = texture(pointShadowMaps, vec4(toFrag + cubeSampleOffsets[i] * diskRadius, light.shadowIndex)).r;
+    shadowed += distance - light.shadowBias > stored ? 1.0 : 0.0;
+  }
 
-  return (ambient + diffuse + specular);
+  return shadowed / 20.0;
 }
 
-vec3 CalculatePointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir) {
-  vec3 lightDir = normalize(light.position - fragPos);
+// fraction of the light that's blocked, 0 is fully lit
+float ShadowFactor(Light light, vec3 normal) {
+  if (light.shadowIndex < 0) {
+    return 0.0;
+  }
 
-  float diff = max(dot(lightDir, normal), 0.0);
+  if (light.type == 0) {
+    return MapShadowFactor(dirShadowMaps, light, normal);
+  } else if (light.type == 2) {
+    return MapShadowFactor(spotShadowMaps, light, normal);
+  }
 
-  vec3 reflectDir = reflect(-lightDir, normal);
-  float spec = pow(max(dot(viewDir, reflectDir), 0.0), material.shininess);
+  return PointShadowFactor(light, normal);
+}
 
-  // attenuation
-  float distance = length(light.position - fragPos);
-  float attenuation = 1.0 / (light.constant + light.linear * distance + light.quadratic * (distance + distance));
+vec3 GetNormal() {
+  vec3 N = normalize(fragNormals);
+  if (!material.hasNormalMap || dot(fragTangent, fragTangent) == 0.0) {
+    return N;
+  }
 
-  //vec3 ambient = light.ambient * vec3(texture(material.texture_diffuse1, texCoords)) * material.ambient;
-  //vec3 diffuse = light.diffuse * diff * vec3(texture(material.texture_diffuse1, texCoords)) * material.diffuse;
-  //vec3 specular = light.specular * spec * vec3(texture(material.texture_specular1, texCoords)) * material.specular;
-  vec3 ambient = light.ambient * material.ambient;
-  vec3 diffuse = light.diffuse * diff * material.diffuse;
-  vec3 specular = light.specular * spec * material.specular;
+  // re-orthogonalize since interpolation skews the frame
+  vec3 T = normalize(fragTangent - N * dot(N, fragTangent));
+  vec3 B = cross(N, T);
+  // mirrored uvs flip the bitangent
+  if (dot(B, fragBitangent) < 0.0) {
+    B = -B;
+  }
 
-  ambient *= attenuation;
-  diffuse *= attenuation;
-  specular *= attenuation;
+  vec3 mapNormal = texture(material.normalMap, texCoords).xyz * 2.0 - 1.0;
+  mapNormal.xy *= material.normalScale;
 
-  return (ambient + diffuse + specular);
+  return normalize(mat3(T, B, N) * mapNormal);
 }
 
-vec3 CalculateSpotLight(SpotLight light, vec3 normal, vec3 fragPos, vec3 viewDir) {
-  vec3 lightDir = normalize(light.position - fragPos);
-
+// diffuse and specular of a single light, `radiance` already has the light's attenuation and cone
+vec3 CalculateLight(vec3 lightDir, vec3 radiance, vec3 normal, vec3 viewDir) {
   float diff = max(dot(lightDir, normal), 0.0);
 
   vec3 reflectDir = reflect(-lightDir, normal);
   float spec = pow(max(dot(viewDir, reflectDir), 0.0), material.shininess);
 
-  // attenuation
-  float distance = length(light.position - fragPos);
-  float attenuation = 1.0 / (light.constant + light.linear * distance + light.quadratic * (distance + distance));
-
-  // spotlight
-  float theta = dot(lightDir, normalize(-light.direction));
-  float epsilon = light.cutOff - light.outerCutOff;
-  float intensity = clamp((theta - light.outerCutOff) / epsilon, 0.0, 1.0);
+  vec3 diffuse = radiance * diff * material.diffuse;
+  vec3 specular = radiance * spec * material.specular;
 
-  //vec3 ambient = light.ambient * vec3(texture(material.texture_diffuse1, texCoords)) * material.ambient;
-  //vec3 diffuse = light.diffuse * diff * vec3(texture(material.texture_diffuse1, texCoords)) * material.diffuse;
-  //vec3 specular = light.specular * spec * vec3(texture(material.texture_specular1, texCoords)) * material.specular;
-  vec3 ambient = light.ambient * material.ambient;
-  vec3 diffuse = light.diffuse * diff * material.diffuse;
-  vec3 specular = light.specular * spec * material.specular;
-
-  diffuse *= intensity;
-  specular *= intensity;
-
-  ambient *= attenuation;
-  diffuse *= attenuation;
-  specular *= attenuation;
-
-  return (ambient + diffuse + specular);
+  return diffuse + specular;
 }
 
 void main()
 {
-  vec3 norm = normalize(fragNormals);
+  vec3 norm = GetNormal();
   vec3 viewDir = normalize(viewPos - fragPos);
 
-  // direction light
-  vec3 result = CalculateDirLight(dirLight, norm, viewDir);
+  vec3 result = ambientLight * material.ambient;
 
-  // point lights
-  for (int i = 0; i < NR_POINT_LIGHTS; i++) {
-    result += CalculatePointLight(pointLights[i], norm, fragPos, viewDir);
-  }
+  for (int i = 0; i < lightCount; i++) {
+    vec3 lightDir;
+    vec3 radiance = IncomingLight(lights[i], lightDir);
+    if (radiance == vec3(0.0)) {
+      continue;
+    }
 
-  result += CalculateSpotLight(spotLight, norm, fragPos, viewDir);
+    // shadows only block direct light
+    result += CalculateLight(lightDir, radiance, norm, viewDir) * (1.0 - ShadowFactor(lights[i], norm));
+  }
 
   FragColor = vec4(result, 1.0);
 }
-
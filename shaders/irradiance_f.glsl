@@ -0,0 +1,32 @@
+#version 330 core
+in vec3 localPos;
+
+out vec4 FragColor;
+
+uniform samplerCube environmentMap;
+
+const float PI = 3.14159265359;
+
+// cosine weighted average of the environment over the hemisphere around the normal
+void main() {
+    vec3 N = normalize(localPos);
+    vec3 up = abs(N.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(0.0, 0.0, 1.0);
+    vec3 right = normalize(cross(up, N));
+    up = cross(N, right);
+
+    vec3 irradiance = vec3(0.0);
+    float sampleDelta = 0.025;
+    float samples = 0.0;
+    for (float phi = 0.0; phi < 2.0 * PI; phi += sampleDelta) {
+        for (float theta = 0.0; theta < 0.5 * PI; theta += sampleDelta) {
+            vec3 tangentSample = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            vec3 sampleVec = tangentSample.x * right + tangentSample.y * up + tangentSample.z * N;
+
+            // a low mip keeps the sum from aliasing on small bright spots
+            irradiance += textureLod(environmentMap, sampleVec, 4.0).rgb * cos(theta) * sin(theta);
+            samples++;
+        }
+    }
+
+    FragColor = vec4(PI * irradiance / samples, 1.0);
+}
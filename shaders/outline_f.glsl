@@ -0,0 +1,34 @@
+#version 330 core
+in vec2 texCoords;
+
+out vec4 FragColor;
+
+// 1 where the selection is
+uniform sampler2D mask;
+uniform vec3 color;
+// in pixels
+uniform int width;
+
+void main() {
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    ivec2 maxPixel = textureSize(mask, 0) - 1;
+    if (texelFetch(mask, pixel, 0).r > 0.0) {
+        discard;
+    }
+
+    // outside of the selection but close enough to its edge
+    for (int x = -width; x <= width; x++) {
+        for (int y = -width; y <= width; y++) {
+            if (x * x + y * y > width * width) {
+                continue;
+            }
+
+            if (texelFetch(mask, clamp(pixel + ivec2(x, y), ivec2(0), maxPixel), 0).r > 0.0) {
+                FragColor = vec4(color, 1.0);
+                return;
+            }
+        }
+    }
+
+    discard;
+}
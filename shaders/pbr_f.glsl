@@ -0,0 +1,364 @@
+#version 430 core
+in vec3 fragNormals;
+in vec3 fragTangent;
+in vec3 fragBitangent;
+in vec3 fragPos;
+in vec2 texCoords;
+
+out vec4 FragColor;
+
+// metallic-roughness material, factors are multiplied with the maps when a map is bound
+struct Material {
+  vec4 baseColor;
+  float metallic;
+  float roughness;
+  vec3 emissive;
+  float normalScale;
+  float occlusionStrength;
+  float opacity;
+  bool twoSided;
+  // 0 opaque, 1 mask, 2 blend
+  int alphaMode;
+  float alphaCutoff;
+
+  sampler2D baseColorMap;
+  sampler2D metallicRoughnessMap;
+  sampler2D normalMap;
+  sampler2D occlusionMap;
+  sampler2D emissiveMap;
+
+  bool hasBaseColorMap;
+  bool hasMetallicRoughnessMap;
+  bool hasNormalMap;
+  bool hasOcclusionMap;
+  bool hasEmissiveMap;
+};
+
+const float PI = 3.14159265359;
+uniform vec3 viewPos;
+uniform Material material;
+
+// same layout as GpuLight in light.rs
+struct Light {
+  vec3 position;
+  // 0 directional, 1 point, 2 spot
+  int type;
+  vec3 direction;
+  // 0 never fades out
+  float range;
+  vec3 color;
+  float intensity;
+  float innerCutOff;
+  float outerCutOff;
+  // layer in the shadow maps of the light's type, -1 without shadows
+  int shadowIndex;
+  float shadowBias;
+  float normalBias;
+  int pcfRadius;
+  vec2 padding;
+  mat4 lightSpace;
+};
+
+layout(std430, binding = 0) readonly buffer Lights {
+  Light lights[];
+};
+uniform int lightCount;
+// only used without an environment
+uniform vec3 ambientLight;
+
+// image based lighting from the environment, see environment.rs
+uniform bool hasEnvironment;
+uniform samplerCube irradianceMap;
+uniform samplerCube prefilterMap;
+uniform sampler2D brdfLut;
+uniform float prefilterMaxLod;
+// around the y axis in radians
+uniform float envRotation;
+uniform float envIntensity;
+
+uniform sampler2DArray dirShadowMaps;
+uniform sampler2DArray spotShadowMaps;
+// the maps store the distance to the light divided by pointShadowFar
+uniform samplerCubeArray pointShadowMaps;
+uniform float pointShadowFar;
+
+// sample offsets for filtering cube maps, a 2d kernel doesn't map well onto the faces
+const vec3 cubeSampleOffsets[20] = vec3[](
+  vec3(1, 1, 1), vec3(1, -1, 1), vec3(-1, -1, 1), vec3(-1, 1, 1),
+  vec3(1, 1, -1), vec3(1, -1, -1), vec3(-1, -1, -1), vec3(-1, 1, -1),
+  vec3(1, 1, 0), vec3(1, -1, 0), vec3(-1, -1, 0), vec3(-1, 1, 0),
+  vec3(1, 0, 1), vec3(-1, 0, 1), vec3(1, 0, -1), vec3(-1, 0, -1),
+  vec3(0, 1, 1), vec3(0, -1, 1), vec3(0, -1, -1), vec3(0, 1, -1)
+);
+
+// inverse square falloff that smoothly reaches 0 at the light's range
+float Attenuation(Light light, float distance) {
+  float falloff = 1.0 / max(distance * distance, 0.0001);
+  if (light.range <= 0.0) {
+    return falloff;
+  }
+
+  float ratio = distance / light.range;
+  float window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+  return falloff * window * window;
+}
+
+// light that reaches the fragment before shadows, L is the direction towards the light
+vec3 IncomingLight(Light light, out vec3 L) {
+  if (light.type == 0) {
+    L = normalize(-light.direction);
+    return light.color * light.intensity;
+  }
+
+  vec3 toLight = light.position - fragPos;
+  float distance = length(toLight);
+  L = toLight / max(distance, 0.0001);
+  vec3 radiance = light.color * light.intensity * Attenuation(light, distance);
+
+  if (light.type == 2) {
+    float theta = dot(L, normalize(-light.direction));
+    float epsilon = max(light.innerCutOff - light.outerCutOff, 0.0001);
+    radiance *= clamp((theta - light.outerCutOff) / epsilon, 0.0, 1.0);
+  }
+
+  return radiance;
+}
+
+float MapShadowFactor(sampler2DArray shadowMaps, Light light, vec3 normal) {
+  vec4 lightSpacePos = light.lightSpace * vec4(fragPos + normal * light.normalBias, 1.0);
+  vec3 projected = lightSpacePos.xyz / lightSpacePos.w * 0.5 + 0.5;
+  // nothing outside of the map casts shadows
+  if (lightSpacePos.w <= 0.0 || projected.z > 1.0
+      || any(lessThan(projected.xy, vec2(0.0))) || any(greaterThan(projected.xy, vec2(1.0)))) {
+    return 0.0;
+  }
+
+  vec2 texelSize = 1.0 / vec2(textureSize(shadowMaps, 0).xy);
+  float shadowed = 0.0;
+  for (int x = -light.pcfRadius; x <= light.pcfRadius; x++) {
+    for (int y = -light.pcfRadius; y <= light.pcfRadius; y++) {
+      float depth = texture(shadowMaps, vec3(projected.xy + vec2(x, y) * texelSize, light.shadowIndex)).r;
+      shadowed += projected.z - light.shadowBias > depth ? 1.0 : 0.0;
+    }
+  }
+
+  float width = float(light.pcfRadius * 2 + 1);
+  return shadowed / (width * width);
+}
+
+float PointShadowFactor(Light light, vec3 normal) {
+  vec3 toFrag = fragPos + normal * light.normalBias - light.position;
+  float distance = length(toFrag) / pointShadowFar;
+  if (distance > 1.0) {
+    return 0.0;
+  }
+
+  if (light.pcfRadius == 0) {
+    return distance - light.shadowBias > texture(pointShadowMaps, vec4(toFrag, light.shadowIndex)).r ? 1.0 : 0.0;
+  }
+
+  // keeps the same angular size at any distance
+  float diskRadius = float(light.pcfRadius) * 0.005 * length(toFrag);
+  float shadowed = 0.0;
+  for (int i = 0; i < 20; i++) {
+    float stored = texture(pointShadowMaps, vec4(toFrag + cubeSampleOffsets[i] * diskRadius, light.shadowIndex)).r;
+    shadowed += distance - light.shadowBias > stored ? 1.0 : 0.0;
+  }
+
+  return shadowed / 20.0;
+}
+
+// fraction of the light that's blocked, 0 is fully lit
+float ShadowFactor(Light light, vec3 normal) {
+  if (light.shadowIndex < 0) {
+    return 0.0;
+  }
+
+  if (light.type == 0) {
+    return MapShadowFactor(dirShadowMaps, light, normal);
+  } else if (light.type == 2) {
+    return MapShadowFactor(spotShadowMaps, light, normal);
+  }
+
+  return PointShadowFactor(light, normal);
+}
+
+// builds a tangent frame from screen space derivatives for meshes without vertex tangents
+// source: http://www.thetenthplanet.de/archives/1180
+mat3 CotangentFrame(vec3 N, vec3 p, vec2 uv) {
+  vec3 dp1 = dFdx(p);
+  vec3 dp2 = dFdy(p);
+  vec2 duv1 = dFdx(uv);
+  vec2 duv2 = dFdy(uv);
+
+  vec3 dp2perp = cross(dp2, N);
+  vec3 dp1perp = cross(N, dp1);
+  vec3 T = dp2perp * duv1.x + dp1perp * duv2.x;
+  vec3 B = dp2perp * duv1.y + dp1perp * duv2.y;
+
+  float invmax = inversesqrt(max(dot(T, T), dot(B, B)));
+  return mat3(T * invmax, B * invmax, N);
+}
+
+vec3 GetNormal() {
+  vec3 N = normalize(fragNormals);
+  // light the back of two sided surfaces like the front
+  if (material.twoSided && !gl_FrontFacing) {
+    N = -N;
+  }
+  if (!material.hasNormalMap) {
+    return N;
+  }
+
+  vec3 mapNormal = texture(material.normalMap, texCoords).xyz * 2.0 - 1.0;
+  mapNormal.xy *= material.normalScale;
+
+  mat3 TBN;
+  if (dot(fragTangent, fragTangent) > 0.0) {
+    // re-orthogonalize since interpolation skews the frame
+    vec3 T = normalize(fragTangent - N * dot(N, fragTangent));
+    vec3 B = cross(N, T);
+    // mirrored uvs flip the bitangent
+    if (dot(B, fragBitangent) < 0.0) {
+      B = -B;
+    }
+    TBN = mat3(T, B, N);
+  } else {
+    TBN = CotangentFrame(N, fragPos, texCoords);
+  }
+
+  return normalize(TBN * mapNormal);
+}
+
+float DistributionGGX(vec3 N, vec3 H, float roughness) {
+  float a = roughness * roughness;
+  float a2 = a * a;
+  float NdotH = max(dot(N, H), 0.0);
+  float denom = NdotH * NdotH * (a2 - 1.0) + 1.0;
+
+  return a2 / (PI * denom * denom);
+}
+
+float GeometrySchlickGGX(float NdotV, float roughness) {
+  float r = roughness + 1.0;
+  float k = (r * r) / 8.0;
+
+  return NdotV / (NdotV * (1.0 - k) + k);
+}
+
+float GeometrySmith(vec3 N, vec3 V, vec3 L, float roughness) {
+  float NdotV = max(dot(N, V), 0.0);
+  float NdotL = max(dot(N, L), 0.0);
+
+  return GeometrySchlickGGX(NdotV, roughness) * GeometrySchlickGGX(NdotL, roughness);
+}
+
+vec3 FresnelSchlick(float cosTheta, vec3 F0) {
+  return F0 + (1.0 - F0) * pow(clamp(1.0 - cosTheta, 0.0, 1.0), 5.0);
+}
+
+vec3 FresnelSchlickRoughness(float cosTheta, vec3 F0, float roughness) {
+  return F0 + (max(vec3(1.0 - roughness), F0) - F0) * pow(clamp(1.0 - cosTheta, 0.0, 1.0), 5.0);
+}
+
+vec3 RotateY(vec3 v, float angle) {
+  float s = sin(angle);
+  float c = cos(angle);
+  return vec3(c * v.x + s * v.z, v.y, -s * v.x + c * v.z);
+}
+
+// diffuse and specular light reflected from the environment
+vec3 AmbientLight(vec3 N, vec3 V, vec3 albedo, float metallic, float roughness) {
+  if (!hasEnvironment) {
+    return ambientLight * albedo;
+  }
+
+  float NdotV = max(dot(N, V), 0.0);
+  vec3 F0 = mix(vec3(0.04), albedo, metallic);
+  vec3 F = FresnelSchlickRoughness(NdotV, F0, roughness);
+  vec3 kD = (vec3(1.0) - F) * (1.0 - metallic);
+
+  vec3 diffuse = texture(irradianceMap, RotateY(N, envRotation)).rgb * albedo;
+
+  vec3 R = RotateY(reflect(-V, N), envRotation);
+  vec3 prefiltered = textureLod(prefilterMap, R, roughness * prefilterMaxLod).rgb;
+  vec2 brdf = texture(brdfLut, vec2(NdotV, roughness)).rg;
+  vec3 specular = prefiltered * (F * brdf.x + brdf.y);
+
+  return (kD * diffuse + specular) * envIntensity;
+}
+
+// outgoing radiance for a single light with direction L and incoming radiance
+vec3 CookTorrance(vec3 N, vec3 V, vec3 L, vec3 radiance, vec3 albedo, float metallic, float roughness) {
+  vec3 H = normalize(V + L);
+  vec3 F0 = mix(vec3(0.04), albedo, metallic);
+
+  float NDF = DistributionGGX(N, H, roughness);
+  float G = GeometrySmith(N, V, L, roughness);
+  vec3 F = FresnelSchlick(max(dot(H, V), 0.0), F0);
+
+  vec3 specular = (NDF * G * F) / (4.0 * max(dot(N, V), 0.0) * max(dot(N, L), 0.0) + 0.0001);
+
+  vec3 kD = (vec3(1.0) - F) * (1.0 - metallic);
+  float NdotL = max(dot(N, L), 0.0);
+
+  return (kD * albedo / PI + specular) * radiance * NdotL;
+}
+
+void main()
+{
+  vec4 baseColor = material.baseColor;
+  if (material.hasBaseColorMap) {
+    baseColor *= texture(material.baseColorMap, texCoords);
+  }
+  vec3 albedo = baseColor.rgb;
+
+  float alpha = baseColor.a * material.opacity;
+  if (material.alphaMode == 0) {
+    alpha = 1.0;
+  } else if (material.alphaMode == 1) {
+    if (alpha < material.alphaCutoff) {
+      discard;
+    }
+    alpha = 1.0;
+  }
+
+  float metallic = material.metallic;
+  float roughness = material.roughness;
+  if (material.hasMetallicRoughnessMap) {
+    // gltf packs roughness in green and metalness in blue
+    vec4 mr = texture(material.metallicRoughnessMap, texCoords);
+    roughness *= mr.g;
+    metallic *= mr.b;
+  }
+  roughness = clamp(roughness, 0.04, 1.0);
+  metallic = clamp(metallic, 0.0, 1.0);
+
+  float ao = 1.0;
+  if (material.hasOcclusionMap) {
+    ao = 1.0 + material.occlusionStrength * (texture(material.occlusionMap, texCoords).r - 1.0);
+  }
+
+  vec3 emissive = material.emissive;
+  if (material.hasEmissiveMap) {
+    emissive *= texture(material.emissiveMap, texCoords).rgb;
+  }
+
+  vec3 N = GetNormal();
+  vec3 V = normalize(viewPos - fragPos);
+
+  vec3 Lo = vec3(0.0);
+  for (int i = 0; i < lightCount; i++) {
+    vec3 L;
+    vec3 radiance = IncomingLight(lights[i], L);
+    if (radiance == vec3(0.0)) {
+      continue;
+    }
+
+    Lo += CookTorrance(N, V, L, radiance, albedo, metallic, roughness) * (1.0 - ShadowFactor(lights[i], N));
+  }
+
+  vec3 color = AmbientLight(N, V, albedo, metallic, roughness) * ao + Lo + emissive;
+
+  FragColor = vec4(color, alpha);
+}
@@ -0,0 +1,12 @@
+#version 330 core
+out uvec4 FragColor;
+
+// offset by one, 0 is the background
+uniform int objectId;
+uniform int nodeId;
+uniform int meshId;
+
+void main()
+{
+  FragColor = uvec4(objectId, nodeId, meshId, 0);
+}
@@ -0,0 +1,84 @@
+#version 330 core
+in vec2 texCoords;
+
+out vec4 FragColor;
+
+uniform sampler2D hdrScene;
+// exposure in stops
+uniform float exposure;
+// 0 none, 1 reinhard, 2 aces, 3 agx
+uniform int toneMapping;
+
+vec3 Reinhard(vec3 color) {
+  return color / (1.0 + color);
+}
+
+// Stephen Hill's fit of the ACES RRT + ODT
+vec3 Aces(vec3 color) {
+  const mat3 inputMat = mat3(
+    0.59719, 0.07600, 0.02840,
+    0.35458, 0.90834, 0.13383,
+    0.04823, 0.01566, 0.83777);
+  const mat3 outputMat = mat3(
+    1.60475, -0.10208, -0.00327,
+    -0.53108, 1.10813, -0.07276,
+    -0.07367, -0.00605, 1.07602);
+
+  color = inputMat * color;
+  vec3 a = color * (color + 0.0245786) - 0.000090537;
+  vec3 b = color * (0.983729 * color + 0.4329510) + 0.238081;
+  color = outputMat * (a / b);
+
+  return clamp(color, 0.0, 1.0);
+}
+
+// polynomial fit of the AgX base contrast curve
+vec3 AgxContrast(vec3 x) {
+  vec3 x2 = x * x;
+  vec3 x4 = x2 * x2;
+  return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+vec3 Agx(vec3 color) {
+  const mat3 inset = mat3(
+    0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+    0.0784335999999992, 0.878468636469772, 0.0784336,
+    0.0792237451477643, 0.0791661274605434, 0.879142973793104);
+  const mat3 outset = mat3(
+    1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+    -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+    -0.0990297440797205, -0.0989611768448433, 1.15107367264116);
+  const float minEv = -12.47393;
+  const float maxEv = 4.026069;
+
+  color = inset * color;
+  color = clamp(log2(max(color, 1e-10)), minEv, maxEv);
+  color = (color - minEv) / (maxEv - minEv);
+  color = AgxContrast(color);
+  color = outset * color;
+
+  // the curve outputs gamma 2.2 encoded values, go back to linear so they're encoded like the others
+  return pow(max(color, 0.0), vec3(2.2));
+}
+
+vec3 LinearToSrgb(vec3 color) {
+  color = clamp(color, 0.0, 1.0);
+  vec3 low = color * 12.92;
+  vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+  return mix(high, low, vec3(lessThanEqual(color, vec3(0.0031308))));
+}
+
+void main() {
+  vec4 hdr = texture(hdrScene, texCoords);
+  vec3 color = hdr.rgb * exp2(exposure);
+
+  if (toneMapping == 1) {
+    color = Reinhard(color);
+  } else if (toneMapping == 2) {
+    color = Aces(color);
+  } else if (toneMapping == 3) {
+    color = Agx(color);
+  }
+
+  FragColor = vec4(LinearToSrgb(color), 1.0);
+}
@@ -0,0 +1,9 @@
+#version 330 core
+out vec2 texCoords;
+
+// a single triangle that covers the whole screen, no vertex buffer needed
+void main() {
+    vec2 p = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    texCoords = p;
+    gl_Position = vec4(p * 2.0 - 1.0, 0.0, 1.0);
+}
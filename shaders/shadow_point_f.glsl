@@ -0,0 +1,10 @@
+#version 330 core
+in vec3 fragPos;
+
+uniform vec3 lightPos;
+uniform float farPlane;
+
+// store the distance to the light so the lighting shaders can compare it without the face's projection
+void main() {
+  gl_FragDepth = length(fragPos - lightPos) / farPlane;
+}
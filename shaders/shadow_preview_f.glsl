@@ -0,0 +1,40 @@
+#version 430 core
+in vec2 texCoords;
+
+out vec4 FragColor;
+
+uniform sampler2DArray depthMaps;
+uniform samplerCubeArray cubeMaps;
+uniform int layer;
+// 0 ortho depth, 1 perspective depth, 2 distance cube map
+uniform int mode;
+uniform float near;
+uniform float far;
+
+// direction of a point on a face of the cube map, faces are laid out as +x -x +y on top and -y +z -z below
+vec3 CubeDirection(vec2 uv) {
+  int face = int(floor(uv.x * 3.0)) + 3 * int(floor((1.0 - uv.y) * 2.0));
+  vec2 st = fract(vec2(uv.x * 3.0, uv.y * 2.0)) * 2.0 - 1.0;
+
+  if (face == 0) return vec3(1.0, -st.y, -st.x);
+  if (face == 1) return vec3(-1.0, -st.y, st.x);
+  if (face == 2) return vec3(st.x, 1.0, st.y);
+  if (face == 3) return vec3(st.x, -1.0, -st.y);
+  if (face == 4) return vec3(st.x, -st.y, 1.0);
+  return vec3(-st.x, -st.y, -1.0);
+}
+
+void main() {
+  float value;
+  if (mode == 0) {
+    value = texture(depthMaps, vec3(texCoords, layer)).r;
+  } else if (mode == 1) {
+    // perspective depth is almost all white, show the linear distance instead
+    float z = texture(depthMaps, vec3(texCoords, layer)).r * 2.0 - 1.0;
+    value = (2.0 * near * far / (far + near - z * (far - near))) / far;
+  } else {
+    value = texture(cubeMaps, vec4(CubeDirection(texCoords), layer)).r;
+  }
+
+  FragColor = vec4(vec3(value), 1.0);
+}
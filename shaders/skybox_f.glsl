@@ -0,0 +1,28 @@
+#version 330 core
+in vec3 localPos;
+
+out vec4 FragColor;
+
+uniform samplerCube environmentMap;
+uniform samplerCube prefilterMap;
+// around the y axis in radians
+uniform float rotation;
+uniform float intensity;
+// mip of the prefiltered map, 0 is sharp
+uniform float lod;
+
+vec3 RotateY(vec3 v, float angle) {
+    float s = sin(angle);
+    float c = cos(angle);
+    return vec3(c * v.x + s * v.z, v.y, -s * v.x + c * v.z);
+}
+
+void main() {
+    vec3 dir = RotateY(normalize(localPos), rotation);
+    vec3 sharp = texture(environmentMap, dir).rgb;
+    vec3 blurred = textureLod(prefilterMap, dir, lod).rgb;
+    // the prefiltered map's first mip is lower resolution, so fade into it over the first mip
+    vec3 color = mix(sharp, blurred, clamp(lod, 0.0, 1.0));
+
+    FragColor = vec4(color * intensity, 1.0);
+}
@@ -0,0 +1,15 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+
+out vec3 localPos;
+
+uniform mat4 view;
+uniform mat4 projection;
+
+void main() {
+    localPos = aPos;
+    // without the translation the sky stays around the camera
+    vec4 pos = projection * mat4(mat3(view)) * vec4(aPos, 1.0);
+    // on the far plane, so it only shows where nothing else was drawn
+    gl_Position = pos.xyww;
+}
@@ -2,19 +2,66 @@
 layout (location = 0) in vec3 aPos;
 layout (location = 1) in vec3 aNormal;
 layout (location = 2) in vec2 aTexCoords;
+layout (location = 3) in ivec4 aBoneIds;
+layout (location = 4) in vec4 aBoneWeights;
+layout (location = 5) in vec3 aTangent;
+layout (location = 6) in vec3 aBitangent;
 out vec3 fragNormals;
+out vec3 fragTangent;
+out vec3 fragBitangent;
 out vec3 fragPos;
 out vec2 texCoords;
 
+// have to match MAX_BONES and MAX_MORPH_TARGETS in mesh.rs
+#define MAX_BONES 128
+#define MAX_MORPH_TARGETS 64
+
 uniform mat4 model;
 uniform mat4 view;
 uniform mat4 projection;
 
+uniform bool skinned;
+uniform mat4 bones[MAX_BONES];
+
+// position and normal delta of every vertex for every target, target after target
+uniform samplerBuffer morphTargets;
+uniform int morphTargetCount;
+uniform int morphVertexCount;
+uniform float morphWeights[MAX_MORPH_TARGETS];
+
 void main()
 {
-  gl_Position = projection * view * model * vec4(aPos, 1.0f);
-  fragNormals = mat3(transpose(inverse(model))) * aNormal;
-  fragPos = vec3(model * vec4(aPos, 1.0));
+  vec3 position = aPos;
+  vec3 normal = aNormal;
+  for (int i = 0; i < morphTargetCount; i++) {
+    if (morphWeights[i] == 0.0) {
+      continue;
+    }
+
+    int texel = (i * morphVertexCount + gl_VertexID) * 2;
+    position += morphWeights[i] * texelFetch(morphTargets, texel).xyz;
+    normal += morphWeights[i] * texelFetch(morphTargets, texel + 1).xyz;
+  }
+
+  mat4 skin = mat4(1.0);
+  if (skinned) {
+    float totalWeight = aBoneWeights.x + aBoneWeights.y + aBoneWeights.z + aBoneWeights.w;
+    // vertices that aren't weighted to any bone stay where they are
+    if (totalWeight > 0.0) {
+      skin = bones[aBoneIds.x] * aBoneWeights.x
+        + bones[aBoneIds.y] * aBoneWeights.y
+        + bones[aBoneIds.z] * aBoneWeights.z
+        + bones[aBoneIds.w] * aBoneWeights.w;
+    }
+  }
+
+  mat4 skinnedModel = model * skin;
+
+  gl_Position = projection * view * skinnedModel * vec4(position, 1.0f);
+  fragNormals = mat3(transpose(inverse(skinnedModel))) * normal;
+  // tangents lie on the surface so they're transformed like positions
+  fragTangent = mat3(skinnedModel) * aTangent;
+  fragBitangent = mat3(skinnedModel) * aBitangent;
+  fragPos = vec3(skinnedModel * vec4(position, 1.0));
   texCoords = aTexCoords;
 }
-
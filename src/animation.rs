@@ -0,0 +1,166 @@
+use crate::transform::{Quat, Transform};
+
+/// assimp doesn't export how keys are interpolated so it's picked per model in the ui
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    Step,
+    /// lerp for translation and scale, slerp for rotation
+    Linear,
+}
+
+impl Interpolation {
+    pub const LABELS: [&'static str; 2] = ["Step", "Linear"];
+
+    pub fn index(&self) -> usize {
+        match self {
+            Interpolation::Step => 0,
+            Interpolation::Linear => 1,
+        }
+    }
+
+    pub fn from_index(idx: usize) -> Self {
+        match idx {
+            0 => Interpolation::Step,
+            _ => Interpolation::Linear,
+        }
+    }
+}
+
+impl Default for Interpolation {
+    fn default() -> Self {
+        Interpolation::Linear
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Keyframe<T> {
+    /// in seconds
+    pub time: f32,
+    pub value: T,
+}
+
+/// Keys of a single node, missing keys leave that part of the node's transform as it is
+#[derive(Debug)]
+pub struct Channel {
+    pub node: usize,
+    pub translations: Vec<Keyframe<glm::Vec3>>,
+    pub rotations: Vec<Keyframe<Quat>>,
+    pub scales: Vec<Keyframe<glm::Vec3>>,
+}
+
+impl Channel {
+    /// `rest` is the node's transform around its origin rather than its pivot, the parts without keys are taken from it
+    pub fn sample(&self, time: f32, interpolation: Interpolation, rest: &Transform) -> Transform {
+        let lerp = |a: &glm::Vec3, b: &glm::Vec3, t: f32| *a + (*b - *a) * t;
+
+        Transform {
+            translation: sample(&self.translations, time, interpolation, lerp).unwrap_or(rest.translation),
+            rotation: sample(&self.rotations, time, interpolation, |a, b, t| a.slerp(b, t)).unwrap_or(rest.rotation),
+            scale: sample(&self.scales, time, interpolation, lerp).unwrap_or(rest.scale),
+            // animated transforms can't be sheared
+            shear: None,
+        }
+    }
+}
+
+/// Morph target weights of one or more meshes
+#[derive(Debug)]
+pub struct MorphChannel {
+    pub meshes: Vec<usize>,
+    /// one weight per morph target
+    pub weights: Vec<Keyframe<Vec<f32>>>,
+}
+
+impl MorphChannel {
+    pub fn sample(&self, time: f32, interpolation: Interpolation) -> Option<Vec<f32>> {
+        sample(&self.weights, time, interpolation, |a, b, t| {
+            a.iter().zip(b.iter()).map(|(a, b)| a + (b - a) * t).collect()
+        })
+    }
+}
+
+/// Keys are assumed to be sorted by time, times outside of the keys clamp to the first/last key
+fn sample<T: Clone>(keys: &[Keyframe<T>], time: f32, interpolation: Interpolation, mix: impl Fn(&T, &T, f32) -> T) -> Option<T> {
+    let first = keys.first()?;
+    let last = keys.last()?;
+
+    if time <= first.time {
+        return Some(first.value.clone());
+    }
+    if time >= last.time {
+        return Some(last.value.clone());
+    }
+
+    // first key after `time`, can't be 0 or past the end because of the checks above
+    let next = keys.partition_point(|key| key.time <= time);
+    let (a, b) = (&keys[next - 1], &keys[next]);
+
+    match interpolation {
+        Interpolation::Step => Some(a.value.clone()),
+        Interpolation::Linear => {
+            let t = (time - a.time) / (b.time - a.time);
+            Some(mix(&a.value, &b.value, t))
+        },
+    }
+}
+
+#[derive(Debug)]
+pub struct Clip {
+    pub name: String,
+    /// in seconds
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+    pub morph_channels: Vec<MorphChannel>,
+}
+
+/// Playback state of a model's clips
+#[derive(Debug)]
+pub struct Animator {
+    /// `None` shows the bind pose
+    pub clip: Option<usize>,
+    /// in seconds
+    pub time: f32,
+    pub playing: bool,
+    pub looping: bool,
+    pub speed: f32,
+    pub interpolation: Interpolation,
+}
+
+impl Default for Animator {
+    fn default() -> Self {
+        Animator {
+            clip: None,
+            time: 0.0,
+            playing: false,
+            looping: true,
+            speed: 1.0,
+            interpolation: Interpolation::default(),
+        }
+    }
+}
+
+impl Animator {
+    pub fn set_clip(&mut self, clip: Option<usize>) {
+        self.clip = clip;
+        self.time = 0.0;
+        self.playing = clip.is_some();
+    }
+
+    /// Moves the time forward by `delta_time` scaled by the speed, wrapping or stopping at the ends
+    pub fn advance(&mut self, delta_time: f32, duration: f32) {
+        if !self.playing {
+            return;
+        }
+
+        self.time += delta_time * self.speed;
+
+        if duration <= 0.0 {
+            self.time = 0.0;
+        } else if self.looping {
+            self.time = self.time.rem_euclid(duration);
+        } else if self.time > duration || self.time < 0.0 {
+            self.time = self.time.clamp(0.0, duration);
+            self.playing = false;
+        }
+    }
+}
@@ -1,5 +1,10 @@
 use glfw::{Action, Key};
 use glm;
+
+/// Clip planes of the projection, the grid shader fades out with the same values
+pub const NEAR: f32 = 0.01;
+pub const FAR: f32 = 200.0;
+
 pub struct Camera {
     pub position: glm::Vec3,
     pub front: glm::Vec3,
@@ -42,6 +47,17 @@ impl Camera {
             self.pitch = -89.0;
         }
 
+        self.update_front();
+    }
+
+    /// Points the camera along `pitch` and `yaw` in degrees
+    pub fn set_orientation(&mut self, pitch: f32, yaw: f32) {
+        self.pitch = pitch;
+        self.yaw = yaw;
+        self.update_front();
+    }
+
+    fn update_front(&mut self) {
         let front = glm::vec3(
             self.pitch.to_radians().cos() * self.yaw.to_radians().cos(),
             self.pitch.to_radians().sin(),
@@ -78,6 +94,14 @@ impl Camera {
         }
     }
 
+    pub fn view_matrix(&self) -> glm::Mat4 {
+        glm::ext::look_at(self.position, self.position + self.front, self.up)
+    }
+
+    pub fn projection_matrix(&self, aspect: f32) -> glm::Mat4 {
+        glm::ext::perspective(glm::radians(self.fov), aspect, NEAR, FAR)
+    }
+
     pub fn update_speed(&mut self, delta_time: f32) {
         self._speed = self.speed * delta_time;
     }
@@ -0,0 +1,321 @@
+use std::{path::PathBuf, sync::mpsc};
+
+use glad_gl::gl;
+use serde::{Deserialize, Serialize};
+
+use crate::{gl_object::{Buffer, Framebuffer, GlTexture, VertexArray}, shader::Shader, shadow::CUBE_FACES};
+
+const CUBEMAP_SIZE: i32 = 512;
+const IRRADIANCE_SIZE: i32 = 32;
+const PREFILTER_SIZE: i32 = 128;
+/// Mip 0 is a mirror, the last one is fully rough
+const PREFILTER_MIPS: i32 = 5;
+const BRDF_LUT_SIZE: i32 = 512;
+
+/// Units after the material maps and before the shadow maps.
+/// The irradiance map, the prefiltered map, then the brdf lut
+const ENVIRONMENT_TEXTURE_UNIT: u32 = 20;
+
+/// How the environment is shown and how much it lights the scene
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvironmentSettings {
+    /// around the y axis in degrees
+    pub rotation: f32,
+    pub intensity: f32,
+    /// blurs the skybox like the reflections on a surface this rough
+    pub blur: f32,
+    pub show_skybox: bool,
+}
+
+impl Default for EnvironmentSettings {
+    fn default() -> Self {
+        Self {
+            rotation: 0.0,
+            intensity: 1.0,
+            blur: 0.0,
+            show_skybox: true,
+        }
+    }
+}
+
+/// An hdr environment and the maps precomputed from it for image based lighting
+pub struct Environment {
+    pub path: PathBuf,
+    cubemap: GlTexture,
+    irradiance: GlTexture,
+    prefiltered: GlTexture,
+}
+
+impl Environment {
+    pub fn name(&self) -> String {
+        self.path.file_name().map_or(String::new(), |name| name.to_string_lossy().to_string())
+    }
+}
+
+/// An equirectangular .hdr or .exr image that's decoded on a worker thread
+pub struct EnvironmentJob {
+    pub path: PathBuf,
+    receiver: mpsc::Receiver<Result<image::Rgb32FImage, String>>,
+}
+
+impl EnvironmentJob {
+    pub fn spawn(path: PathBuf) -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        let thread_path = path.clone();
+        std::thread::spawn(move || {
+            let result = image::open(&thread_path).map(|image| image.into_rgb32f()).map_err(|e| e.to_string());
+            // the job was dropped if nobody is listening anymore
+            let _ = sender.send(result);
+        });
+
+        Self {
+            path,
+            receiver,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.path.file_name().map_or(String::new(), |name| name.to_string_lossy().to_string())
+    }
+
+    /// Returns the decoded image once the worker is done
+    pub fn poll(&self) -> Option<Result<image::Rgb32FImage, String>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => Some(Err(String::from("Environment thread crashed"))),
+        }
+    }
+}
+
+/// Bakes environments into cube maps and draws them as a skybox
+pub struct EnvironmentRenderer {
+    equirect_shader: Shader,
+    irradiance_shader: Shader,
+    prefilter_shader: Shader,
+    skybox_shader: Shader,
+    cube_vao: VertexArray,
+    cube_vbo: Buffer,
+    fb: Framebuffer,
+    /// doesn't depend on the environment so it's only computed once
+    brdf_lut: GlTexture,
+}
+
+impl EnvironmentRenderer {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        let brdf_shader = Shader::new("shaders/post_v.glsl", "shaders/brdf_f.glsl")?;
+
+        let renderer = Self {
+            equirect_shader: Shader::new("shaders/cubemap_v.glsl", "shaders/equirect_f.glsl")?,
+            irradiance_shader: Shader::new("shaders/cubemap_v.glsl", "shaders/irradiance_f.glsl")?,
+            prefilter_shader: Shader::new("shaders/cubemap_v.glsl", "shaders/prefilter_f.glsl")?,
+            skybox_shader: Shader::new("shaders/skybox_v.glsl", "shaders/skybox_f.glsl")?,
+            cube_vao: VertexArray::new(),
+            cube_vbo: Buffer::new(),
+            fb: Framebuffer::new(),
+            brdf_lut: GlTexture::new(),
+        };
+
+        unsafe {
+            gl::BindVertexArray(renderer.cube_vao.id());
+            gl::BindBuffer(gl::ARRAY_BUFFER, renderer.cube_vbo.id());
+            gl::BufferData(gl::ARRAY_BUFFER, std::mem::size_of_val(&CUBE_VERTICES) as isize, CUBE_VERTICES.as_ptr() as *const std::ffi::c_void, gl::STATIC_DRAW);
+            gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, 3 * std::mem::size_of::<f32>() as i32, std::ptr::null());
+            gl::EnableVertexAttribArray(0);
+            gl::BindVertexArray(0);
+
+            // sampling across cube faces would show the seams on blurry mips otherwise
+            gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
+
+            gl::BindTexture(gl::TEXTURE_2D, renderer.brdf_lut.id());
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RG16F as i32, BRDF_LUT_SIZE, BRDF_LUT_SIZE, 0, gl::RG, gl::FLOAT, std::ptr::null());
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, renderer.fb.id());
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, renderer.brdf_lut.id(), 0);
+            gl::Viewport(0, 0, BRDF_LUT_SIZE, BRDF_LUT_SIZE);
+            gl::Disable(gl::DEPTH_TEST);
+            gl::Disable(gl::BLEND);
+
+            // the fullscreen triangle has no attributes, any vao will do
+            brdf_shader.use_shader();
+            gl::BindVertexArray(renderer.cube_vao.id());
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            gl::BindVertexArray(0);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+
+        Ok(renderer)
+    }
+
+    /// Converts an equirectangular image into a cube map and precomputes the lighting maps from it
+    pub fn bake(&self, path: PathBuf, image: &image::Rgb32FImage) -> Environment {
+        unsafe {
+            gl::Disable(gl::DEPTH_TEST);
+            gl::Disable(gl::BLEND);
+
+            let equirect = GlTexture::new();
+            gl::BindTexture(gl::TEXTURE_2D, equirect.id());
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGB32F as i32, image.width() as i32, image.height() as i32, 0, gl::RGB, gl::FLOAT, image.as_ptr() as *const std::ffi::c_void);
+
+            let cubemap = allocate_cubemap(CUBEMAP_SIZE, mip_count(CUBEMAP_SIZE));
+            self.equirect_shader.use_shader();
+            self.equirect_shader.set_int("equirectMap", 0);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, equirect.id());
+            self.draw_faces(&self.equirect_shader, &cubemap, CUBEMAP_SIZE, 0);
+            // the prefilter pass samples the lower mips so bright spots don't turn into speckles
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, cubemap.id());
+            gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+
+            let irradiance = allocate_cubemap(IRRADIANCE_SIZE, 1);
+            self.irradiance_shader.use_shader();
+            self.irradiance_shader.set_int("environmentMap", 0);
+            self.draw_faces(&self.irradiance_shader, &irradiance, IRRADIANCE_SIZE, 0);
+
+            let prefiltered = allocate_cubemap(PREFILTER_SIZE, PREFILTER_MIPS);
+            self.prefilter_shader.use_shader();
+            self.prefilter_shader.set_int("environmentMap", 0);
+            self.prefilter_shader.set_float("resolution", CUBEMAP_SIZE as f32);
+            for mip in 0..PREFILTER_MIPS {
+                self.prefilter_shader.set_float("roughness", mip as f32 / (PREFILTER_MIPS - 1) as f32);
+                self.draw_faces(&self.prefilter_shader, &prefiltered, PREFILTER_SIZE, mip);
+            }
+
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+
+            Environment {
+                path,
+                cubemap,
+                irradiance,
+                prefiltered,
+            }
+        }
+    }
+
+    /// Renders every face of a cube map mip with `shader` looking out from the center
+    unsafe fn draw_faces(&self, shader: &Shader, target: &GlTexture, size: i32, mip: i32) {
+        let projection = glm::ext::perspective(glm::radians(90.0), 1.0, 0.1, 10.0);
+        let mip_size = (size >> mip).max(1);
+
+        shader.set_mat4fv("projection", &projection);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.fb.id());
+        gl::Viewport(0, 0, mip_size, mip_size);
+        gl::BindVertexArray(self.cube_vao.id());
+
+        for (face, (dir, up)) in CUBE_FACES.iter().enumerate() {
+            let view = glm::ext::look_at(glm::vec3(0.0, 0.0, 0.0), glm::vec3(dir[0], dir[1], dir[2]), glm::vec3(up[0], up[1], up[2]));
+            shader.set_mat4fv("view", &view);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32, target.id(), mip);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            gl::DrawArrays(gl::TRIANGLES, 0, 36);
+        }
+
+        gl::BindVertexArray(0);
+    }
+
+    /// Sets up the image based lighting of a pbr shader, which falls back to its ambient light without an environment
+    pub fn bind(&self, shader: &Shader, environment: Option<&Environment>, settings: &EnvironmentSettings) {
+        shader.use_shader();
+        // set even without an environment so the samplers never share a unit with a different texture type
+        shader.set_int("irradianceMap", ENVIRONMENT_TEXTURE_UNIT as i32);
+        shader.set_int("prefilterMap", ENVIRONMENT_TEXTURE_UNIT as i32 + 1);
+        shader.set_int("brdfLut", ENVIRONMENT_TEXTURE_UNIT as i32 + 2);
+        shader.set_bool("hasEnvironment", environment.is_some());
+        shader.set_float("envRotation", glm::radians(settings.rotation));
+        shader.set_float("envIntensity", settings.intensity);
+        shader.set_float("prefilterMaxLod", (PREFILTER_MIPS - 1) as f32);
+
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + ENVIRONMENT_TEXTURE_UNIT);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, environment.map_or(0, |env| env.irradiance.id()));
+            gl::ActiveTexture(gl::TEXTURE0 + ENVIRONMENT_TEXTURE_UNIT + 1);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, environment.map_or(0, |env| env.prefiltered.id()));
+            gl::ActiveTexture(gl::TEXTURE0 + ENVIRONMENT_TEXTURE_UNIT + 2);
+            gl::BindTexture(gl::TEXTURE_2D, self.brdf_lut.id());
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+    }
+
+    /// Draws the environment behind everything that's already in the bound framebuffer's depth buffer
+    pub fn draw_skybox(&self, environment: &Environment, settings: &EnvironmentSettings, view: &glm::Mat4, projection: &glm::Mat4) {
+        self.skybox_shader.use_shader();
+        self.skybox_shader.set_mat4fv("view", view);
+        self.skybox_shader.set_mat4fv("projection", projection);
+        self.skybox_shader.set_int("environmentMap", 0);
+        self.skybox_shader.set_int("prefilterMap", 1);
+        self.skybox_shader.set_float("rotation", glm::radians(settings.rotation));
+        self.skybox_shader.set_float("intensity", settings.intensity);
+        self.skybox_shader.set_float("lod", settings.blur * (PREFILTER_MIPS - 1) as f32);
+
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, environment.cubemap.id());
+            gl::ActiveTexture(gl::TEXTURE1);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, environment.prefiltered.id());
+
+            // the sky sits on the far plane, where the cleared depth is
+            gl::DepthFunc(gl::LEQUAL);
+            gl::BindVertexArray(self.cube_vao.id());
+            gl::DrawArrays(gl::TRIANGLES, 0, 36);
+            gl::BindVertexArray(0);
+            gl::DepthFunc(gl::LESS);
+
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+        }
+    }
+}
+
+fn mip_count(size: i32) -> i32 {
+    32 - (size.max(1) as u32).leading_zeros() as i32
+}
+
+unsafe fn allocate_cubemap(size: i32, mips: i32) -> GlTexture {
+    let texture = GlTexture::new();
+
+    gl::BindTexture(gl::TEXTURE_CUBE_MAP, texture.id());
+    // rendered into, rgb16f isn't a required color renderable format
+    gl::TexStorage2D(gl::TEXTURE_CUBE_MAP, mips, gl::RGBA16F, size, size);
+    let min_filter = if mips > 1 { gl::LINEAR_MIPMAP_LINEAR } else { gl::LINEAR };
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, min_filter as i32);
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+    gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+
+    texture
+}
+
+/// A unit cube seen from the inside
+const CUBE_VERTICES: [f32; 108] = [
+    -1.0,  1.0, -1.0,  -1.0, -1.0, -1.0,   1.0, -1.0, -1.0,
+     1.0, -1.0, -1.0,   1.0,  1.0, -1.0,  -1.0,  1.0, -1.0,
+
+    -1.0, -1.0,  1.0,  -1.0, -1.0, -1.0,  -1.0,  1.0, -1.0,
+    -1.0,  1.0, -1.0,  -1.0,  1.0,  1.0,  -1.0, -1.0,  1.0,
+
+     1.0, -1.0, -1.0,   1.0, -1.0,  1.0,   1.0,  1.0,  1.0,
+     1.0,  1.0,  1.0,   1.0,  1.0, -1.0,   1.0, -1.0, -1.0,
+
+    -1.0, -1.0,  1.0,  -1.0,  1.0,  1.0,   1.0,  1.0,  1.0,
+     1.0,  1.0,  1.0,   1.0, -1.0,  1.0,  -1.0, -1.0,  1.0,
+
+    -1.0,  1.0, -1.0,   1.0,  1.0, -1.0,   1.0,  1.0,  1.0,
+     1.0,  1.0,  1.0,  -1.0,  1.0,  1.0,  -1.0,  1.0, -1.0,
+
+    -1.0, -1.0, -1.0,  -1.0, -1.0,  1.0,   1.0, -1.0, -1.0,
+     1.0, -1.0, -1.0,  -1.0, -1.0,  1.0,   1.0, -1.0,  1.0,
+];
@@ -0,0 +1,450 @@
+use std::{collections::HashSet, path::{Path, PathBuf}, rc::Rc};
+
+use glad_gl::gl;
+use serde_json::{json, Value};
+
+use crate::{gl_object::GlTexture, material::{AlphaMode, Material}, mesh::{self, Mesh, Texture}, model::Model, utils};
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+
+const GLB_MAGIC: u32 = 0x46546C67;
+const GLB_JSON_CHUNK: u32 = 0x4E4F534A;
+const GLB_BIN_CHUNK: u32 = 0x004E4942;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ExportSettings {
+    /// images go into the .gltf as data uris or into the .glb's buffer, otherwise they're written next to it
+    pub embed_images: bool,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            embed_images: true,
+        }
+    }
+}
+
+/// Writes the scene graph, meshes, materials and textures of `objects` as gltf 2.0.
+/// A .glb path writes a single binary file, anything else a .gltf with a .bin next to it.
+/// Skins, morph targets and animations aren't exported, the meshes are written in their bind pose
+pub fn export(objects: &[Model], path: &Path, settings: &ExportSettings) -> Result<(), Box<dyn std::error::Error>> {
+    if objects.is_empty() {
+        return Err("There's nothing to export".into());
+    }
+
+    let binary = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("glb"));
+    let mut builder = Builder::new(path, binary, *settings);
+
+    let mut roots = vec![];
+    for object in objects {
+        roots.push(builder.add_object(object)?);
+    }
+
+    let bin_name = format!("{}.bin", builder.stem);
+    let buffer = match binary {
+        true => json!({ "byteLength": builder.bin.len() }),
+        false => json!({ "byteLength": builder.bin.len(), "uri": bin_name }),
+    };
+
+    let mut document = json!({
+        "asset": { "version": "2.0", "generator": "rust-gl" },
+        "scene": 0,
+        "scenes": [{ "nodes": roots }],
+        "nodes": builder.nodes,
+        "meshes": builder.meshes,
+        "materials": builder.materials,
+        "accessors": builder.accessors,
+        "bufferViews": builder.buffer_views,
+        "buffers": [buffer],
+    });
+    if !builder.images.is_empty() {
+        document["images"] = Value::from(builder.images);
+        document["textures"] = Value::from(builder.textures);
+        // the same filtering and wrapping every texture is uploaded with
+        document["samplers"] = json!([{ "magFilter": gl::LINEAR, "minFilter": gl::LINEAR_MIPMAP_LINEAR, "wrapS": gl::REPEAT, "wrapT": gl::REPEAT }]);
+    }
+    if builder.uses_emissive_strength {
+        document["extensionsUsed"] = json!(["KHR_materials_emissive_strength"]);
+    }
+
+    let json = serde_json::to_vec(&document)?;
+    if binary {
+        std::fs::write(path, glb(json, builder.bin))?;
+    } else {
+        std::fs::write(path, json)?;
+        std::fs::write(builder.directory.join(bin_name), builder.bin)?;
+    }
+
+    Ok(())
+}
+
+/// Header, json chunk and binary chunk, both chunks are padded to 4 bytes
+fn glb(mut json: Vec<u8>, mut bin: Vec<u8>) -> Vec<u8> {
+    pad(&mut json, b' ');
+    pad(&mut bin, 0);
+
+    let length = 12 + 8 + json.len() + 8 + bin.len();
+    let mut glb = Vec::with_capacity(length);
+    for word in [GLB_MAGIC, 2, length as u32, json.len() as u32, GLB_JSON_CHUNK] {
+        glb.extend_from_slice(&word.to_le_bytes());
+    }
+    glb.append(&mut json);
+    for word in [bin.len() as u32, GLB_BIN_CHUNK] {
+        glb.extend_from_slice(&word.to_le_bytes());
+    }
+    glb.append(&mut bin);
+
+    glb
+}
+
+fn pad(bytes: &mut Vec<u8>, byte: u8) {
+    bytes.resize(bytes.len() + (4 - bytes.len() % 4) % 4, byte);
+}
+
+/// The arrays of the gltf document and the binary buffer they point into
+struct Builder {
+    directory: PathBuf,
+    /// file name of the export without its extension, images and the .bin are named after it
+    stem: String,
+    binary: bool,
+    settings: ExportSettings,
+    bin: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+    nodes: Vec<Value>,
+    meshes: Vec<Value>,
+    materials: Vec<Value>,
+    /// to share materials between meshes that have the same one
+    material_keys: Vec<(Material, [Option<usize>; 5])>,
+    images: Vec<Value>,
+    textures: Vec<Value>,
+    /// gpu texture of every image, in the same order
+    image_sources: Vec<*const GlTexture>,
+    /// files written next to the export, so two images don't end up with the same name
+    image_names: HashSet<String>,
+    uses_emissive_strength: bool,
+}
+
+impl Builder {
+    fn new(path: &Path, binary: bool, settings: ExportSettings) -> Self {
+        Self {
+            directory: path.parent().map_or(PathBuf::from("."), Path::to_path_buf),
+            stem: path.file_stem().map_or(String::from("scene"), |stem| stem.to_string_lossy().to_string()),
+            binary,
+            settings,
+            bin: vec![],
+            buffer_views: vec![],
+            accessors: vec![],
+            nodes: vec![],
+            meshes: vec![],
+            materials: vec![],
+            material_keys: vec![],
+            images: vec![],
+            textures: vec![],
+            image_sources: vec![],
+            image_names: HashSet::new(),
+            uses_emissive_strength: false,
+        }
+    }
+
+    /// Returns the index of the buffer view, views start 4 byte aligned since every accessor is made of floats or u32s
+    fn push_view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        pad(&mut self.bin, 0);
+
+        let mut view = json!({ "buffer": 0, "byteOffset": self.bin.len(), "byteLength": bytes.len() });
+        if let Some(target) = target {
+            view["target"] = Value::from(target);
+        }
+        self.bin.extend_from_slice(bytes);
+        self.buffer_views.push(view);
+
+        self.buffer_views.len() - 1
+    }
+
+    fn push_floats<const N: usize>(&mut self, values: &[[f32; N]], typ: &str, bounds: bool) -> usize {
+        let bytes: Vec<u8> = values.iter().flatten().flat_map(|value| value.to_le_bytes()).collect();
+        let view = self.push_view(&bytes, Some(ARRAY_BUFFER));
+
+        let mut accessor = json!({ "bufferView": view, "componentType": FLOAT, "count": values.len(), "type": typ });
+        // required for positions
+        if bounds {
+            let mut min = [f32::INFINITY; N];
+            let mut max = [f32::NEG_INFINITY; N];
+            for value in values {
+                for i in 0..N {
+                    min[i] = min[i].min(value[i]);
+                    max[i] = max[i].max(value[i]);
+                }
+            }
+            accessor["min"] = json!(min.to_vec());
+            accessor["max"] = json!(max.to_vec());
+        }
+        self.accessors.push(accessor);
+
+        self.accessors.len() - 1
+    }
+
+    fn push_indices(&mut self, indices: &[u32]) -> usize {
+        let bytes: Vec<u8> = indices.iter().flat_map(|index| index.to_le_bytes()).collect();
+        let view = self.push_view(&bytes, Some(ELEMENT_ARRAY_BUFFER));
+        self.accessors.push(json!({ "bufferView": view, "componentType": UNSIGNED_INT, "count": indices.len(), "type": "SCALAR" }));
+
+        self.accessors.len() - 1
+    }
+
+    /// Adds every node of the object and returns the index of its root, the axis conversion is baked into the root
+    fn add_object(&mut self, object: &Model) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut meshes = vec![];
+        for mesh in &object.meshes {
+            meshes.push(self.add_mesh(mesh, object.import_settings.flip_uvs)?);
+        }
+
+        let base = self.nodes.len();
+        for (idx, node) in object.nodes.iter().enumerate() {
+            let pivot = object.pivot_point(idx);
+            let transform = &node.local_transform;
+            let mut json = json!({ "name": node.name });
+
+            if idx == object.root() {
+                let matrix = object.axis_conversion.matrix() * transform.to_mat4_with_pivot(pivot);
+                json["matrix"] = json!(utils::mat4_to_array(&matrix));
+            } else if transform.shear.is_some() || glm::length(pivot) > 0.0 {
+                json["matrix"] = json!(utils::mat4_to_array(&transform.to_mat4_with_pivot(pivot)));
+            } else {
+                let rotation = transform.rotation.normalize();
+                json["translation"] = json!(transform.translation.as_array());
+                json["rotation"] = json!([rotation.x, rotation.y, rotation.z, rotation.w]);
+                json["scale"] = json!(transform.scale.as_array());
+            }
+
+            self.nodes.push(json);
+        }
+
+        for (idx, node) in object.nodes.iter().enumerate() {
+            let mut children: Vec<usize> = node.children.iter().map(|child| base + child).collect();
+
+            // gltf nodes have a single mesh, the others go into child nodes
+            let node_meshes: Vec<usize> = node.mesh_indices.iter().filter_map(|&mesh| meshes.get(mesh).copied().flatten()).collect();
+            match node_meshes.as_slice() {
+                [] => {},
+                [mesh] => self.nodes[base + idx]["mesh"] = Value::from(*mesh),
+                _ => {
+                    for (i, mesh) in node_meshes.iter().enumerate() {
+                        children.push(self.nodes.len());
+                        self.nodes.push(json!({ "name": format!("{}_{}", node.name, i), "mesh": mesh }));
+                    }
+                },
+            }
+
+            if !children.is_empty() {
+                self.nodes[base + idx]["children"] = json!(children);
+            }
+        }
+
+        Ok(base + object.root())
+    }
+
+    /// None for meshes without any triangles, gltf accessors can't be empty
+    fn add_mesh(&mut self, mesh: &Mesh, flip_uvs: bool) -> Result<Option<usize>, Box<dyn std::error::Error>> {
+        if mesh.vertices.is_empty() || mesh.indices.is_empty() {
+            return Ok(None);
+        }
+
+        // copies since the vertices are packed
+        let positions: Vec<[f32; 3]> = mesh.vertices.iter().map(|vertex| { let position = vertex.position; *position.as_array() }).collect();
+        let normals: Vec<glm::Vec3> = mesh.vertices.iter().map(|vertex| vertex.normal).collect();
+        // gltf's uvs start at the top left, assimp's start at the bottom left unless they were flipped on import
+        let uvs: Vec<[f32; 2]> = mesh.vertices.iter().map(|vertex| {
+            let uv = vertex.tex_coords;
+            if flip_uvs { [uv.x, uv.y] } else { [uv.x, 1.0 - uv.y] }
+        }).collect();
+
+        let mut attributes = json!({ "POSITION": self.push_floats(&positions, "VEC3", true) });
+        // missing normals are left at zero, gltf needs unit length ones
+        if normals.iter().all(|normal| glm::length(*normal) > f32::EPSILON) {
+            let normals: Vec<[f32; 3]> = normals.iter().map(|normal| *glm::normalize(*normal).as_array()).collect();
+            attributes["NORMAL"] = Value::from(self.push_floats(&normals, "VEC3", false));
+        }
+        if !mesh.textures.is_empty() {
+            attributes["TEXCOORD_0"] = Value::from(self.push_floats(&uvs, "VEC2", false));
+        }
+        let indices = self.push_indices(&mesh.indices);
+        let material = self.add_material(mesh)?;
+
+        self.meshes.push(json!({
+            "name": mesh.name,
+            "primitives": [{ "attributes": attributes, "indices": indices, "material": material }],
+        }));
+
+        Ok(Some(self.meshes.len() - 1))
+    }
+
+    fn add_material(&mut self, mesh: &Mesh) -> Result<usize, Box<dyn std::error::Error>> {
+        // the first texture of each kind wins, same as when it's drawn
+        let mut textures = [None; 5];
+        for texture in &mesh.textures {
+            if let Some(map) = mesh::pbr_map(texture.typ).filter(|&map| textures[map].is_none()) {
+                textures[map] = Some(self.add_texture(texture)?);
+            }
+        }
+
+        let material = &mesh.material;
+        if let Some(idx) = self.material_keys.iter().position(|(key, key_textures)| key == material && *key_textures == textures) {
+            return Ok(idx);
+        }
+
+        let base = material.base_color;
+        let mut pbr = json!({
+            "baseColorFactor": [base.x, base.y, base.z, base.w * material.opacity],
+            "metallicFactor": material.metallic,
+            "roughnessFactor": material.roughness,
+        });
+        let mut json = json!({
+            "name": material.name,
+            "emissiveFactor": material.emissive.as_array(),
+            "doubleSided": material.two_sided,
+            "alphaMode": match material.alpha_mode {
+                AlphaMode::Opaque => "OPAQUE",
+                AlphaMode::Mask => "MASK",
+                AlphaMode::Blend => "BLEND",
+            },
+        });
+        if material.alpha_mode == AlphaMode::Mask {
+            json["alphaCutoff"] = Value::from(material.alpha_cutoff);
+        }
+        if material.emissive_strength != 1.0 {
+            json["extensions"] = json!({ "KHR_materials_emissive_strength": { "emissiveStrength": material.emissive_strength } });
+            self.uses_emissive_strength = true;
+        }
+
+        for (map, texture) in textures.iter().enumerate() {
+            let texture = match texture {
+                Some(texture) => *texture,
+                None => continue,
+            };
+            // same order as `mesh::pbr_map`
+            match map {
+                0 => pbr["baseColorTexture"] = json!({ "index": texture }),
+                1 => pbr["metallicRoughnessTexture"] = json!({ "index": texture }),
+                2 => json["normalTexture"] = json!({ "index": texture, "scale": material.normal_scale }),
+                3 => json["occlusionTexture"] = json!({ "index": texture, "strength": material.occlusion_strength }),
+                _ => json["emissiveTexture"] = json!({ "index": texture }),
+            }
+        }
+        json["pbrMetallicRoughness"] = pbr;
+
+        self.materials.push(json);
+        self.material_keys.push((material.clone(), textures));
+
+        Ok(self.materials.len() - 1)
+    }
+
+    /// Images are shared between the meshes that share a texture, paths aren't unique since embedded
+    /// textures of different models can have the same one
+    fn add_texture(&mut self, texture: &Texture) -> Result<usize, Box<dyn std::error::Error>> {
+        let source = Rc::as_ptr(&texture.gl);
+        if let Some(idx) = self.image_sources.iter().position(|&image| image == source) {
+            return Ok(idx);
+        }
+
+        let (bytes, mime) = image_bytes(texture)?;
+        let image = if self.settings.embed_images && self.binary {
+            let view = self.push_view(&bytes, None);
+            json!({ "bufferView": view, "mimeType": mime })
+        } else if self.settings.embed_images {
+            json!({ "uri": format!("data:{};base64,{}", mime, base64::encode(&bytes)) })
+        } else {
+            let name = self.image_name(texture, mime);
+            std::fs::write(self.directory.join(&name), &bytes)?;
+            json!({ "uri": name })
+        };
+
+        self.images.push(image);
+        self.textures.push(json!({ "source": self.images.len() - 1, "sampler": 0 }));
+        self.image_sources.push(source);
+
+        Ok(self.textures.len() - 1)
+    }
+
+    /// The name of the original file if there is one, one that isn't taken yet otherwise
+    fn image_name(&mut self, texture: &Texture, mime: &str) -> String {
+        let extension = if mime == "image/jpeg" { "jpg" } else { "png" };
+        let original = texture.path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            // embedded textures are named "*<index>"
+            .filter(|stem| !stem.starts_with('*'));
+
+        let mut name = match &original {
+            Some(stem) => format!("{}.{}", stem, extension),
+            None => format!("{}_{}.{}", self.stem, self.images.len(), extension),
+        };
+        let mut i = 1;
+        while self.image_names.contains(&name) {
+            name = format!("{}_{}.{}", original.as_deref().unwrap_or(&self.stem), i, extension);
+            i += 1;
+        }
+        self.image_names.insert(name.clone());
+
+        name
+    }
+}
+
+/// The original file if it's a png or jpeg, anything else (embedded textures, other formats, bump maps that were
+/// turned into normal maps) is read back from the gpu as a png
+fn image_bytes(texture: &Texture) -> Result<(Vec<u8>, &'static str), Box<dyn std::error::Error>> {
+    let extension = texture.path.extension().map(|ext| ext.to_string_lossy().to_lowercase());
+    let mime = match extension.as_deref() {
+        Some("png") => Some("image/png"),
+        Some("jpg" | "jpeg") => Some("image/jpeg"),
+        _ => None,
+    };
+
+    let converted = texture.typ == russimp::material::TextureType::Height;
+    if let (Some(mime), true, false) = (mime, texture.path.is_file(), converted) {
+        return Ok((std::fs::read(&texture.path)?, mime));
+    }
+
+    Ok((read_texture(&texture.gl)?, "image/png"))
+}
+
+/// Needs the gl context, the texels come back in the order they were uploaded in so there's nothing to flip.
+/// One and two channel textures are grayscale (with alpha), they're read with their own channels so they don't turn red
+fn read_texture(texture: &GlTexture) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let (mut w, mut h, mut green, mut blue) = (0, 0, 0, 0);
+    unsafe {
+        gl::GetTextureLevelParameteriv(texture.id(), 0, gl::TEXTURE_WIDTH, &mut w);
+        gl::GetTextureLevelParameteriv(texture.id(), 0, gl::TEXTURE_HEIGHT, &mut h);
+        gl::GetTextureLevelParameteriv(texture.id(), 0, gl::TEXTURE_GREEN_SIZE, &mut green);
+        gl::GetTextureLevelParameteriv(texture.id(), 0, gl::TEXTURE_BLUE_SIZE, &mut blue);
+    }
+
+    let (format, channels) = match (green > 0, blue > 0) {
+        (false, _) => (gl::RED, 1),
+        (true, false) => (gl::RG, 2),
+        (true, true) => (gl::RGBA, 4),
+    };
+
+    let mut pixels = vec![0u8; (w * h * channels) as usize];
+    unsafe {
+        // rows of one and two channel textures aren't padded to 4 bytes
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::GetTextureImage(texture.id(), 0, format, gl::UNSIGNED_BYTE, pixels.len() as i32, pixels.as_mut_ptr() as *mut std::ffi::c_void);
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 4);
+    }
+
+    let (w, h) = (w as u32, h as u32);
+    let image = match channels {
+        1 => image::GrayImage::from_raw(w, h, pixels).map(image::DynamicImage::ImageLuma8),
+        2 => image::GrayAlphaImage::from_raw(w, h, pixels).map(image::DynamicImage::ImageLumaA8),
+        _ => image::RgbaImage::from_raw(w, h, pixels).map(image::DynamicImage::ImageRgba8),
+    }.ok_or("Texture has no pixels")?;
+
+    let mut png = std::io::Cursor::new(vec![]);
+    image.write_to(&mut png, image::ImageOutputFormat::Png)?;
+
+    Ok(png.into_inner())
+}
@@ -0,0 +1,585 @@
+use glm::GenSquareMat;
+
+use crate::{model::Model, transform::{Quat, Transform}};
+
+/// Length of the axes on screen, in pixels
+const GIZMO_SIZE: f32 = 90.0;
+/// How close the mouse has to be to grab a handle, in pixels
+const GRAB_DISTANCE: f32 = 6.0;
+const RING_SEGMENTS: usize = 64;
+/// Radius of the uniform scale handle, in pixels
+const UNIFORM_RADIUS: f32 = 8.0;
+
+const AXIS_COLORS: [[f32; 4]; 3] = [
+    [0.9, 0.2, 0.2, 1.0],
+    [0.3, 0.8, 0.2, 1.0],
+    [0.2, 0.4, 0.95, 1.0],
+];
+const HIGHLIGHT_COLOR: [f32; 4] = [1.0, 0.85, 0.2, 1.0];
+const UNIFORM_COLOR: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+impl GizmoMode {
+    pub const LABELS: [&'static str; 3] = ["Translate (W)", "Rotate (E)", "Scale (R)"];
+
+    pub fn index(&self) -> usize {
+        match self {
+            GizmoMode::Translate => 0,
+            GizmoMode::Rotate => 1,
+            GizmoMode::Scale => 2,
+        }
+    }
+
+    pub fn from_index(idx: usize) -> Self {
+        match idx {
+            0 => GizmoMode::Translate,
+            1 => GizmoMode::Rotate,
+            _ => GizmoMode::Scale,
+        }
+    }
+}
+
+impl Default for GizmoMode {
+    fn default() -> Self {
+        GizmoMode::Translate
+    }
+}
+
+/// Which way the axes of the gizmo point, scaling always happens along the node's own axes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GizmoSpace {
+    Local,
+    World,
+}
+
+impl GizmoSpace {
+    pub const LABELS: [&'static str; 2] = ["Local", "World"];
+
+    pub fn index(&self) -> usize {
+        match self {
+            GizmoSpace::Local => 0,
+            GizmoSpace::World => 1,
+        }
+    }
+
+    pub fn from_index(idx: usize) -> Self {
+        match idx {
+            0 => GizmoSpace::Local,
+            _ => GizmoSpace::World,
+        }
+    }
+}
+
+impl Default for GizmoSpace {
+    fn default() -> Self {
+        GizmoSpace::World
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GizmoSettings {
+    pub mode: GizmoMode,
+    pub space: GizmoSpace,
+    /// holding ctrl flips this while dragging
+    pub snap: bool,
+    /// in world units
+    pub translate_snap: f32,
+    /// in degrees
+    pub rotate_snap: f32,
+    /// scale factor increments
+    pub scale_snap: f32,
+}
+
+impl Default for GizmoSettings {
+    fn default() -> Self {
+        Self {
+            mode: GizmoMode::default(),
+            space: GizmoSpace::default(),
+            snap: false,
+            translate_snap: 0.5,
+            rotate_snap: 15.0,
+            scale_snap: 0.1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Handle {
+    /// moves or scales along an axis
+    Axis(usize),
+    /// moves in the plane the axis is the normal of
+    Plane(usize),
+    /// rotates around an axis
+    Ring(usize),
+    /// scales all axes at once
+    Uniform,
+}
+
+/// Where the gizmo is and which way its axes point, in world space
+#[derive(Clone, Copy)]
+struct Frame {
+    origin: glm::Vec3,
+    axes: [glm::Vec3; 3],
+    /// world length of the axes, keeps the gizmo the same size on screen at any distance
+    length: f32,
+}
+
+struct Drag {
+    handle: Handle,
+    /// the node's transform when the drag started
+    start: Transform,
+    /// where the drag started on the handle's axis or plane, in world space
+    start_point: glm::Vec3,
+    start_mouse: [f32; 2],
+    /// the gizmo when the drag started, the math is done against it so the handle doesn't move under the mouse
+    frame: Frame,
+}
+
+/// Maps between world space and the pixels of the scene image
+struct Viewport {
+    view_projection: glm::Mat4,
+    inverse: glm::Mat4,
+    eye: glm::Vec3,
+    /// projection[1][1], 1 / tan(fov / 2)
+    focal: f32,
+    min: [f32; 2],
+    size: [f32; 2],
+}
+
+impl Viewport {
+    fn new(view: &glm::Mat4, projection: &glm::Mat4, min: [f32; 2], size: [f32; 2]) -> Option<Self> {
+        let view_projection = *projection * *view;
+        let eye = view.inverse()?.c3.truncate(3);
+
+        Some(Self {
+            view_projection,
+            inverse: view_projection.inverse()?,
+            eye,
+            focal: projection.c1.y,
+            min,
+            size,
+        })
+    }
+
+    /// Screen position of a point, None if it's behind the camera
+    fn project(&self, point: glm::Vec3) -> Option<[f32; 2]> {
+        let clip = self.view_projection * glm::vec4(point.x, point.y, point.z, 1.0);
+        if clip.w <= 1e-4 {
+            return None;
+        }
+
+        let ndc = glm::vec2(clip.x / clip.w, clip.y / clip.w);
+        Some([
+            self.min[0] + (ndc.x + 1.0) * 0.5 * self.size[0],
+            self.min[1] + (1.0 - ndc.y) * 0.5 * self.size[1],
+        ])
+    }
+
+    /// World units per pixel at the depth of `point`
+    fn pixel_size(&self, point: glm::Vec3) -> Option<f32> {
+        let clip = self.view_projection * glm::vec4(point.x, point.y, point.z, 1.0);
+        if clip.w <= 1e-4 {
+            return None;
+        }
+
+        Some(2.0 * clip.w / (self.focal * self.size[1]))
+    }
+
+    /// Origin and direction of the ray through a screen position
+    fn ray(&self, mouse: [f32; 2]) -> (glm::Vec3, glm::Vec3) {
+        let x = (mouse[0] - self.min[0]) / self.size[0] * 2.0 - 1.0;
+        let y = 1.0 - (mouse[1] - self.min[1]) / self.size[1] * 2.0;
+
+        let near = self.inverse * glm::vec4(x, y, -1.0, 1.0);
+        let far = self.inverse * glm::vec4(x, y, 1.0, 1.0);
+        let near = near.truncate(3) / near.w;
+        let far = far.truncate(3) / far.w;
+
+        (near, glm::normalize(far - near))
+    }
+}
+
+/// Translate, rotate and scale handles drawn over the scene image
+#[derive(Default)]
+pub struct Gizmo {
+    pub settings: GizmoSettings,
+    drag: Option<Drag>,
+}
+
+impl Gizmo {
+    /// Draws the gizmo of `node` into the current window and applies drags to the node's transform.
+    /// `image_min` and `image_size` are the scene image's rect, returns whether the gizmo uses the mouse
+    #[allow(clippy::too_many_arguments)]
+    pub fn manipulate(&mut self, ui: &imgui::Ui, object: &mut Model, node: usize, view: &glm::Mat4, projection: &glm::Mat4, image_min: [f32; 2], image_size: [f32; 2], image_hovered: bool) -> bool {
+        let viewport = match Viewport::new(view, projection, image_min, image_size) {
+            Some(viewport) => viewport,
+            None => return false,
+        };
+        let frame = match self.frame(object, node, &viewport) {
+            Some(frame) => frame,
+            None => {
+                self.drag = None;
+                return false;
+            },
+        };
+
+        let mouse = ui.io().mouse_pos;
+        if !ui.is_mouse_down(imgui::MouseButton::Left) {
+            self.drag = None;
+        }
+
+        let hovered = match &self.drag {
+            Some(drag) => Some(drag.handle),
+            None if image_hovered => self.handle_at(&frame, &viewport, mouse),
+            None => None,
+        };
+
+        if self.drag.is_none() && ui.is_mouse_clicked(imgui::MouseButton::Left) {
+            if let Some(handle) = hovered {
+                self.drag = start_point(handle, &frame, &viewport, mouse).map(|start_point| Drag {
+                    handle,
+                    start: object.nodes[node].local_transform,
+                    start_point,
+                    start_mouse: mouse,
+                    frame,
+                });
+            }
+        }
+
+        if let Some(drag) = &self.drag {
+            // ctrl flips snapping for the current drag
+            let snap = self.settings.snap != ui.io().key_ctrl;
+            if let Some(transform) = self.dragged_transform(drag, object, node, &viewport, mouse, snap) {
+                object.nodes[node].local_transform = transform;
+            }
+        }
+
+        self.draw(ui, &frame, &viewport, hovered);
+
+        hovered.is_some()
+    }
+
+    fn frame(&self, object: &Model, node: usize, viewport: &Viewport) -> Option<Frame> {
+        let world = object.world_transform(node);
+        let pivot = object.pivot_point(node);
+        let origin = (world * glm::vec4(pivot.x, pivot.y, pivot.z, 1.0)).truncate(3);
+
+        let world_axes = [glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0), glm::vec3(0.0, 0.0, 1.0)];
+        let local = self.settings.space == GizmoSpace::Local || self.settings.mode == GizmoMode::Scale;
+        let axes = if local {
+            let columns = [world.c0.truncate(3), world.c1.truncate(3), world.c2.truncate(3)];
+            let mut axes = world_axes;
+            for (axis, column) in axes.iter_mut().zip(columns) {
+                // a node scaled to 0 has no direction left
+                if glm::length(column) > 1e-6 {
+                    *axis = glm::normalize(column);
+                }
+            }
+            axes
+        } else {
+            world_axes
+        };
+
+        Some(Frame {
+            origin,
+            axes,
+            length: GIZMO_SIZE * viewport.pixel_size(origin)?,
+        })
+    }
+
+    /// The handles of the current mode, closest one first
+    fn handle_at(&self, frame: &Frame, viewport: &Viewport, mouse: [f32; 2]) -> Option<Handle> {
+        let origin = viewport.project(frame.origin)?;
+        let mut closest: Option<(f32, Handle)> = None;
+        let mut consider = |distance: f32, handle: Handle| {
+            if distance <= GRAB_DISTANCE && closest.iter().all(|(best, _)| distance < *best) {
+                closest = Some((distance, handle));
+            }
+        };
+
+        match self.settings.mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                if self.settings.mode == GizmoMode::Scale {
+                    let center = distance(mouse, origin) - UNIFORM_RADIUS;
+                    consider(center.max(0.0), Handle::Uniform);
+                }
+
+                for i in 0..3 {
+                    if let Some(end) = visible_axis(frame, viewport, i) {
+                        consider(segment_distance(mouse, origin, end), Handle::Axis(i));
+                    }
+                }
+
+                if self.settings.mode == GizmoMode::Translate {
+                    for i in 0..3 {
+                        if let Some(quad) = plane_quad(frame, viewport, i) {
+                            if point_in_quad(mouse, &quad) {
+                                consider(0.0, Handle::Plane(i));
+                            }
+                        }
+                    }
+                }
+            },
+            GizmoMode::Rotate => {
+                for i in 0..3 {
+                    let ring = ring_points(frame, viewport, i);
+                    for segment in ring.windows(2) {
+                        consider(segment_distance(mouse, segment[0], segment[1]), Handle::Ring(i));
+                    }
+                }
+            },
+        }
+
+        closest.map(|(_, handle)| handle)
+    }
+
+    fn dragged_transform(&self, drag: &Drag, object: &Model, node: usize, viewport: &Viewport, mouse: [f32; 2], snap: bool) -> Option<Transform> {
+        let frame = &drag.frame;
+        let mut transform = drag.start;
+
+        // deltas are in world space, the transform is relative to the parent
+        let parent = match object.nodes[node].parent {
+            Some(parent) => object.world_transform(parent),
+            None => object.axis_conversion.matrix(),
+        };
+        let inverse_parent = parent.inverse()?;
+
+        match drag.handle {
+            Handle::Axis(i) if self.settings.mode == GizmoMode::Scale => {
+                let point = closest_on_axis(frame.origin, frame.axes[i], viewport.ray(mouse))?;
+                let start = glm::dot(drag.start_point - frame.origin, frame.axes[i]);
+                if start.abs() < 1e-6 {
+                    return None;
+                }
+
+                let factor = snap_value(glm::dot(point - frame.origin, frame.axes[i]) / start, self.settings.scale_snap, snap);
+                transform.scale[i] = drag.start.scale[i] * factor;
+            },
+            Handle::Uniform => {
+                let origin = viewport.project(frame.origin)?;
+                let start = distance(drag.start_mouse, origin);
+                if start < 1.0 {
+                    return None;
+                }
+
+                let factor = snap_value(distance(mouse, origin) / start, self.settings.scale_snap, snap);
+                transform.scale = drag.start.scale * factor;
+            },
+            Handle::Axis(i) => {
+                let point = closest_on_axis(frame.origin, frame.axes[i], viewport.ray(mouse))?;
+                let moved = snap_value(glm::dot(point - drag.start_point, frame.axes[i]), self.settings.translate_snap, snap);
+                let delta = frame.axes[i] * moved;
+                transform.translation = drag.start.translation + (inverse_parent * glm::vec4(delta.x, delta.y, delta.z, 0.0)).truncate(3);
+            },
+            Handle::Plane(i) => {
+                let point = intersect_plane(frame.origin, frame.axes[i], viewport.ray(mouse))?;
+                let offset = point - drag.start_point;
+                let mut delta = glm::vec3(0.0, 0.0, 0.0);
+                for j in [(i + 1) % 3, (i + 2) % 3] {
+                    delta = delta + frame.axes[j] * snap_value(glm::dot(offset, frame.axes[j]), self.settings.translate_snap, snap);
+                }
+                transform.translation = drag.start.translation + (inverse_parent * glm::vec4(delta.x, delta.y, delta.z, 0.0)).truncate(3);
+            },
+            Handle::Ring(i) => {
+                let axis = frame.axes[i];
+                let point = intersect_plane(frame.origin, axis, viewport.ray(mouse))?;
+                let from = drag.start_point - frame.origin;
+                let to = point - frame.origin;
+                let angle = glm::dot(axis, glm::cross(from, to)).atan2(glm::dot(from, to));
+                let mut angle = snap_value(angle.to_degrees(), self.settings.rotate_snap, snap).to_radians();
+
+                // the same rotation seen from the parent's space, which turns the other way if it's mirrored
+                let parent_axis = (inverse_parent * glm::vec4(axis.x, axis.y, axis.z, 0.0)).truncate(3);
+                if glm::length(parent_axis) < 1e-6 {
+                    return None;
+                }
+                let (x, y, z) = (parent.c0.truncate(3), parent.c1.truncate(3), parent.c2.truncate(3));
+                if glm::dot(x, glm::cross(y, z)) < 0.0 {
+                    angle = -angle;
+                }
+
+                transform.rotation = (Quat::from_axis_angle(parent_axis, angle) * drag.start.rotation).normalize();
+            },
+        }
+
+        Some(transform)
+    }
+
+    fn draw(&self, ui: &imgui::Ui, frame: &Frame, viewport: &Viewport, hovered: Option<Handle>) {
+        let origin = match viewport.project(frame.origin) {
+            Some(origin) => origin,
+            None => return,
+        };
+        let draw_list = ui.get_window_draw_list();
+        let color = |handle: Handle| match handle {
+            _ if hovered == Some(handle) => HIGHLIGHT_COLOR,
+            Handle::Axis(i) | Handle::Plane(i) | Handle::Ring(i) => AXIS_COLORS[i],
+            Handle::Uniform => UNIFORM_COLOR,
+        };
+
+        match self.settings.mode {
+            GizmoMode::Translate => {
+                for i in 0..3 {
+                    if let Some(quad) = plane_quad(frame, viewport, i) {
+                        let [r, g, b, _] = color(Handle::Plane(i));
+                        draw_list.add_polyline(quad.to_vec(), [r, g, b, 0.5]).filled(true).build();
+                    }
+                }
+                for i in 0..3 {
+                    if let Some(end) = visible_axis(frame, viewport, i) {
+                        let axis_color = color(Handle::Axis(i));
+                        draw_list.add_line(origin, end, axis_color).thickness(3.0).build();
+                        draw_arrow_head(&draw_list, origin, end, axis_color);
+                    }
+                }
+            },
+            GizmoMode::Rotate => {
+                for i in 0..3 {
+                    let ring = ring_points(frame, viewport, i);
+                    draw_list.add_polyline(ring, color(Handle::Ring(i))).thickness(2.5).build();
+                }
+            },
+            GizmoMode::Scale => {
+                for i in 0..3 {
+                    if let Some(end) = visible_axis(frame, viewport, i) {
+                        let axis_color = color(Handle::Axis(i));
+                        draw_list.add_line(origin, end, axis_color).thickness(3.0).build();
+                        draw_list.add_rect([end[0] - 5.0, end[1] - 5.0], [end[0] + 5.0, end[1] + 5.0], axis_color).filled(true).build();
+                    }
+                }
+                draw_list.add_circle(origin, UNIFORM_RADIUS, color(Handle::Uniform)).filled(true).build();
+            },
+        }
+    }
+}
+
+/// Screen position of the end of an axis, None if it points too much at the camera to be grabbed
+fn visible_axis(frame: &Frame, viewport: &Viewport, i: usize) -> Option<[f32; 2]> {
+    let origin = viewport.project(frame.origin)?;
+    let end = viewport.project(frame.origin + frame.axes[i] * frame.length)?;
+
+    if distance(origin, end) < GIZMO_SIZE * 0.15 {
+        return None;
+    }
+
+    Some(end)
+}
+
+/// Corners of the square handle in the plane that has axis `i` as its normal, None if it's seen edge on
+fn plane_quad(frame: &Frame, viewport: &Viewport, i: usize) -> Option<[[f32; 2]; 4]> {
+    let view_dir = glm::normalize(frame.origin - viewport.eye);
+    if glm::abs(glm::dot(view_dir, frame.axes[i])) < 0.2 {
+        return None;
+    }
+
+    let a = frame.axes[(i + 1) % 3] * frame.length;
+    let b = frame.axes[(i + 2) % 3] * frame.length;
+    let corner = |u: f32, v: f32| viewport.project(frame.origin + a * u + b * v);
+
+    Some([corner(0.2, 0.2)?, corner(0.45, 0.2)?, corner(0.45, 0.45)?, corner(0.2, 0.45)?])
+}
+
+/// Screen points of the ring around axis `i`, only the parts in front of the camera
+fn ring_points(frame: &Frame, viewport: &Viewport, i: usize) -> Vec<[f32; 2]> {
+    let a = frame.axes[(i + 1) % 3] * frame.length;
+    let b = frame.axes[(i + 2) % 3] * frame.length;
+
+    (0..=RING_SEGMENTS).filter_map(|segment| {
+        let angle = segment as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+        viewport.project(frame.origin + a * angle.cos() + b * angle.sin())
+    }).collect()
+}
+
+fn draw_arrow_head(draw_list: &imgui::DrawListMut, from: [f32; 2], to: [f32; 2], color: [f32; 4]) {
+    let length = distance(from, to);
+    let dir = [(to[0] - from[0]) / length, (to[1] - from[1]) / length];
+    let side = [-dir[1] * 6.0, dir[0] * 6.0];
+    let tip = [to[0] + dir[0] * 12.0, to[1] + dir[1] * 12.0];
+
+    draw_list.add_triangle(tip, [to[0] + side[0], to[1] + side[1]], [to[0] - side[0], to[1] - side[1]], color)
+        .filled(true)
+        .build();
+}
+
+/// Where a drag on `handle` starts, on the handle's axis or plane
+fn start_point(handle: Handle, frame: &Frame, viewport: &Viewport, mouse: [f32; 2]) -> Option<glm::Vec3> {
+    match handle {
+        Handle::Axis(i) => closest_on_axis(frame.origin, frame.axes[i], viewport.ray(mouse)),
+        Handle::Plane(i) | Handle::Ring(i) => intersect_plane(frame.origin, frame.axes[i], viewport.ray(mouse)),
+        Handle::Uniform => Some(frame.origin),
+    }
+}
+
+/// Point on the line through `origin` along `axis` that's closest to the ray
+fn closest_on_axis(origin: glm::Vec3, axis: glm::Vec3, (ray_origin, ray_dir): (glm::Vec3, glm::Vec3)) -> Option<glm::Vec3> {
+    let w = origin - ray_origin;
+    let b = glm::dot(axis, ray_dir);
+    let denom = glm::dot(axis, axis) - b * b;
+    // looking straight down the axis
+    if denom.abs() < 1e-6 {
+        return None;
+    }
+
+    let t = (b * glm::dot(ray_dir, w) - glm::dot(axis, w)) / denom;
+    Some(origin + axis * t)
+}
+
+fn intersect_plane(origin: glm::Vec3, normal: glm::Vec3, (ray_origin, ray_dir): (glm::Vec3, glm::Vec3)) -> Option<glm::Vec3> {
+    let facing = glm::dot(normal, ray_dir);
+    if facing.abs() < 1e-6 {
+        return None;
+    }
+
+    let t = glm::dot(normal, origin - ray_origin) / facing;
+    if t < 0.0 {
+        return None;
+    }
+
+    Some(ray_origin + ray_dir * t)
+}
+
+fn snap_value(value: f32, increment: f32, snap: bool) -> f32 {
+    if snap && increment > 0.0 {
+        (value / increment).round() * increment
+    } else {
+        value
+    }
+}
+
+fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+}
+
+fn segment_distance(p: [f32; 2], a: [f32; 2], b: [f32; 2]) -> f32 {
+    let ab = [b[0] - a[0], b[1] - a[1]];
+    let length = ab[0] * ab[0] + ab[1] * ab[1];
+    if length < 1e-6 {
+        return distance(p, a);
+    }
+
+    let t = (((p[0] - a[0]) * ab[0] + (p[1] - a[1]) * ab[1]) / length).clamp(0.0, 1.0);
+    distance(p, [a[0] + ab[0] * t, a[1] + ab[1] * t])
+}
+
+/// Works for either winding since the quad is convex
+fn point_in_quad(p: [f32; 2], quad: &[[f32; 2]; 4]) -> bool {
+    let mut sign = 0.0;
+    for i in 0..4 {
+        let (a, b) = (quad[i], quad[(i + 1) % 4]);
+        let cross = (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0]);
+        if cross * sign < 0.0 {
+            return false;
+        }
+        if cross != 0.0 {
+            sign = cross;
+        }
+    }
+
+    true
+}
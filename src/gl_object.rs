@@ -0,0 +1,124 @@
+use glad_gl::gl;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Kinds of gl objects that are counted for the debug panel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    VertexArray,
+    Buffer,
+    Texture,
+    Program,
+    Framebuffer,
+    Renderbuffer,
+}
+
+impl Kind {
+    pub const ALL: [Kind; 6] = [Kind::VertexArray, Kind::Buffer, Kind::Texture, Kind::Program, Kind::Framebuffer, Kind::Renderbuffer];
+    pub const LABELS: [&'static str; 6] = ["Vertex Arrays", "Buffers", "Textures", "Programs", "Framebuffers", "Renderbuffers"];
+
+    pub fn index(&self) -> usize {
+        match self {
+            Kind::VertexArray => 0,
+            Kind::Buffer => 1,
+            Kind::Texture => 2,
+            Kind::Program => 3,
+            Kind::Framebuffer => 4,
+            Kind::Renderbuffer => 5,
+        }
+    }
+
+    /// Objects of this kind that haven't been deleted yet
+    pub fn live_count(&self) -> usize {
+        LIVE[self.index()].load(Ordering::Relaxed)
+    }
+}
+
+static LIVE: [AtomicUsize; 6] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+fn created(kind: Kind) {
+    LIVE[kind.index()].fetch_add(1, Ordering::Relaxed);
+}
+
+fn deleted(kind: Kind) {
+    LIVE[kind.index()].fetch_sub(1, Ordering::Relaxed);
+}
+
+/// Owning handle types that are created with `glGen*` and deleted with `glDelete*` when dropped.
+/// They have to be created and dropped on the thread that has the gl context
+macro_rules! gl_object {
+    ($(#[$doc:meta])* $name:ident, $kind:expr, $gen:path, $delete:path) => {
+        $(#[$doc])*
+        #[derive(Debug)]
+        pub struct $name(u32);
+
+        // a default would hide that this creates a gl object
+        #[allow(clippy::new_without_default)]
+        impl $name {
+            pub fn new() -> Self {
+                let mut id = 0;
+                unsafe {
+                    $gen(1, &mut id);
+                }
+                created($kind);
+
+                $name(id)
+            }
+
+            pub fn id(&self) -> u32 {
+                self.0
+            }
+        }
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                unsafe {
+                    $delete(1, &self.0);
+                }
+                deleted($kind);
+            }
+        }
+    };
+}
+
+gl_object!(VertexArray, Kind::VertexArray, gl::GenVertexArrays, gl::DeleteVertexArrays);
+gl_object!(Buffer, Kind::Buffer, gl::GenBuffers, gl::DeleteBuffers);
+gl_object!(
+    /// Named so it doesn't clash with `mesh::Texture`, which shares these between meshes
+    GlTexture, Kind::Texture, gl::GenTextures, gl::DeleteTextures
+);
+gl_object!(Framebuffer, Kind::Framebuffer, gl::GenFramebuffers, gl::DeleteFramebuffers);
+gl_object!(Renderbuffer, Kind::Renderbuffer, gl::GenRenderbuffers, gl::DeleteRenderbuffers);
+
+/// Linked shader program
+#[derive(Debug)]
+pub struct Program(u32);
+
+#[allow(clippy::new_without_default)]
+impl Program {
+    pub fn new() -> Self {
+        let id = unsafe { gl::CreateProgram() };
+        created(Kind::Program);
+
+        Program(id)
+    }
+
+    pub fn id(&self) -> u32 {
+        self.0
+    }
+}
+
+impl Drop for Program {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteProgram(self.0);
+        }
+        deleted(Kind::Program);
+    }
+}
@@ -0,0 +1,337 @@
+use crate::{light::Light, material::Material, model::{Model, Placement}, node::Pivot, selection::Selection, transform::Transform};
+
+/// Undo steps that are kept, the oldest ones are dropped first
+const MAX_STEPS: usize = 100;
+
+/// The parts of the state that commands edit
+pub struct Scene<'a> {
+    pub objects: &'a mut Vec<Model>,
+    pub lights: &'a mut Vec<Light>,
+    pub selection: &'a mut Option<Selection>,
+}
+
+/// Everything the transform editors and the gizmo change on a node
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NodeTransform {
+    pub transform: Transform,
+    pub pivot: Pivot,
+}
+
+impl NodeTransform {
+    pub fn of(model: &Model, node: usize) -> Self {
+        Self {
+            transform: model.nodes[node].local_transform,
+            pivot: model.nodes[node].pivot,
+        }
+    }
+
+    fn apply(&self, model: &mut Model, node: usize) {
+        model.nodes[node].local_transform = self.transform;
+        model.nodes[node].pivot = self.pivot;
+    }
+}
+
+/// A reversible edit of the scene, the things a command takes out of the scene are kept in it until it's applied again
+pub enum Command {
+    /// an object and the lights that came with it, the lights go after the scene's lights
+    Import {
+        index: usize,
+        object: Option<Model>,
+        light_start: usize,
+        light_count: usize,
+        lights: Vec<Light>,
+    },
+    Delete {
+        index: usize,
+        object: Option<Model>,
+    },
+    Transform {
+        object: usize,
+        node: usize,
+        before: NodeTransform,
+        after: NodeTransform,
+    },
+    Material {
+        object: usize,
+        mesh: usize,
+        before: Box<Material>,
+        after: Box<Material>,
+    },
+    /// weights of a mesh's morph targets set with the sliders, not the ones an animation plays
+    MorphWeights {
+        object: usize,
+        mesh: usize,
+        before: Vec<f32>,
+        after: Vec<f32>,
+    },
+    Light {
+        index: usize,
+        before: Box<Light>,
+        after: Box<Light>,
+    },
+    AddLight {
+        index: usize,
+        light: Option<Light>,
+    },
+    RemoveLight {
+        index: usize,
+        light: Option<Light>,
+    },
+    Reparent {
+        object: usize,
+        node: usize,
+        before: Placement,
+        after: Placement,
+    },
+}
+
+impl Command {
+    /// An import that moves the model's lights into the scene with it
+    pub fn import(mut object: Model, scene: &Scene) -> Self {
+        let lights: Vec<Light> = object.imported_lights.drain(..).collect();
+        Command::Import {
+            index: scene.objects.len(),
+            object: Some(object),
+            light_start: scene.lights.len(),
+            light_count: lights.len(),
+            lights,
+        }
+    }
+
+    fn apply(&mut self, scene: &mut Scene) {
+        match self {
+            Command::Import { index, object, light_start, lights, .. } => {
+                insert_object(scene, *index, object.take().expect("Import applied twice"));
+                scene.lights.splice(*light_start..*light_start, lights.drain(..));
+            },
+            Command::Delete { index, object } => *object = Some(remove_object(scene, *index)),
+            Command::Transform { object, node, after, .. } => after.apply(&mut scene.objects[*object], *node),
+            Command::Material { object, mesh, after, .. } => scene.objects[*object].meshes[*mesh].material = (**after).clone(),
+            Command::MorphWeights { object, mesh, after, .. } => scene.objects[*object].meshes[*mesh].morph_weights = after.clone(),
+            Command::Light { index, after, .. } => scene.lights[*index] = (**after).clone(),
+            Command::AddLight { index, light } => scene.lights.insert(*index, light.take().expect("Light added twice")),
+            Command::RemoveLight { index, light } => *light = Some(scene.lights.remove(*index)),
+            Command::Reparent { object, node, after, .. } => {
+                scene.objects[*object].reparent(*node, *after);
+            },
+        }
+    }
+
+    fn revert(&mut self, scene: &mut Scene) {
+        match self {
+            Command::Import { index, object, light_start, light_count, lights } => {
+                *object = Some(remove_object(scene, *index));
+                *lights = scene.lights.drain(*light_start..*light_start + *light_count).collect();
+            },
+            Command::Delete { index, object } => insert_object(scene, *index, object.take().expect("Delete reverted twice")),
+            Command::Transform { object, node, before, .. } => before.apply(&mut scene.objects[*object], *node),
+            Command::Material { object, mesh, before, .. } => scene.objects[*object].meshes[*mesh].material = (**before).clone(),
+            Command::MorphWeights { object, mesh, before, .. } => scene.objects[*object].meshes[*mesh].morph_weights = before.clone(),
+            Command::Light { index, before, .. } => scene.lights[*index] = (**before).clone(),
+            Command::AddLight { index, light } => *light = Some(scene.lights.remove(*index)),
+            Command::RemoveLight { index, light } => scene.lights.insert(*index, light.take().expect("Light removal reverted twice")),
+            Command::Reparent { object, node, before, .. } => {
+                scene.objects[*object].reparent(*node, *before);
+            },
+        }
+    }
+
+    /// Folds `next` into this command if both edit the same thing
+    fn merge(&mut self, next: &Command) -> bool {
+        match (self, next) {
+            (Command::Transform { object, node, after, .. }, Command::Transform { object: next_object, node: next_node, after: next_after, .. })
+                if object == next_object && node == next_node => *after = *next_after,
+            (Command::Material { object, mesh, after, .. }, Command::Material { object: next_object, mesh: next_mesh, after: next_after, .. })
+                if object == next_object && mesh == next_mesh => *after = next_after.clone(),
+            (Command::MorphWeights { object, mesh, after, .. }, Command::MorphWeights { object: next_object, mesh: next_mesh, after: next_after, .. })
+                if object == next_object && mesh == next_mesh => *after = next_after.clone(),
+            (Command::Light { index, after, .. }, Command::Light { index: next_index, after: next_after, .. })
+                if index == next_index => *after = next_after.clone(),
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Whether merging ended up where it started, like a drag that was moved back
+    fn is_noop(&self) -> bool {
+        match self {
+            Command::Transform { before, after, .. } => before == after,
+            Command::Material { before, after, .. } => before == after,
+            Command::MorphWeights { before, after, .. } => before == after,
+            Command::Light { before, after, .. } => before == after,
+            _ => false,
+        }
+    }
+}
+
+fn insert_object(scene: &mut Scene, index: usize, object: Model) {
+    scene.objects.insert(index, object);
+    *scene.selection = scene.selection.map(|selection| selection.object_inserted(index));
+}
+
+fn remove_object(scene: &mut Scene, index: usize) -> Model {
+    *scene.selection = scene.selection.and_then(|selection| selection.object_removed(index));
+    scene.objects.remove(index)
+}
+
+struct Step {
+    label: String,
+    command: Command,
+}
+
+/// Undo and redo stacks of the edits made in the ui
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+    /// index of the undo step edits of the same thing are merged into until the ui lets go of it
+    merging: Option<usize>,
+}
+
+impl History {
+    /// Records a command that was already applied to the scene
+    pub fn push(&mut self, label: String, command: Command) {
+        self.redo.clear();
+
+        if let Some(idx) = self.merging {
+            if self.undo[idx].command.merge(&command) {
+                // an edit that went back to where it started is dropped, what follows starts a new step
+                if self.undo[idx].command.is_noop() {
+                    self.undo.remove(idx);
+                    self.merging = None;
+                }
+                return;
+            }
+        }
+
+        self.undo.push(Step { label, command });
+        if self.undo.len() > MAX_STEPS {
+            self.undo.remove(0);
+        }
+        self.merging = Some(self.undo.len() - 1);
+    }
+
+    /// Applies a command and records it
+    pub fn execute(&mut self, label: String, mut command: Command, scene: &mut Scene) {
+        command.apply(scene);
+        self.end_merge();
+        self.push(label, command);
+        self.end_merge();
+    }
+
+    /// Records a transform edit of a node if `before` isn't where it is now
+    pub fn record_transform(&mut self, model: &Model, object: usize, node: usize, before: NodeTransform) {
+        let after = NodeTransform::of(model, node);
+        if after != before {
+            self.push(format!("Transform {}", model.nodes[node].name), Command::Transform { object, node, before, after });
+        }
+    }
+
+    /// Called once nothing in the ui is being edited, the next edit starts a new step
+    pub fn end_merge(&mut self) {
+        self.merging = None;
+    }
+
+    /// Returns the label of the step that was undone
+    pub fn undo(&mut self, scene: &mut Scene) -> Option<String> {
+        let mut step = self.undo.pop()?;
+        step.command.revert(scene);
+        self.merging = None;
+
+        let label = step.label.clone();
+        self.redo.push(step);
+        Some(label)
+    }
+
+    /// Returns the label of the step that was redone
+    pub fn redo(&mut self, scene: &mut Scene) -> Option<String> {
+        let mut step = self.redo.pop()?;
+        step.command.apply(scene);
+        self.merging = None;
+
+        let label = step.label.clone();
+        self.undo.push(step);
+        Some(label)
+    }
+
+    /// Oldest first, the last one is undone next
+    pub fn undo_labels(&self) -> impl Iterator<Item = &str> {
+        self.undo.iter().map(|step| step.label.as_str())
+    }
+
+    /// The first one is redone next
+    pub fn redo_labels(&self) -> impl Iterator<Item = &str> {
+        self.redo.iter().rev().map(|step| step.label.as_str())
+    }
+
+    pub fn undo_count(&self) -> usize {
+        self.undo.len()
+    }
+
+    pub fn redo_count(&self) -> usize {
+        self.redo.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.merging = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::LightKind;
+
+    fn light(intensity: f32) -> Light {
+        let mut light = Light::new("Light", LightKind::Point);
+        light.intensity = intensity;
+        light
+    }
+
+    /// Edits the light the way the ui does, applied first and then recorded
+    fn edit(history: &mut History, lights: &mut [Light], from: f32, to: f32) {
+        lights[0] = light(to);
+        history.push(String::from("Edit Light"), Command::Light { index: 0, before: Box::new(light(from)), after: Box::new(light(to)) });
+    }
+
+    fn undo(history: &mut History, lights: &mut Vec<Light>) {
+        let (mut objects, mut selection) = (vec![], None);
+        history.undo(&mut Scene { objects: &mut objects, lights, selection: &mut selection });
+    }
+
+    #[test]
+    fn edits_that_go_back_start_a_new_step() {
+        let mut history = History::default();
+        let mut lights = vec![light(1.0)];
+
+        edit(&mut history, &mut lights, 1.0, 2.0);
+        history.end_merge();
+
+        // a drag that goes back to where it started and then keeps going
+        edit(&mut history, &mut lights, 2.0, 3.0);
+        edit(&mut history, &mut lights, 3.0, 2.0);
+        edit(&mut history, &mut lights, 2.0, 4.0);
+        assert_eq!(history.undo_count(), 2);
+
+        undo(&mut history, &mut lights);
+        assert_eq!(lights[0].intensity, 2.0);
+        undo(&mut history, &mut lights);
+        assert_eq!(lights[0].intensity, 1.0);
+    }
+
+    #[test]
+    fn drags_merge_into_one_step() {
+        let mut history = History::default();
+        let mut lights = vec![light(1.0)];
+
+        edit(&mut history, &mut lights, 1.0, 2.0);
+        edit(&mut history, &mut lights, 2.0, 3.0);
+        assert_eq!(history.undo_count(), 1);
+
+        undo(&mut history, &mut lights);
+        assert_eq!(lights[0].intensity, 1.0);
+    }
+}
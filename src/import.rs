@@ -0,0 +1,467 @@
+use std::{collections::HashMap, path::PathBuf, sync::{atomic::{AtomicBool, Ordering}, mpsc, Arc, Mutex}, time::Duration};
+
+use russimp::scene::PostProcess;
+use serde::{Deserialize, Serialize};
+
+use crate::{model::{Model, ModelData, ModelUpload}, project::ObjectEntry, log::Log};
+
+/// Axis the model file treats as up, it's rotated so that axis ends up as y
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum UpAxis {
+    /// trust the file
+    File,
+    X,
+    Y,
+    Z,
+}
+
+impl UpAxis {
+    pub const LABELS: [&'static str; 4] = ["From File", "X", "Y", "Z"];
+
+    pub fn index(&self) -> usize {
+        match self {
+            UpAxis::File => 0,
+            UpAxis::X => 1,
+            UpAxis::Y => 2,
+            UpAxis::Z => 3,
+        }
+    }
+
+    pub fn from_index(idx: usize) -> Self {
+        match idx {
+            1 => UpAxis::X,
+            2 => UpAxis::Y,
+            3 => UpAxis::Z,
+            _ => UpAxis::File,
+        }
+    }
+
+    /// Up, front and right axes of the file, `None` to use the ones from its metadata
+    pub fn axes(&self) -> Option<(SignedAxis, SignedAxis, SignedAxis)> {
+        match self {
+            UpAxis::File => None,
+            UpAxis::X => Some((SignedAxis::PosX, SignedAxis::PosZ, SignedAxis::NegY)),
+            UpAxis::Y => Some((SignedAxis::PosY, SignedAxis::PosZ, SignedAxis::PosX)),
+            // blender's convention
+            UpAxis::Z => Some((SignedAxis::PosZ, SignedAxis::NegY, SignedAxis::PosX)),
+        }
+    }
+}
+
+impl Default for UpAxis {
+    fn default() -> Self {
+        UpAxis::File
+    }
+}
+
+/// Options picked in the import dialog, most of them map directly to assimp's post processing steps
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImportSettings {
+    pub flip_uvs: bool,
+    /// only affects meshes that don't have normals
+    pub gen_smooth_normals: bool,
+    pub join_identical_vertices: bool,
+    pub optimize_meshes: bool,
+    /// bakes the node transforms into the meshes and flattens the hierarchy
+    pub pre_transform_vertices: bool,
+    pub flip_winding_order: bool,
+    pub merge_materials: bool,
+    pub scale: f32,
+    pub up_axis: UpAxis,
+}
+
+impl Default for ImportSettings {
+    fn default() -> Self {
+        ImportSettings {
+            flip_uvs: true,
+            gen_smooth_normals: false,
+            join_identical_vertices: false,
+            optimize_meshes: false,
+            pre_transform_vertices: false,
+            flip_winding_order: false,
+            merge_materials: false,
+            scale: 1.0,
+            up_axis: UpAxis::default(),
+        }
+    }
+}
+
+impl ImportSettings {
+    pub fn post_process(&self) -> Vec<PostProcess> {
+        // always needed by the renderer
+        let mut steps = vec![
+            PostProcess::Triangulate,
+            PostProcess::CalculateTangentSpace,
+            PostProcess::LimitBoneWeights,
+        ];
+
+        let optional = [
+            (self.flip_uvs, PostProcess::FlipUVs),
+            (self.gen_smooth_normals, PostProcess::GenerateSmoothNormals),
+            (self.join_identical_vertices, PostProcess::JoinIdenticalVertices),
+            (self.optimize_meshes, PostProcess::OptimizeMeshes),
+            (self.pre_transform_vertices, PostProcess::PreTransformVertices),
+            (self.flip_winding_order, PostProcess::FlipWindingOrder),
+            (self.merge_materials, PostProcess::RemoveRedundantMaterials),
+        ];
+        steps.extend(optional.into_iter().filter(|(enabled, _)| *enabled).map(|(_, step)| step));
+
+        steps
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SignedAxis {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+impl SignedAxis {
+    pub const LABELS: [&'static str; 6] = ["+X", "-X", "+Y", "-Y", "+Z", "-Z"];
+
+    pub fn index(&self) -> usize {
+        match self {
+            SignedAxis::PosX => 0,
+            SignedAxis::NegX => 1,
+            SignedAxis::PosY => 2,
+            SignedAxis::NegY => 3,
+            SignedAxis::PosZ => 4,
+            SignedAxis::NegZ => 5,
+        }
+    }
+
+    pub fn from_index(idx: usize) -> Self {
+        match idx {
+            1 => SignedAxis::NegX,
+            2 => SignedAxis::PosY,
+            3 => SignedAxis::NegY,
+            4 => SignedAxis::PosZ,
+            5 => SignedAxis::NegZ,
+            _ => SignedAxis::PosX,
+        }
+    }
+
+    /// fbx stores axes as an index (0 x, 1 y, 2 z) and a sign
+    fn from_fbx(axis: f64, sign: f64) -> Option<Self> {
+        let positive = sign >= 0.0;
+        match axis as i32 {
+            0 => Some(if positive { SignedAxis::PosX } else { SignedAxis::NegX }),
+            1 => Some(if positive { SignedAxis::PosY } else { SignedAxis::NegY }),
+            2 => Some(if positive { SignedAxis::PosZ } else { SignedAxis::NegZ }),
+            _ => None,
+        }
+    }
+
+    pub fn vector(&self) -> glm::Vec3 {
+        match self {
+            SignedAxis::PosX => glm::vec3(1.0, 0.0, 0.0),
+            SignedAxis::NegX => glm::vec3(-1.0, 0.0, 0.0),
+            SignedAxis::PosY => glm::vec3(0.0, 1.0, 0.0),
+            SignedAxis::NegY => glm::vec3(0.0, -1.0, 0.0),
+            SignedAxis::PosZ => glm::vec3(0.0, 0.0, 1.0),
+            SignedAxis::NegZ => glm::vec3(0.0, 0.0, -1.0),
+        }
+    }
+}
+
+/// Converts a model from the axes and units of its file to y up, z front and meters
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AxisConversion {
+    pub up: SignedAxis,
+    pub front: SignedAxis,
+    pub right: SignedAxis,
+    /// meters per unit of the file
+    pub unit_scale: f32,
+}
+
+impl Default for AxisConversion {
+    fn default() -> Self {
+        AxisConversion {
+            up: SignedAxis::PosY,
+            front: SignedAxis::PosZ,
+            right: SignedAxis::PosX,
+            unit_scale: 1.0,
+        }
+    }
+}
+
+impl AxisConversion {
+    /// Metadata keys that fbx files store their axes and unit scale under
+    pub const METADATA_KEYS: [&'static str; 7] = ["UpAxis", "UpAxisSign", "FrontAxis", "FrontAxisSign", "CoordAxis", "CoordAxisSign", "UnitScaleFactor"];
+
+    /// Reads the axes and unit scale fbx files store in their metadata, `metadata` has the numeric values by key.
+    /// `None` if there aren't any
+    /// reference: https://github.com/assimp/assimp/issues/849#issuecomment-538982013
+    pub fn from_metadata(metadata: &HashMap<String, f64>) -> Option<Self> {
+        let value = |key: &str| metadata.get(key).copied();
+        let axis = |axis: &str, sign: &str| SignedAxis::from_fbx(value(axis)?, value(sign).unwrap_or(1.0));
+
+        let up = axis("UpAxis", "UpAxisSign");
+        let front = axis("FrontAxis", "FrontAxisSign");
+        let right = axis("CoordAxis", "CoordAxisSign");
+        let unit_scale = value("UnitScaleFactor");
+
+        if up.is_none() && front.is_none() && right.is_none() && unit_scale.is_none() {
+            return None;
+        }
+
+        let default = AxisConversion::default();
+        Some(AxisConversion {
+            up: up.unwrap_or(default.up),
+            front: front.unwrap_or(default.front),
+            right: right.unwrap_or(default.right),
+            // fbx units are in centimeters
+            unit_scale: unit_scale.map_or(default.unit_scale, |scale| scale as f32 * 0.01),
+        })
+    }
+
+    /// The axes have to be 3 different ones, otherwise the model collapses
+    pub fn is_valid(&self) -> bool {
+        let (up, front, right) = (self.up.vector(), self.front.vector(), self.right.vector());
+        glm::dot(up, front) == 0.0 && glm::dot(up, right) == 0.0 && glm::dot(front, right) == 0.0
+    }
+
+    /// Applied above the root node, identity if the axes aren't valid
+    pub fn matrix(&self) -> glm::Mat4 {
+        if !self.is_valid() {
+            return crate::utils::mat_ident();
+        }
+
+        // rows are the file's right, up and front axes
+        let (r, u, f) = (self.right.vector() * self.unit_scale, self.up.vector() * self.unit_scale, self.front.vector() * self.unit_scale);
+        glm::mat4(
+            r.x, u.x, f.x, 0.0,
+            r.y, u.y, f.y, 0.0,
+            r.z, u.z, f.z, 0.0,
+            0.0, 0.0, 0.0, 1.0,
+        )
+    }
+}
+
+impl std::fmt::Display for AxisConversion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "up {}, front {}, right {}, unit scale {}",
+            SignedAxis::LABELS[self.up.index()],
+            SignedAxis::LABELS[self.front.index()],
+            SignedAxis::LABELS[self.right.index()],
+            self.unit_scale,
+        )
+    }
+}
+
+/// Settings last used for every file extension
+#[derive(Debug, Default)]
+pub struct ImportPresets {
+    presets: HashMap<String, ImportSettings>,
+}
+
+impl ImportPresets {
+    pub fn get(&self, path: &std::path::Path) -> ImportSettings {
+        self.presets.get(&extension(path)).cloned().unwrap_or_default()
+    }
+
+    pub fn set(&mut self, path: &std::path::Path, settings: ImportSettings) {
+        self.presets.insert(extension(path), settings);
+    }
+}
+
+/// Lowercase so "model.FBX" and "model.fbx" share their settings
+pub fn extension(path: &std::path::Path) -> String {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// Shared between the ui and the thread loading a model
+#[derive(Debug, Default)]
+pub struct Progress {
+    /// fraction done and what's being done
+    stage: Mutex<(f32, String)>,
+    cancelled: AtomicBool,
+}
+
+impl Progress {
+    pub fn set(&self, fraction: f32, stage: &str) {
+        if let Ok(mut current) = self.stage.lock() {
+            *current = (fraction, stage.to_string());
+        }
+    }
+
+    pub fn get(&self) -> (f32, String) {
+        match self.stage.lock() {
+            Ok(current) => current.clone(),
+            Err(_) => (0.0, String::new()),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// How long uploading a model to the gpu can take every frame
+const UPLOAD_BUDGET: Duration = Duration::from_millis(4);
+
+enum Stage {
+    Loading(mpsc::Receiver<Result<ModelData, String>>),
+    Uploading(Box<ModelUpload>),
+    Cancelled,
+}
+
+/// What an import job ended with
+pub enum ImportOutcome {
+    Loaded(Model),
+    Failed(String),
+    /// by the user, nothing of the model is kept
+    Cancelled,
+}
+
+/// A model that's read on a worker thread and then uploaded a bit every frame
+pub struct ImportJob {
+    pub path: PathBuf,
+    pub progress: Arc<Progress>,
+    /// overrides from a project file, applied once the model is loaded
+    pub restore: Option<ObjectEntry>,
+    stage: Stage,
+}
+
+impl ImportJob {
+    pub fn spawn(path: PathBuf, settings: ImportSettings) -> Self {
+        let progress = Arc::new(Progress::default());
+        let (sender, receiver) = mpsc::channel();
+
+        let thread_path = path.clone();
+        let thread_progress = progress.clone();
+        std::thread::spawn(move || {
+            let result = match thread_path.to_str() {
+                Some(path) => ModelData::load(path, &settings, &thread_progress).map_err(|e| e.to_string()),
+                None => Err(String::from("Model path isn't valid utf-8")),
+            };
+            // the job was dropped if nobody is listening anymore
+            let _ = sender.send(result);
+        });
+
+        ImportJob {
+            path,
+            progress,
+            restore: None,
+            stage: Stage::Loading(receiver),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.path.file_name().map_or(String::new(), |name| name.to_string_lossy().to_string())
+    }
+
+    /// Fraction done and what's being done, loading and uploading are half of it each
+    pub fn status(&self) -> (f32, String) {
+        match &self.stage {
+            Stage::Loading(..) => {
+                let (fraction, stage) = self.progress.get();
+                (fraction * 0.5, stage)
+            },
+            Stage::Uploading(upload) => (0.5 + upload.progress() * 0.5, String::from("Uploading")),
+            Stage::Cancelled => (1.0, String::from("Cancelled")),
+        }
+    }
+
+    /// Has to be called on the thread with the gl context, returns the model once it's done
+    pub fn poll(&mut self, log: &mut Log) -> Option<ImportOutcome> {
+        if self.progress.is_cancelled() {
+            // drops the textures and meshes that were already uploaded, the loading thread finds nobody listening
+            self.stage = Stage::Cancelled;
+            return Some(ImportOutcome::Cancelled);
+        }
+
+        if let Stage::Loading(receiver) = &self.stage {
+            match receiver.try_recv() {
+                Ok(Ok(data)) => self.stage = Stage::Uploading(Box::new(ModelUpload::new(data))),
+                Ok(Err(e)) => return Some(ImportOutcome::Failed(e)),
+                Err(mpsc::TryRecvError::Empty) => return None,
+                Err(mpsc::TryRecvError::Disconnected) => return Some(ImportOutcome::Failed(String::from("Import thread crashed"))),
+            }
+        }
+
+        match &mut self.stage {
+            Stage::Uploading(upload) => {
+                if upload.step(UPLOAD_BUDGET) {
+                    Some(ImportOutcome::Loaded(upload.finish(log)))
+                } else {
+                    None
+                }
+            },
+            Stage::Loading(..) => None,
+            Stage::Cancelled => Some(ImportOutcome::Cancelled),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(values: &[(&str, f64)]) -> HashMap<String, f64> {
+        values.iter().map(|&(key, value)| (key.to_string(), value)).collect()
+    }
+
+    #[test]
+    fn reads_fbx_axes_and_units() {
+        let conversion = AxisConversion::from_metadata(&metadata(&[
+            ("UpAxis", 2.0), ("UpAxisSign", 1.0),
+            ("FrontAxis", 1.0), ("FrontAxisSign", -1.0),
+            ("CoordAxis", 0.0), ("CoordAxisSign", 1.0),
+            ("UnitScaleFactor", 1.0),
+        ])).unwrap();
+
+        assert_eq!((conversion.up, conversion.front, conversion.right), (SignedAxis::PosZ, SignedAxis::NegY, SignedAxis::PosX));
+        assert!((conversion.unit_scale - 0.01).abs() < 1e-6);
+    }
+
+    #[test]
+    fn files_without_axes_have_no_conversion() {
+        assert!(AxisConversion::from_metadata(&metadata(&[("FrameRate", 24.0)])).is_none());
+    }
+
+    #[test]
+    fn z_up_files_are_rotated_to_y_up() {
+        // z up, -y front and centimeters, the way 3ds max writes them
+        let conversion = AxisConversion {
+            up: SignedAxis::from_fbx(2.0, 1.0).unwrap(),
+            front: SignedAxis::from_fbx(1.0, -1.0).unwrap(),
+            right: SignedAxis::from_fbx(0.0, 1.0).unwrap(),
+            unit_scale: 0.01,
+        };
+        assert!(conversion.is_valid());
+
+        let matrix = conversion.matrix();
+        let up = matrix * glm::vec4(0.0, 0.0, 1.0, 0.0);
+        let front = matrix * glm::vec4(0.0, -1.0, 0.0, 0.0);
+        assert!(glm::length(up.truncate(3) - glm::vec3(0.0, 0.01, 0.0)) < 1e-6);
+        assert!(glm::length(front.truncate(3) - glm::vec3(0.0, 0.0, 0.01)) < 1e-6);
+    }
+
+    #[test]
+    fn repeated_axes_are_ignored() {
+        let conversion = AxisConversion {
+            up: SignedAxis::PosY,
+            front: SignedAxis::NegY,
+            ..AxisConversion::default()
+        };
+
+        assert!(!conversion.is_valid());
+        assert_eq!(conversion.matrix(), crate::utils::mat_ident());
+    }
+}
@@ -5,7 +5,23 @@ pub mod imgui_glfw_support;
 #[path="imgui-opengl-renderer/mod.rs"]
 pub mod imgui_opengl_renderer;
 pub mod utils;
+pub mod gl_object;
+pub mod render_target;
+pub mod post;
+pub mod light;
+pub mod shadow;
+pub mod environment;
+pub mod selection;
+pub mod gizmo;
+pub mod history;
+pub mod project;
+pub mod export;
 pub mod mesh;
+pub mod material;
 pub mod model;
+pub mod node;
+pub mod transform;
+pub mod animation;
+pub mod import;
 pub mod ui;
 pub mod log;
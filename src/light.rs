@@ -0,0 +1,224 @@
+use glad_gl::gl;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{gl_object::Buffer, shader::Shader, shadow::ShadowSettings, utils};
+
+/// Has to match the binding of the `Lights` block in the shaders
+const LIGHTS_BINDING: u32 = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LightKind {
+    Directional,
+    Point,
+    Spot,
+}
+
+impl LightKind {
+    pub const LABELS: [&'static str; 3] = ["Directional", "Point", "Spot"];
+
+    pub fn index(&self) -> usize {
+        match self {
+            LightKind::Directional => 0,
+            LightKind::Point => 1,
+            LightKind::Spot => 2,
+        }
+    }
+
+    pub fn from_index(idx: usize) -> Self {
+        match idx {
+            0 => LightKind::Directional,
+            1 => LightKind::Point,
+            _ => LightKind::Spot,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Light {
+    pub name: String,
+    pub kind: LightKind,
+    /// unused by directional lights
+    pub position: glm::Vec3,
+    /// unused by point lights
+    pub direction: glm::Vec3,
+    pub color: glm::Vec3,
+    pub intensity: f32,
+    /// distance where point and spot lights fade out completely, 0 never fades out
+    pub range: f32,
+    /// spot cone angles from the light's direction in degrees, full intensity inside the inner one
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+    /// moves with the camera like a flashlight
+    pub follow_camera: bool,
+    pub shadow: ShadowSettings,
+}
+
+impl Light {
+    pub fn new(name: &str, kind: LightKind) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            position: glm::vec3(0.0, 2.0, 0.0),
+            direction: glm::vec3(0.0, -1.0, 0.0),
+            color: glm::vec3(1.0, 1.0, 1.0),
+            intensity: match kind {
+                LightKind::Directional => 1.0,
+                _ => 5.0,
+            },
+            range: 0.0,
+            inner_angle: 12.5,
+            outer_angle: 15.0,
+            follow_camera: false,
+            shadow: ShadowSettings::new(kind),
+        }
+    }
+
+    /// Lights from a model file, `transform` takes them from their node's space into world space
+    pub fn from_assimp(light: &russimp::light::Light, transform: &glm::Mat4) -> Option<Self> {
+        let kind = match light.light_source_type {
+            russimp::light::LightSourceType::Directional => LightKind::Directional,
+            russimp::light::LightSourceType::Point => LightKind::Point,
+            russimp::light::LightSourceType::Spot => LightKind::Spot,
+            _ => return None,
+        };
+
+        // assimp bakes the intensity into the color
+        let color = glm::vec3(light.color_diffuse.r, light.color_diffuse.g, light.color_diffuse.b);
+        let intensity = color.x.max(color.y).max(color.z);
+        let position = *transform * glm::vec4(light.pos.x, light.pos.y, light.pos.z, 1.0);
+        let direction = *transform * glm::vec4(light.direction.x, light.direction.y, light.direction.z, 0.0);
+
+        let mut imported = Self::new(&light.name, kind);
+        imported.position = position.truncate(3);
+        if glm::length(direction.truncate(3)) > 0.0 {
+            imported.direction = glm::normalize(direction.truncate(3));
+        }
+        if intensity > 0.0 {
+            imported.color = color / intensity;
+            imported.intensity = intensity;
+        }
+        if kind == LightKind::Spot {
+            imported.inner_angle = glm::degrees(light.angle_inner_cone);
+            imported.outer_angle = glm::degrees(light.angle_outer_cone);
+        }
+
+        Some(imported)
+    }
+
+    /// The lights the scene starts with
+    pub fn defaults() -> Vec<Self> {
+        let mut sun = Light::new("Sun", LightKind::Directional);
+        sun.direction = glm::normalize(glm::vec3(-0.2, -1.0, -0.3));
+        sun.intensity = 0.5;
+
+        let mut lights = vec![sun];
+        let positions = [
+            glm::vec3(0.7, 0.2, 2.0),
+            glm::vec3(2.3, -3.3, -4.0),
+            glm::vec3(-4.0, 2.0, -12.0),
+            glm::vec3(0.0, 0.0, -3.0),
+        ];
+        for (i, position) in positions.iter().enumerate() {
+            let mut point = Light::new(&format!("Point Light {}", i), LightKind::Point);
+            point.position = *position;
+            point.range = 20.0;
+            lights.push(point);
+        }
+
+        let mut flashlight = Light::new("Flashlight", LightKind::Spot);
+        flashlight.follow_camera = true;
+        flashlight.intensity = 2.0;
+        flashlight.range = 50.0;
+        // the camera is inside of its own shadow map's near plane, there's nothing to see
+        flashlight.shadow.enabled = false;
+        lights.push(flashlight);
+
+        lights
+    }
+}
+
+/// A light as the shaders read it, laid out for std430
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GpuLight {
+    position: [f32; 3],
+    kind: i32,
+    direction: [f32; 3],
+    range: f32,
+    color: [f32; 3],
+    intensity: f32,
+    inner_cut_off: f32,
+    outer_cut_off: f32,
+    /// layer in the shadow map array of the light's kind, -1 without shadows
+    shadow_index: i32,
+    shadow_bias: f32,
+    normal_bias: f32,
+    pcf_radius: i32,
+    _padding: [f32; 2],
+    light_space: [f32; 16],
+}
+
+/// Storage buffer with every light in the scene, sized to the light count
+pub struct LightBuffer {
+    buffer: Buffer,
+    count: usize,
+    /// lights the buffer has room for, at least 1 so it can always be bound
+    allocated: usize,
+}
+
+impl LightBuffer {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            buffer: Buffer::new(),
+            count: 0,
+            allocated: 0,
+        }
+    }
+
+    /// `shadows` has the shadow map layer and light space matrix of every light
+    pub fn upload(&mut self, lights: &[Light], shadows: &[(i32, glm::Mat4)]) {
+        let gpu_lights: Vec<GpuLight> = lights.iter().zip(shadows).map(|(light, (shadow_index, light_space))| GpuLight {
+            position: *light.position.as_array(),
+            kind: light.kind.index() as i32,
+            direction: *glm::normalize(light.direction).as_array(),
+            range: light.range,
+            color: *light.color.as_array(),
+            intensity: light.intensity,
+            inner_cut_off: glm::cos(glm::radians(light.inner_angle.min(light.outer_angle))),
+            outer_cut_off: glm::cos(glm::radians(light.outer_angle)),
+            shadow_index: *shadow_index,
+            shadow_bias: light.shadow.bias,
+            normal_bias: light.shadow.normal_bias,
+            pcf_radius: light.shadow.pcf_radius,
+            _padding: [0.0; 2],
+            light_space: utils::mat4_to_array(light_space),
+        }).collect();
+
+        self.count = gpu_lights.len();
+        let allocated = self.count.max(1);
+
+        unsafe {
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.buffer.id());
+            if allocated != self.allocated {
+                gl::BufferData(gl::SHADER_STORAGE_BUFFER, (allocated * std::mem::size_of::<GpuLight>()) as isize, std::ptr::null(), gl::DYNAMIC_DRAW);
+                self.allocated = allocated;
+            }
+            if !gpu_lights.is_empty() {
+                gl::BufferSubData(gl::SHADER_STORAGE_BUFFER, 0, (gpu_lights.len() * std::mem::size_of::<GpuLight>()) as isize, gpu_lights.as_ptr() as *const std::ffi::c_void);
+            }
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+        }
+    }
+
+    /// Binds the buffer and sets the light count of a lighting shader
+    pub fn bind(&self, shader: &Shader) {
+        shader.use_shader();
+        shader.set_int("lightCount", self.count as i32);
+
+        unsafe {
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, LIGHTS_BINDING, self.buffer.id());
+        }
+    }
+}
@@ -2,7 +2,7 @@ use glfw::{Action, Context, Key, Modifiers};
 use glad_gl::gl;
 use anyhow;
 
-use rust_gl::{shader, model, ui::ui};
+use rust_gl::{shader, camera, model, material, import, post, shadow, environment, selection, gizmo, log, light::{self, LightKind}, gl_object::{Buffer, VertexArray}, render_target::{self, Format, RenderTarget}, ui::ui};
 
 fn main() -> anyhow::Result<(), Box<dyn std::error::Error>> {
     let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS)?;
@@ -24,8 +24,15 @@ fn main() -> anyhow::Result<(), Box<dyn std::error::Error>> {
     let (mut imgui, glfw_platform, renderer) = ui::init_imgui(&mut window);
 
     let mesh_shader = shader::Shader::new("shaders/vertex.glsl", "shaders/frag.glsl")?;
+    let pbr_shader = shader::Shader::new("shaders/vertex.glsl", "shaders/pbr_f.glsl")?;
     let light_shader = shader::Shader::new("shaders/vertex.glsl", "shaders/light_f.glsl")?;
     let grid_shader = shader::Shader::new("shaders/grid_v.glsl", "shaders/grid_f.glsl")?;
+    let post_process = post::PostProcess::new()?;
+    let mut shadow_maps = shadow::ShadowMaps::new()?;
+    let mut light_buffer = light::LightBuffer::new();
+    let environment_renderer = environment::EnvironmentRenderer::new()?;
+    let mut picker = selection::Picker::new()?;
+    let mut outline = selection::Outline::new()?;
 
     let vertices: [f32; 288] = [
         // positions // normals // texture coords
@@ -67,13 +74,6 @@ fn main() -> anyhow::Result<(), Box<dyn std::error::Error>> {
         -0.5, 0.5, -0.5, 0.0, 1.0, 0.0, 0.0, 1.0
             ];
 
-    let points_lights: [glm::Vec3; 4] = [
-        glm::vec3(0.7, 0.2, 2.0),
-        glm::vec3(2.3, -3.3, -4.0),
-        glm::vec3(-4.0, 2.0, -12.0),
-        glm::vec3(0.0, 0.0, -3.0),
-    ];
-
     let ident_mat = glm::mat4(
         1., 0., 0., 0.,
         0., 1., 0., 0.,
@@ -92,10 +92,9 @@ fn main() -> anyhow::Result<(), Box<dyn std::error::Error>> {
     unsafe {
         // Object 1: Cube/container
         //
-        let mut vbo: u32 = 0;
-        gl::GenBuffers(1, &mut vbo);
+        let vbo = Buffer::new();
 
-        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo.id());
         gl::BufferData(gl::ARRAY_BUFFER, (vertices.len() * core::mem::size_of::<f32>()) as isize, vertices.as_ptr() as *const std::ffi::c_void, gl::STATIC_DRAW);
 
         // position attribute, maps to 'aPos' in vertex shader with location 0
@@ -111,53 +110,36 @@ fn main() -> anyhow::Result<(), Box<dyn std::error::Error>> {
         gl::EnableVertexAttribArray(2);
 
         // Object 2: Light source
-        let mut light_vao: u32 = 0;
-        gl::GenVertexArrays(1, &mut light_vao);
+        let light_vao = VertexArray::new();
 
-        gl::BindVertexArray(light_vao);
+        gl::BindVertexArray(light_vao.id());
 
-        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo.id());
 
         // position attribute, maps to 'aPos' in vertex shader with location 0
         gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, 8 * std::mem::size_of::<f32>() as i32, std::ptr::null());
         gl::EnableVertexAttribArray(0);
 
         grid_shader.use_shader();
-        grid_shader.set_float("near", 0.01);
-        grid_shader.set_float("far", 200.0);
-
-        mesh_shader.use_shader();
-
-        // set light uniforms
-        for i in 0..points_lights.len() {
-            mesh_shader.set_3fv(&format!("pointLights[{}].position", i), points_lights[i]);
-
-            mesh_shader.set_float(&format!("pointLights[{}].constant", i), 1.0);
-            mesh_shader.set_float(&format!("pointLights[{}].linear", i), 0.09);
-            mesh_shader.set_float(&format!("pointLights[{}].quadratic", i), 0.032);
-
-            mesh_shader.set_3fv(&format!("pointLights[{}].ambient", i), glm::vec3(0.2, 0.2, 0.2));
-            mesh_shader.set_3fv(&format!("pointLights[{}].diffuse", i), glm::vec3(0.5, 0.5, 0.5));
-            mesh_shader.set_3fv(&format!("pointLights[{}].specular", i), glm::vec3(1.0, 1.0, 1.0));
+        grid_shader.set_float("near", camera::NEAR);
+        grid_shader.set_float("far", camera::FAR);
+
+        // the scene is drawn multisampled in hdr, resolved, then tone mapped into an srgb texture that imgui can show
+        let mut scene_msaa = RenderTarget::new(&[Format::Rgba16F], Some(Format::Depth24Stencil8));
+        let mut scene_hdr = RenderTarget::new(&[Format::Rgba16F], None);
+        let mut scene_target = RenderTarget::new(&[Format::Rgba8], None);
+        state.max_samples = render_target::max_samples();
+        state.msaa_samples = state.msaa_samples.min(state.max_samples);
+
+        // a scene passed on the command line, otherwise the lantern as an example
+        match std::env::args().nth(1) {
+            Some(project) => ui::open_project(&mut state, std::path::Path::new(&project)),
+            None => {
+                let mut lantern = model::Model::new("models/lantern/Lantern.gltf", &import::ImportSettings::default(), &mut state.log)?;
+                state.lights.append(&mut lantern.imported_lights);
+                state.objects.push(lantern);
+            },
         }
-        mesh_shader.set_float("spotLight.cutOff", glm::cos(glm::radians(12.5)));
-        mesh_shader.set_float("spotLight.outerCutOff", glm::cos(glm::radians(15.0)));
-        mesh_shader.set_3fv("spotLight.ambient", glm::vec3(0.2, 0.2, 0.2));
-        mesh_shader.set_3fv("spotLight.diffuse", glm::vec3(0.2, 0.2, 0.2));
-        mesh_shader.set_3fv("spotLight.specular", glm::vec3(1.0, 1.0, 1.0));
-        mesh_shader.set_float("spotLight.constant", 1.0);
-        mesh_shader.set_float("spotLight.linear", 0.09);
-        mesh_shader.set_float("spotLight.quadratic", 0.032);
-
-        mesh_shader.set_3fv("dirLight.direction", glm::vec3(-0.2, -1.0, -0.3));
-        mesh_shader.set_3fv("dirLight.ambient", glm::vec3(0.2, 0.2, 0.2));
-        mesh_shader.set_3fv("dirLight.diffuse", glm::vec3(0.5, 0.5, 0.5));
-        mesh_shader.set_3fv("dirLight.specular", glm::vec3(1.0, 1.0, 1.0));
-
-        let lantern = model::Model::new("models/lantern/Lantern.gltf", &mut state)?;
-        state.objects.push(lantern);
-
-        let scene_fb = create_scene_framebuffer();
 
         // main loop
         while !window.should_close() {
@@ -171,8 +153,8 @@ fn main() -> anyhow::Result<(), Box<dyn std::error::Error>> {
             imgui.io_mut().update_delta_time(std::time::Duration::from_secs_f32(delta_time));
 
             // camera matrices
-            let view_mat = glm::ext::look_at(state.camera.position, state.camera.position + state.camera.front, state.camera.up);
-            let projection_mat = glm::ext::perspective(glm::radians(state.camera.fov), state.viewport_size[0] / state.viewport_size[1], 0.01, 200.0);
+            let view_mat = state.camera.view_matrix();
+            let projection_mat = state.camera.projection_matrix(state.viewport_size[0] / state.viewport_size[1]);
 
             for (_, event) in glfw::flush_messages(&events) {
                 if !state.is_cursor_captured {
@@ -198,26 +180,76 @@ fn main() -> anyhow::Result<(), Box<dyn std::error::Error>> {
                 }
             }
 
+            for obj in &mut state.objects {
+                obj.update_animation(delta_time);
+            }
+
             //
             // draw scene to framebuffer
             //
-            let (scene_texture, rbo) = create_scene_texture_and_renderbuffer(&window, scene_fb);
+            for light in state.lights.iter_mut().filter(|light| light.follow_camera) {
+                light.position = state.camera.position;
+                light.direction = state.camera.front;
+            }
 
-            gl::BindFramebuffer(gl::FRAMEBUFFER, scene_fb);
+            // environments are decoded in the background and baked once they're ready
+            if let Some(result) = state.environment_job.as_ref().and_then(|job| job.poll()) {
+                let job = state.environment_job.take().unwrap();
+                match result {
+                    Ok(image) => {
+                        state.log.log(&format!("Loaded environment \"{}\"", job.path.display()), log::LogLevel::Info);
+                        state.environment = Some(environment_renderer.bake(job.path, &image));
+                    },
+                    Err(e) => {
+                        let error = format!("Error loading environment \"{}\": {}", job.path.display(), e);
+                        println!("{}", error);
+
+                        state.log.log(&error, log::LogLevel::Error);
+                    },
+                }
+            }
+
+            shadow_maps.render(&state.objects, &state.lights);
+            light_buffer.upload(&state.lights, shadow_maps.assignments());
+            if state.shadow_maps_shown {
+                shadow_maps.draw_previews(&state.lights);
+                state.shadow_previews = shadow_maps.previews();
+            }
+
+            // sized to the scene panel from the last frame
+            let (scene_w, scene_h) = (state.viewport_size[0] as i32, state.viewport_size[1] as i32);
+            scene_msaa.set_samples(state.msaa_samples);
+            scene_msaa.resize(scene_w, scene_h);
+            scene_hdr.resize(scene_w, scene_h);
+            scene_target.resize(scene_w, scene_h);
+
+            // a click in the scene panel from the last frame
+            if let Some(pixel) = state.pick_request.take() {
+                state.selection = picker.pick(&state.objects, pixel, (scene_w, scene_h), &view_mat, &projection_mat);
+                state.reveal_selection = state.selection.is_some();
+            }
+
+            scene_msaa.bind();
             gl::Enable(gl::DEPTH_TEST);
             gl::Enable(gl::BLEND);
             gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
             gl::ClearColor(0.2, 0.2, 0.2, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
 
-            mesh_shader.use_shader();
+            let object_shader = match state.shading_model {
+                material::ShadingModel::Phong => &mesh_shader,
+                material::ShadingModel::Pbr => &pbr_shader,
+            };
+            object_shader.use_shader();
+            shadow_maps.bind(object_shader);
+            light_buffer.bind(object_shader);
+            environment_renderer.bind(object_shader, state.environment.as_ref(), &state.environment_settings);
+            object_shader.set_3fv("ambientLight", state.ambient_light);
 
-            mesh_shader.set_mat4fv("view", &view_mat);
-            mesh_shader.set_mat4fv("projection", &projection_mat);
+            object_shader.set_mat4fv("view", &view_mat);
+            object_shader.set_mat4fv("projection", &projection_mat);
 
-            mesh_shader.set_3fv("spotLight.position", state.camera.position);
-            mesh_shader.set_3fv("spotLight.direction", state.camera.front);
-            mesh_shader.set_3fv("viewPos", state.camera.position);
+            object_shader.set_3fv("viewPos", state.camera.position);
 
             for obj in &state.objects {
                 if state.wireframe {
@@ -225,7 +257,7 @@ fn main() -> anyhow::Result<(), Box<dyn std::error::Error>> {
                 } else {
                     gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
                 }
-                obj.draw(&mesh_shader);
+                obj.draw(object_shader);
             }
             gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
 
@@ -233,38 +265,47 @@ fn main() -> anyhow::Result<(), Box<dyn std::error::Error>> {
             light_shader.set_mat4fv("view", &view_mat);
             light_shader.set_mat4fv("projection", &projection_mat);
 
-            for i in 0..points_lights.len() {
-                let light_model = glm::ext::translate(&ident_mat, points_lights[i]);
+            // directional lights have no position and the ones following the camera would cover the view
+            for light in state.lights.iter().filter(|light| light.kind != LightKind::Directional && !light.follow_camera) {
+                let light_model = glm::ext::translate(&ident_mat, light.position);
                 let light_model = glm::ext::scale(&light_model, glm::vec3(0.2, 0.2, 0.2));
                 light_shader.set_mat4fv("model", &light_model);
+                light_shader.set_3fv("lightColor", light.color);
 
-                gl::BindVertexArray(light_vao);
+                gl::BindVertexArray(light_vao.id());
                 gl::DrawArrays(gl::TRIANGLES, 0, 36);
             }
 
+            if let Some(env) = state.environment.as_ref().filter(|_| state.environment_settings.show_skybox) {
+                environment_renderer.draw_skybox(env, &state.environment_settings, &view_mat, &projection_mat);
+            }
+
             // draw grid
             if state.draw_grid {draw_grid(&grid_shader, &view_mat, &projection_mat);}
 
+            scene_msaa.resolve(&scene_hdr);
+
+            scene_target.bind();
+            post_process.draw(scene_hdr.color(0), state.tone_mapping, state.exposure);
+            if let Some(selection) = &state.selection {
+                outline.draw(&state.objects, selection, &scene_target, &view_mat, &projection_mat);
+            }
+
             //
             // draw ui
             //
             gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            let (fb_w, fb_h) = window.get_framebuffer_size();
+            gl::Viewport(0, 0, fb_w, fb_h);
             gl::ClearColor(0.1, 0.1, 0.1, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT);
             gl::Disable(gl::DEPTH_TEST);
             gl::Disable(gl::BLEND);
-            ui::draw_ui(&mut imgui, &renderer, &glfw_platform, &mut window, &mut state, delta_time, &mut last_cursor, scene_texture);
+            ui::draw_ui(&mut imgui, &renderer, &glfw_platform, &mut window, &mut state, delta_time, &mut last_cursor, scene_target.color(0).id());
 
             glfw.poll_events();
             window.swap_buffers();
-
-            gl::DeleteTextures(1, &scene_texture);
-            gl::DeleteRenderbuffers(1, &rbo);
         }
-
-        gl::DeleteVertexArrays(1, &light_vao);
-        gl::DeleteBuffers(1, &vbo);
-        gl::DeleteFramebuffers(1, &scene_fb);
     }
 
     Ok(())
@@ -284,6 +325,9 @@ fn handle_window_event(window: &mut glfw::Window, event: &glfw::WindowEvent, sta
         glfw::WindowEvent::Key(Key::Q, _, Action::Press, Modifiers::Control) => {
             window.set_should_close(true);
         }
+        glfw::WindowEvent::Key(Key::S, _, Action::Press, Modifiers::Control) => {
+            ui::save_scene(state);
+        }
         glfw::WindowEvent::Key(Key::LeftShift, _, Action::Press, _) => {
             state.camera.speed *= 5.0;
         }
@@ -298,6 +342,22 @@ fn handle_window_event(window: &mut glfw::Window, event: &glfw::WindowEvent, sta
                 window.set_cursor_mode(glfw::CursorMode::Normal);
             }
         }
+        // gizmo modes, the camera uses the same keys while the cursor is captured
+        glfw::WindowEvent::Key(Key::W, _, Action::Press, _) if state.scene_hovered && !state.is_cursor_captured => {
+            state.gizmo.settings.mode = gizmo::GizmoMode::Translate;
+        }
+        glfw::WindowEvent::Key(Key::E, _, Action::Press, _) if state.scene_hovered && !state.is_cursor_captured => {
+            state.gizmo.settings.mode = gizmo::GizmoMode::Rotate;
+        }
+        glfw::WindowEvent::Key(Key::R, _, Action::Press, _) if state.scene_hovered && !state.is_cursor_captured => {
+            state.gizmo.settings.mode = gizmo::GizmoMode::Scale;
+        }
+        glfw::WindowEvent::Key(Key::Z, _, Action::Press | Action::Repeat, modifiers) if !state.text_input_active && *modifiers == Modifiers::Control => {
+            ui::undo(state);
+        }
+        glfw::WindowEvent::Key(Key::Z, _, Action::Press | Action::Repeat, modifiers) if !state.text_input_active && *modifiers == Modifiers::Control | Modifiers::Shift => {
+            ui::redo(state);
+        }
         glfw::WindowEvent::Scroll(_, yoff) => {
             state.camera.handle_mouse_scroll(*yoff as f32, state.is_cursor_captured);
         }
@@ -309,46 +369,3 @@ fn handle_window_event(window: &mut glfw::Window, event: &glfw::WindowEvent, sta
         _ => {}
     }
 }
-
-fn create_scene_framebuffer() -> u32 {
-    let mut fb: u32 = 0;
-
-    unsafe {
-        gl::GenFramebuffers(1, &mut fb);
-        gl::BindFramebuffer(gl::FRAMEBUFFER, fb);
-    }
-
-    return fb;
-}
-
-fn create_scene_texture_and_renderbuffer(window: &glfw::Window, fbo: u32) -> (u32, u32) {
-    let mut fb_texture: u32 = 0;
-    let mut rbo: u32 = 0;
-
-    let (w, h) = window.get_size();
-
-    unsafe {
-        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
-        // texture
-        gl::GenTextures(1, &mut fb_texture);
-        gl::BindTexture(gl::TEXTURE_2D, fb_texture);
-
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
-        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGB as i32, w, h, 0, gl::RGB, gl::UNSIGNED_BYTE, std::ptr::null());
-
-        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, fb_texture, 0);
-
-        // renderbuffer for depth
-        gl::GenRenderbuffers(1, &mut rbo);
-        gl::BindRenderbuffer(gl::RENDERBUFFER, rbo);
-        gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, w, h);
-        gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, rbo);
-
-        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
-            panic!("ERROR::FRAMEBUFFER:: Framebuffer is not complete!");
-        }
-    }
-
-    return (fb_texture, rbo);
-}
@@ -0,0 +1,371 @@
+use russimp::material::{PropertyTypeInfo, TextureType};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ShadingModel {
+    Phong,
+    Pbr,
+}
+
+impl ShadingModel {
+    pub const LABELS: [&'static str; 2] = ["Phong", "PBR"];
+
+    pub fn index(&self) -> usize {
+        match self {
+            ShadingModel::Phong => 0,
+            ShadingModel::Pbr => 1,
+        }
+    }
+
+    pub fn from_index(idx: usize) -> Self {
+        match idx {
+            0 => ShadingModel::Phong,
+            _ => ShadingModel::Pbr,
+        }
+    }
+}
+
+impl Default for ShadingModel {
+    fn default() -> Self {
+        ShadingModel::Pbr
+    }
+}
+
+/// Maps that hold colors are authored in srgb, the rest (normals, roughness, etc..) hold linear data
+pub fn is_srgb_texture(typ: TextureType) -> bool {
+    matches!(typ,
+        TextureType::Diffuse
+        | TextureType::BaseColor
+        | TextureType::Specular
+        | TextureType::Emissive
+        | TextureType::EmissionColor)
+}
+
+/// How the alpha of the base color is used, same as gltf's alphaMode
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AlphaMode {
+    Opaque,
+    /// fragments with an alpha below the cutoff are discarded
+    Mask,
+    Blend,
+}
+
+impl AlphaMode {
+    pub const LABELS: [&'static str; 3] = ["Opaque", "Mask", "Blend"];
+
+    pub fn index(&self) -> usize {
+        match self {
+            AlphaMode::Opaque => 0,
+            AlphaMode::Mask => 1,
+            AlphaMode::Blend => 2,
+        }
+    }
+
+    pub fn from_index(idx: usize) -> Self {
+        match idx {
+            0 => AlphaMode::Opaque,
+            1 => AlphaMode::Mask,
+            _ => AlphaMode::Blend,
+        }
+    }
+}
+
+/// assimp's aiBlendMode
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendMode {
+    Default,
+    Additive,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub name: String,
+
+    // phong
+    pub ambient: glm::Vec3,
+    pub diffuse: glm::Vec3,
+    pub specular: glm::Vec3,
+    pub shininess: f32,
+
+    // pbr metallic-roughness, the factors are multiplied with the maps if the mesh has them
+    pub base_color: glm::Vec4,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: glm::Vec3,
+    pub emissive_strength: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+
+    pub opacity: f32,
+    pub two_sided: bool,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub blend_mode: BlendMode,
+
+    /// files (or "*<index>" references to embedded textures) and the type they're used as
+    pub texture_files: Vec<(TextureType, String)>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            name: String::from("Default_Mat"),
+            ambient: glm::vec3(0.2, 0.2, 0.2),
+            diffuse: glm::vec3(0.7, 0.7, 0.7),
+            specular: glm::vec3(0.1, 0.1, 0.1),
+            shininess: 32.0,
+            base_color: glm::vec4(0.7, 0.7, 0.7, 1.0),
+            metallic: 0.0,
+            roughness: 1.0,
+            emissive: glm::vec3(0.0, 0.0, 0.0),
+            emissive_strength: 1.0,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            opacity: 1.0,
+            two_sided: false,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            blend_mode: BlendMode::Default,
+            texture_files: vec![],
+        }
+    }
+}
+
+/// Index into the model's images and the type the image is used as
+pub type ImageRef = (usize, TextureType);
+
+/// A material as it's read from the model file, its textures aren't uploaded yet
+#[derive(Clone, Debug, Default)]
+pub struct MaterialData {
+    pub material: Material,
+    pub textures: Vec<ImageRef>,
+}
+
+impl std::fmt::Display for Material {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "Ambient: {:?}\nDiffuse: {:?}\nSpecular: {:?}\nShininess: {}", self.ambient, self.diffuse, self.specular, self.shininess)?;
+        writeln!(f, "Base Color: {:?}\nMetallic: {}\nRoughness: {}\nEmissive: {:?} x {}\nNormal Scale: {}\nOcclusion Strength: {}",
+            self.base_color, self.metallic, self.roughness, self.emissive, self.emissive_strength, self.normal_scale, self.occlusion_strength)?;
+        write!(f, "Opacity: {}\nTwo Sided: {}\nAlpha Mode: {:?} ({})\nBlend Mode: {:?}",
+            self.opacity, self.two_sided, self.alpha_mode, self.alpha_cutoff, self.blend_mode)
+    }
+}
+
+/// The type a property value gets converted to before it's applied
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PropertyKind {
+    Color3,
+    Color4,
+    Float,
+    Int,
+    Bool,
+    String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PropertyValue {
+    Color3(glm::Vec3),
+    Color4(glm::Vec4),
+    Float(f32),
+    Int(i32),
+    Bool(bool),
+    String(String),
+}
+
+impl PropertyValue {
+    // values are always converted to the kind of their mapping, the fallbacks are never hit
+
+    fn vec3(self) -> glm::Vec3 {
+        match self {
+            PropertyValue::Color3(c) => c,
+            _ => glm::vec3(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn vec4(self) -> glm::Vec4 {
+        match self {
+            PropertyValue::Color4(c) => c,
+            _ => glm::vec4(0.0, 0.0, 0.0, 1.0),
+        }
+    }
+
+    fn float(self) -> f32 {
+        match self {
+            PropertyValue::Float(f) => f,
+            _ => 0.0,
+        }
+    }
+
+    fn int(self) -> i32 {
+        match self {
+            PropertyValue::Int(i) => i,
+            _ => 0,
+        }
+    }
+
+    fn bool(self) -> bool {
+        matches!(self, PropertyValue::Bool(true))
+    }
+
+    fn string(self) -> String {
+        match self {
+            PropertyValue::String(s) => s,
+            _ => String::new(),
+        }
+    }
+}
+
+/// Which texture semantic a property has to have to match a mapping
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Semantic {
+    /// plain material properties
+    None,
+    Any,
+    Only(TextureType),
+}
+
+pub struct PropertyMapping {
+    pub key: &'static str,
+    pub semantic: Semantic,
+    pub kind: PropertyKind,
+    pub apply: fn(&mut Material, PropertyValue, TextureType),
+}
+
+impl PropertyMapping {
+    pub fn matches(&self, key: &str, semantic: TextureType) -> bool {
+        self.key == key && match self.semantic {
+            Semantic::None => semantic == TextureType::None,
+            Semantic::Any => true,
+            Semantic::Only(typ) => semantic == typ,
+        }
+    }
+}
+
+/// Every assimp material property we know how to use.
+/// Newer assimp versions use the generic pbr keys, older ones the gltf specific ones
+pub const PROPERTIES: &[PropertyMapping] = &[
+    PropertyMapping { key: "?mat.name", semantic: Semantic::None, kind: PropertyKind::String, apply: |m, v, _| m.name = v.string() },
+    PropertyMapping { key: "$clr.ambient", semantic: Semantic::None, kind: PropertyKind::Color3, apply: |m, v, _| m.ambient = v.vec3() },
+    PropertyMapping { key: "$clr.diffuse", semantic: Semantic::None, kind: PropertyKind::Color3, apply: |m, v, _| m.diffuse = v.vec3() },
+    PropertyMapping { key: "$clr.specular", semantic: Semantic::None, kind: PropertyKind::Color3, apply: |m, v, _| m.specular = v.vec3() },
+    PropertyMapping { key: "$mat.shininess", semantic: Semantic::None, kind: PropertyKind::Float, apply: |m, v, _| m.shininess = v.float() },
+    PropertyMapping { key: "$clr.base", semantic: Semantic::None, kind: PropertyKind::Color4, apply: |m, v, _| m.base_color = v.vec4() },
+    PropertyMapping { key: "$mat.gltf.pbrMetallicRoughness.baseColorFactor", semantic: Semantic::None, kind: PropertyKind::Color4, apply: |m, v, _| m.base_color = v.vec4() },
+    PropertyMapping { key: "$mat.metallicFactor", semantic: Semantic::None, kind: PropertyKind::Float, apply: |m, v, _| m.metallic = v.float() },
+    PropertyMapping { key: "$mat.gltf.pbrMetallicRoughness.metallicFactor", semantic: Semantic::None, kind: PropertyKind::Float, apply: |m, v, _| m.metallic = v.float() },
+    PropertyMapping { key: "$mat.roughnessFactor", semantic: Semantic::None, kind: PropertyKind::Float, apply: |m, v, _| m.roughness = v.float() },
+    PropertyMapping { key: "$mat.gltf.pbrMetallicRoughness.roughnessFactor", semantic: Semantic::None, kind: PropertyKind::Float, apply: |m, v, _| m.roughness = v.float() },
+    PropertyMapping { key: "$clr.emissive", semantic: Semantic::None, kind: PropertyKind::Color3, apply: |m, v, _| m.emissive = v.vec3() },
+    PropertyMapping { key: "$mat.emissiveIntensity", semantic: Semantic::None, kind: PropertyKind::Float, apply: |m, v, _| m.emissive_strength = v.float() },
+    PropertyMapping { key: "$mat.opacity", semantic: Semantic::None, kind: PropertyKind::Float, apply: |m, v, _| m.opacity = v.float() },
+    PropertyMapping { key: "$mat.twosided", semantic: Semantic::None, kind: PropertyKind::Bool, apply: |m, v, _| m.two_sided = v.bool() },
+    PropertyMapping { key: "$mat.blend", semantic: Semantic::None, kind: PropertyKind::Int, apply: |m, v, _| {
+        m.blend_mode = match v.int() {
+            1 => BlendMode::Additive,
+            _ => BlendMode::Default,
+        };
+    }},
+    PropertyMapping { key: "$mat.gltf.alphaMode", semantic: Semantic::None, kind: PropertyKind::String, apply: |m, v, _| {
+        m.alpha_mode = match v.string().as_str() {
+            "MASK" => AlphaMode::Mask,
+            "BLEND" => AlphaMode::Blend,
+            _ => AlphaMode::Opaque,
+        };
+    }},
+    PropertyMapping { key: "$mat.gltf.alphaCutoff", semantic: Semantic::None, kind: PropertyKind::Float, apply: |m, v, _| m.alpha_cutoff = v.float() },
+    // gltf normalTexture.scale
+    PropertyMapping { key: "$tex.scale", semantic: Semantic::Only(TextureType::Normals), kind: PropertyKind::Float, apply: |m, v, _| m.normal_scale = v.float() },
+    // gltf occlusionTexture.strength, assimp puts the occlusion map under lightmap
+    PropertyMapping { key: "$tex.strength", semantic: Semantic::Only(TextureType::LightMap), kind: PropertyKind::Float, apply: |m, v, _| m.occlusion_strength = v.float() },
+    PropertyMapping { key: "$tex.file", semantic: Semantic::Any, kind: PropertyKind::String, apply: |m, v, typ| m.texture_files.push((typ, v.string())) },
+];
+
+/// Properties that only exist for pbr materials, the phong ones are approximated without them
+pub const BASE_COLOR_KEYS: [&str; 2] = ["$clr.base", "$mat.gltf.pbrMetallicRoughness.baseColorFactor"];
+
+pub fn find_mapping(key: &str, semantic: TextureType) -> Option<&'static PropertyMapping> {
+    PROPERTIES.iter().find(|mapping| mapping.matches(key, semantic))
+}
+
+/// Converts the raw property data to `kind`, `None` if the types aren't compatible
+pub fn convert(data: &PropertyTypeInfo, kind: PropertyKind) -> Option<PropertyValue> {
+    match kind {
+        PropertyKind::Color3 => floats(data)
+            .filter(|a| a.len() >= 3)
+            .map(|a| PropertyValue::Color3(glm::vec3(a[0], a[1], a[2]))),
+        // rgb colors get an alpha of 1
+        PropertyKind::Color4 => floats(data)
+            .filter(|a| a.len() >= 3)
+            .map(|a| PropertyValue::Color4(glm::vec4(a[0], a[1], a[2], a.get(3).copied().unwrap_or(1.0)))),
+        PropertyKind::Float => floats(data)
+            .and_then(|a| a.first().copied())
+            .map(PropertyValue::Float),
+        PropertyKind::Int => ints(data)
+            .and_then(|a| a.first().copied())
+            .map(PropertyValue::Int),
+        PropertyKind::Bool => ints(data)
+            .and_then(|a| a.first().copied())
+            .map(|i| PropertyValue::Bool(i != 0)),
+        PropertyKind::String => match data {
+            PropertyTypeInfo::String(s) => Some(PropertyValue::String(s.to_string())),
+            _ => None,
+        },
+    }
+}
+
+/// Name of the raw property type for warnings
+pub fn type_name(data: &PropertyTypeInfo) -> &'static str {
+    match data {
+        PropertyTypeInfo::Buffer(_) => "buffer",
+        PropertyTypeInfo::IntegerArray(_) => "integer array",
+        PropertyTypeInfo::FloatArray(_) => "float array",
+        PropertyTypeInfo::String(_) => "string",
+    }
+}
+
+// buffers are raw little endian data, assimp uses them for properties it doesn't have a type for
+
+fn floats(data: &PropertyTypeInfo) -> Option<Vec<f32>> {
+    match data {
+        PropertyTypeInfo::FloatArray(a) => Some(a.clone()),
+        PropertyTypeInfo::IntegerArray(a) => Some(a.iter().map(|&i| i as f32).collect()),
+        PropertyTypeInfo::Buffer(b) if !b.is_empty() && b.len() % 4 == 0 => {
+            Some(b.chunks_exact(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect())
+        },
+        _ => None,
+    }
+}
+
+fn ints(data: &PropertyTypeInfo) -> Option<Vec<i32>> {
+    match data {
+        PropertyTypeInfo::IntegerArray(a) => Some(a.clone()),
+        PropertyTypeInfo::FloatArray(a) => Some(a.iter().map(|&f| f.round() as i32).collect()),
+        PropertyTypeInfo::Buffer(b) if !b.is_empty() && b.len() % 4 == 0 => {
+            Some(b.chunks_exact(4).map(|c| i32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect())
+        },
+        PropertyTypeInfo::Buffer(b) if !b.is_empty() => Some(b.iter().map(|&i| i as i32).collect()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_property_data() {
+        assert_eq!(convert(&PropertyTypeInfo::FloatArray(vec![0.1, 0.2, 0.3]), PropertyKind::Color4), Some(PropertyValue::Color4(glm::vec4(0.1, 0.2, 0.3, 1.0))));
+        assert_eq!(convert(&PropertyTypeInfo::Buffer(0.5f32.to_le_bytes().to_vec()), PropertyKind::Float), Some(PropertyValue::Float(0.5)));
+        assert_eq!(convert(&PropertyTypeInfo::IntegerArray(vec![1]), PropertyKind::Bool), Some(PropertyValue::Bool(true)));
+        assert_eq!(convert(&PropertyTypeInfo::FloatArray(vec![0.1, 0.2]), PropertyKind::Color3), None);
+        assert_eq!(convert(&PropertyTypeInfo::FloatArray(vec![1.0]), PropertyKind::String), None);
+    }
+
+    #[test]
+    fn texture_properties_match_their_semantic() {
+        assert!(find_mapping("$tex.scale", TextureType::Normals).is_some());
+        assert!(find_mapping("$tex.scale", TextureType::Diffuse).is_none());
+        assert!(find_mapping("$tex.file", TextureType::Diffuse).is_some());
+        assert!(find_mapping("$clr.diffuse", TextureType::Diffuse).is_none());
+    }
+}
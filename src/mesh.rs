@@ -1,132 +1,168 @@
+use std::rc::Rc;
+
 use glad_gl::gl;
 use anyhow::Result;
 
-use crate::{shader::Shader, utils};
-
-fn decompose_mat(matrix: &mut glm::Mat4) -> (glm::Vec3, glm::Vec3, glm::Vec3) {
-    let pos = glm::vec3(matrix.c0.w, matrix.c1.w, matrix.c2.w);
-    matrix.c3.x = 0.0;
-    matrix.c3.y = 0.0;
-    matrix.c3.z = 0.0;
-
-    let scale_x = glm::length(glm::vec3(matrix.c0.x, matrix.c1.x, matrix.c2.x));
-    let scale_y = glm::length(glm::vec3(matrix.c0.y, matrix.c1.y, matrix.c2.y));
-    let scale_z = glm::length(glm::vec3(matrix.c0.z, matrix.c1.z, matrix.c2.z));
-    let scale = glm::vec3(scale_x, scale_y, scale_z);
-
-    let matrix = glm::mat3(
-        matrix[0][0] / scale_x, matrix[0][1] / scale_y, matrix[0][2] / scale_z,
-        matrix[1][0] / scale_x, matrix[1][1] / scale_y, matrix[1][2] / scale_z,
-        matrix[2][0] / scale_x, matrix[2][1] / scale_y, matrix[2][2] / scale_z,
-    );
-
-    // println!("original rot: {:#?}", matrix);
-
-    let mut pitch = 0.0;
-    let mut yaw = 0.0;
-    let mut roll = 0.0;
-
-    // source: https://www.geometrictools.com/Documentation/EulerAngles.pdf
-    if matrix[0][2] < 1.0 {
-        if matrix[0][2] > -1.0 {
-            pitch = (-matrix[1][2]).atan2(matrix[2][2]).to_degrees();
-            yaw = matrix[0][2].asin().to_degrees();
-            roll = (-matrix[0][1]).atan2(matrix[0][0]).to_degrees();
-        } else {
-            pitch = -(matrix[1][0].atan2(matrix[1][1])).to_degrees();
-            yaw = -(std::f32::consts::FRAC_PI_2).to_degrees();
-            roll = 0.0;
+use crate::{material::{Material, MaterialData, AlphaMode, BlendMode}, gl_object::{Buffer, GlTexture, VertexArray}, shader::Shader, utils};
+
+/// Has to match MAX_BONES in the vertex shader
+pub const MAX_BONES: usize = 128;
+
+/// Bones that affect a single vertex
+pub const MAX_BONE_INFLUENCE: usize = 4;
+
+/// Has to match MAX_MORPH_TARGETS in the vertex shader
+pub const MAX_MORPH_TARGETS: usize = 64;
+
+/// Unit the morph target buffer texture is bound to, it can't share a unit with the 2D samplers
+const MORPH_TEXTURE_UNIT: u32 = 31;
+
+fn calculate_bounds(vertices: &[Vertex]) -> (glm::Vec3, glm::Vec3) {
+    if vertices.is_empty() {
+        return (glm::vec3(0.0, 0.0, 0.0), glm::vec3(0.0, 0.0, 0.0));
+    }
+
+    let mut min = glm::vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+    let mut max = glm::vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+
+    for vertex in vertices {
+        let pos = vertex.position;
+        min = glm::min(min, pos);
+        max = glm::max(max, pos);
+    }
+
+    (min, max)
+}
+
+/// Fills in the tangents and bitangents of the vertices that don't have any from the triangles' uvs
+/// source: https://terathon.com/blog/tangent-space.html
+pub fn calculate_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    let zero = glm::vec3(0.0, 0.0, 0.0);
+    let mut tangents = vec![zero; vertices.len()];
+    let mut bitangents = vec![zero; vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+        if a >= vertices.len() || b >= vertices.len() || c >= vertices.len() {
+            continue;
+        }
+
+        let edge1 = vertices[b].position - vertices[a].position;
+        let edge2 = vertices[c].position - vertices[a].position;
+        let duv1 = vertices[b].tex_coords - vertices[a].tex_coords;
+        let duv2 = vertices[c].tex_coords - vertices[a].tex_coords;
+
+        let det = duv1.x * duv2.y - duv2.x * duv1.y;
+        // no uvs or a triangle that's collapsed in uv space
+        if det.abs() < f32::EPSILON {
+            continue;
+        }
+
+        let r = 1.0 / det;
+        let tangent = (edge1 * duv2.y - edge2 * duv1.y) * r;
+        let bitangent = (edge2 * duv1.x - edge1 * duv2.x) * r;
+
+        for i in [a, b, c] {
+            tangents[i] = tangents[i] + tangent;
+            bitangents[i] = bitangents[i] + bitangent;
         }
-    } else {
-        pitch = matrix[1][0].atan2(matrix[1][1]).to_degrees();
-        yaw = std::f32::consts::FRAC_PI_2.to_degrees();
-        roll = 0.0;
     }
-    // let pitch = matrix[2][1].atan2(matrix[2][2]).to_degrees();
-    // let yaw = (-matrix[2][0]).atan2((matrix[2][1].powi(2) + matrix[2][2].powi(2)).sqrt()).to_degrees();
-    // let roll = matrix[1][0].atan2(matrix[0][0]).to_degrees();
 
-    let rotation = glm::vec3(pitch, yaw, roll);
+    for (i, vertex) in vertices.iter_mut().enumerate() {
+        if vertex.has_tangents() {
+            continue;
+        }
+
+        let normal = vertex.normal;
+        if glm::length(normal) == 0.0 {
+            continue;
+        }
+        let normal = glm::normalize(normal);
+
+        // gram-schmidt orthogonalize against the normal
+        let mut tangent = tangents[i] - normal * glm::dot(normal, tangents[i]);
+        if glm::length(tangent) < f32::EPSILON {
+            // any direction on the surface is as good as another without uvs
+            let axis = if normal.x.abs() < 0.9 { glm::vec3(1.0, 0.0, 0.0) } else { glm::vec3(0.0, 1.0, 0.0) };
+            tangent = axis - normal * glm::dot(normal, axis);
+        }
+        let tangent = glm::normalize(tangent);
 
-    println!("pos: {:#?}", pos);
-    println!("rot: {:#?}", rotation);
-    println!("scale: {:#?}", scale);
+        // mirrored uvs flip the bitangent
+        let mut bitangent = glm::cross(normal, tangent);
+        if glm::dot(bitangent, bitangents[i]) < 0.0 {
+            bitangent = bitangent * -1.0;
+        }
 
-    (pos, rotation, scale)
+        vertex.tangent = tangent;
+        vertex.bitangent = bitangent;
+    }
 }
 
-fn create_rotation_matrix(pitch: f32, yaw: f32, roll: f32) -> glm::Mat3 {
-    let pitch = pitch.to_radians();
-    let yaw = yaw.to_radians();
-    let roll = roll.to_radians();
-
-    let pitch_matrix = glm::mat3(
-        1.0, 0.0, 0.0,
-        0.0, pitch.cos(), -pitch.sin(),
-        0.0, pitch.sin(), pitch.cos()
-    );
-
-    let yaw_matrix = glm::mat3(
-        yaw.cos(), 0.0, yaw.sin(),
-        0.0, 1.0, 0.0,
-        -yaw.sin(), 0.0, yaw.cos()
-    );
-
-    let roll_matrix = glm::mat3(
-        roll.cos(), -roll.sin(), 0.0,
-        roll.sin(), roll.cos(), 0.0,
-        0.0, 0.0, 1.0
-    );
-
-    pitch_matrix * yaw_matrix * roll_matrix
+/// A mesh as it's read from the model file, before anything is uploaded to the gpu
+#[derive(Debug)]
+pub struct MeshData {
+    pub name: String,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    /// index into the model's materials
+    pub material: usize,
+    pub bones: Vec<Bone>,
+    pub morph_targets: Vec<MorphTarget>,
 }
 
-fn apply_rotation(matrix: &glm::Mat4, rot: glm::Vec3) -> glm::Mat4 {
-    let mut temp = utils::mat_ident();
-    let rot = create_rotation_matrix(rot.x, rot.y, rot.z);
-    // println!("created rot: {:#?}", rot);
-    // panic!("used to stop print spam lol")
+impl MeshData {
+    /// `textures` are the uploaded images of the model, in the same order
+    pub fn upload(self, material: &MaterialData, textures: &[Texture]) -> Mesh {
+        let textures = material.textures.iter()
+            .filter_map(|&(image, typ)| textures.get(image).map(|texture| Texture { typ, ..texture.clone() }))
+            .collect();
 
-    for i in 0..3 {
-        for j in 0..3 {
-            temp[i][j] = rot[j][i];
-        }
+        Mesh::new(&self.name, self.vertices, self.indices, textures, material.material.clone(), self.bones, self.morph_targets)
     }
-    return temp * *matrix;
 }
 
 #[derive(Debug)]
 pub struct Mesh {
     pub name: String,
-    pub position: glm::Vec3,
-    pub rotation: glm::Vec3,
-    pub scale: glm::Vec3,
 
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
     pub textures: Vec<Texture>,
     pub material: Material,
-
-    vao: u32,
+    /// as it was in the file, projects only save the materials that were changed since
+    pub imported_material: Material,
+    pub bones: Vec<Bone>,
+    pub morph_targets: Vec<MorphTarget>,
+    /// weights set in the ui, one per morph target
+    pub morph_weights: Vec<f32>,
+    /// weights from the current animation frame, used instead of `morph_weights` while set
+    pub morph_pose: Option<Vec<f32>>,
+    pub aabb_min: glm::Vec3,
+    pub aabb_max: glm::Vec3,
+
+    vao: VertexArray,
+    // only owned so they're deleted with the mesh, the vao references them
+    _vbo: Buffer,
+    _ebo: Buffer,
+    /// buffer of the morph target deltas and the texture it's read through
+    morph: Option<(Buffer, GlTexture)>,
 }
 
 impl Mesh {
-    pub fn new(name: &str, vertices: Vec<Vertex>, indices: Vec<u32>, textures: Vec<Texture>, material: Material, transformation: &mut glm::Mat4) -> Mesh {
-        let mut vao = 0;
-        let mut vbo = 0;
-        let mut ebo = 0;
+    pub fn new(name: &str, vertices: Vec<Vertex>, indices: Vec<u32>, textures: Vec<Texture>, material: Material, bones: Vec<Bone>, morph_targets: Vec<MorphTarget>) -> Mesh {
+        let vao = VertexArray::new();
+        let vbo = Buffer::new();
+        let ebo = Buffer::new();
+        let mut morph = None;
 
         unsafe {
-            gl::GenVertexArrays(1, &mut vao);
-            gl::GenBuffers(1, &mut vbo);
-            gl::GenBuffers(1, &mut ebo);
-
-            gl::BindVertexArray(vao);
-            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl::BindVertexArray(vao.id());
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo.id());
 
             gl::BufferData(gl::ARRAY_BUFFER, (std::mem::size_of::<Vertex>() * vertices.len() as usize) as isize, vertices.as_ptr() as *const std::ffi::c_void, gl::STATIC_DRAW);
 
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo.id());
             gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, (std::mem::size_of::<u32>() * indices.len() as usize) as isize, indices.as_ptr() as *const std::ffi::c_void, gl::STATIC_DRAW);
 
             // vertex positions
@@ -141,35 +177,107 @@ impl Mesh {
             gl::EnableVertexAttribArray(2);
             gl::VertexAttribPointer(2, 2, gl::FLOAT, gl::FALSE, std::mem::size_of::<Vertex>() as i32, (6 * std::mem::size_of::<f32>()) as *const std::ffi::c_void);
 
+            // bone indices
+            gl::EnableVertexAttribArray(3);
+            gl::VertexAttribIPointer(3, MAX_BONE_INFLUENCE as i32, gl::INT, std::mem::size_of::<Vertex>() as i32, (14 * std::mem::size_of::<f32>()) as *const std::ffi::c_void);
+
+            // bone weights
+            gl::EnableVertexAttribArray(4);
+            gl::VertexAttribPointer(4, MAX_BONE_INFLUENCE as i32, gl::FLOAT, gl::FALSE, std::mem::size_of::<Vertex>() as i32, ((14 + MAX_BONE_INFLUENCE) * std::mem::size_of::<f32>()) as *const std::ffi::c_void);
+
+            // vertex tangents
+            gl::EnableVertexAttribArray(5);
+            gl::VertexAttribPointer(5, 3, gl::FLOAT, gl::FALSE, std::mem::size_of::<Vertex>() as i32, (8 * std::mem::size_of::<f32>()) as *const std::ffi::c_void);
+
+            // vertex bitangents
+            gl::EnableVertexAttribArray(6);
+            gl::VertexAttribPointer(6, 3, gl::FLOAT, gl::FALSE, std::mem::size_of::<Vertex>() as i32, (11 * std::mem::size_of::<f32>()) as *const std::ffi::c_void);
+
             gl::BindVertexArray(0);
+
+            // position and normal deltas of every vertex, one target after the other
+            if !morph_targets.is_empty() {
+                let mut deltas: Vec<f32> = Vec::with_capacity(morph_targets.len() * vertices.len() * 8);
+                let zero = glm::vec3(0.0, 0.0, 0.0);
+
+                for target in &morph_targets {
+                    for i in 0..vertices.len() {
+                        let position = target.position_deltas.get(i).copied().unwrap_or(zero);
+                        let normal = target.normal_deltas.get(i).copied().unwrap_or(zero);
+                        // rgba because rgb buffer textures need gl 4.0
+                        deltas.extend_from_slice(&[position.x, position.y, position.z, 0.0, normal.x, normal.y, normal.z, 0.0]);
+                    }
+                }
+
+                let morph_buffer = Buffer::new();
+                gl::BindBuffer(gl::TEXTURE_BUFFER, morph_buffer.id());
+                gl::BufferData(gl::TEXTURE_BUFFER, (std::mem::size_of::<f32>() * deltas.len()) as isize, deltas.as_ptr() as *const std::ffi::c_void, gl::STATIC_DRAW);
+
+                let morph_texture = GlTexture::new();
+                gl::BindTexture(gl::TEXTURE_BUFFER, morph_texture.id());
+                gl::TexBuffer(gl::TEXTURE_BUFFER, gl::RGBA32F, morph_buffer.id());
+
+                gl::BindTexture(gl::TEXTURE_BUFFER, 0);
+                gl::BindBuffer(gl::TEXTURE_BUFFER, 0);
+
+                morph = Some((morph_buffer, morph_texture));
+            }
         }
 
-        let (position, rotation, scale) = decompose_mat(transformation);
+        let (aabb_min, aabb_max) = calculate_bounds(&vertices);
+        let morph_weights = morph_targets.iter().map(|target| target.default_weight).collect();
 
         Mesh {
             name: name.to_string(),
             vertices,
             indices,
             textures,
+            imported_material: material.clone(),
             material,
+            bones,
+            morph_targets,
+            morph_weights,
+            morph_pose: None,
+            aabb_min,
+            aabb_max,
             vao,
-            position,
-            rotation,
-            scale,
+            _vbo: vbo,
+            _ebo: ebo,
+            morph,
         }
     }
 
-    pub fn draw(&self, shader: &Shader) {
-        let mut diffuse = 1;
-        let mut specular = 1;
+    /// `bone_matrices` are the skinning matrices of `self.bones`, empty for meshes that aren't skinned
+    pub fn draw(&self, shader: &Shader, model_mat: &glm::Mat4, bone_matrices: &[glm::Mat4]) {
+        let mut diffuse = 0;
+        let mut specular = 0;
+        let mut bound_maps = [false; PBR_MAPS.len()];
 
         shader.use_shader();
 
-        let model_mat = glm::ext::scale(&utils::mat_ident(), self.scale);
-        let model_mat = apply_rotation(&model_mat, self.rotation);
-        let model_mat = glm::ext::translate(&model_mat, self.position);
-        shader.set_mat4fv("model", &model_mat);
+        shader.set_mat4fv("model", model_mat);
+
+        shader.set_bool("skinned", !bone_matrices.is_empty());
+        if !bone_matrices.is_empty() {
+            shader.set_mat4fv_array("bones", &bone_matrices[..bone_matrices.len().min(MAX_BONES)]);
+        }
+
+        let morph_weights = self.morph_pose.as_ref().unwrap_or(&self.morph_weights);
+        let morph_target_count = morph_weights.len().min(self.morph_targets.len()).min(MAX_MORPH_TARGETS);
+        // always set so the buffer sampler never shares a unit with a 2D sampler
+        shader.set_int("morphTargets", MORPH_TEXTURE_UNIT as i32);
+        shader.set_int("morphTargetCount", morph_target_count as i32);
+        if let (true, Some((_, morph_texture))) = (morph_target_count > 0, &self.morph) {
+            shader.set_int("morphVertexCount", self.vertices.len() as i32);
+            shader.set_float_array("morphWeights", &morph_weights[..morph_target_count]);
+
+            unsafe {
+                gl::ActiveTexture(gl::TEXTURE0 + MORPH_TEXTURE_UNIT);
+                gl::BindTexture(gl::TEXTURE_BUFFER, morph_texture.id());
+            }
+        }
 
+        // phong
         shader.set_3fv("material.ambient", self.material.ambient);
         shader.set_3fv("material.diffuse", self.material.diffuse);
         shader.set_3fv("material.specular", self.material.specular);
@@ -177,44 +285,106 @@ impl Mesh {
         // the model to be black or rather cause the light to just be absorbed and barely reflected)
         shader.set_float("material.shininess", 32.0);
 
+        // pbr
+        shader.set_4fv("material.baseColor", self.material.base_color);
+        shader.set_float("material.metallic", self.material.metallic);
+        shader.set_float("material.roughness", self.material.roughness);
+        shader.set_3fv("material.emissive", self.material.emissive * self.material.emissive_strength);
+        shader.set_float("material.normalScale", self.material.normal_scale);
+        shader.set_float("material.occlusionStrength", self.material.occlusion_strength);
+        shader.set_float("material.opacity", self.material.opacity);
+        shader.set_bool("material.twoSided", self.material.two_sided);
+        shader.set_int("material.alphaMode", match self.material.alpha_mode {
+            AlphaMode::Opaque => 0,
+            AlphaMode::Mask => 1,
+            AlphaMode::Blend => 2,
+        });
+        shader.set_float("material.alphaCutoff", self.material.alpha_cutoff);
+
         for i in 0..self.textures.len() {
             unsafe {
                 gl::ActiveTexture(gl::TEXTURE0 + i as u32);
-                match self.textures[i].typ {
-                    russimp::material::TextureType::Diffuse => {
-                        diffuse += 1;
-                        shader.set_int(format!("material.texture_diffuse{}", diffuse).as_str(), i as i32);
-                    },
-                    russimp::material::TextureType::Specular => {
-                        specular += 1;
-                        shader.set_int(format!("material.texture_specular{}", specular).as_str(), i as i32);
-                    },
-                    _ => {}, // don't do anything because unsupported texture types are logged
-                             // when the model is loaded
-                }
+                gl::BindTexture(gl::TEXTURE_2D, self.textures[i].gl.id());
+            }
 
-                gl::BindTexture(gl::TEXTURE_2D, self.textures[i].id);
+            match self.textures[i].typ {
+                russimp::material::TextureType::Diffuse | russimp::material::TextureType::BaseColor => {
+                    diffuse += 1;
+                    shader.set_int(format!("material.texture_diffuse{}", diffuse).as_str(), i as i32);
+                },
+                russimp::material::TextureType::Specular => {
+                    specular += 1;
+                    shader.set_int(format!("material.texture_specular{}", specular).as_str(), i as i32);
+                },
+                _ => {},
             }
+
+            // the same map can show up under multiple types (e.g. diffuse and base color), the first one wins
+            if let Some(map) = pbr_map(self.textures[i].typ) {
+                if !bound_maps[map] {
+                    bound_maps[map] = true;
+                    shader.set_int(PBR_MAPS[map].0, i as i32);
+                }
+            }
+        }
+
+        for (map, &bound) in bound_maps.iter().enumerate() {
+            shader.set_bool(PBR_MAPS[map].1, bound);
         }
 
         unsafe {
+            if self.material.blend_mode == BlendMode::Additive {
+                gl::BlendFunc(gl::SRC_ALPHA, gl::ONE);
+            }
+
             // draw Mesh
-            gl::BindVertexArray(self.vao);
+            gl::BindVertexArray(self.vao.id());
             gl::DrawElements(gl::TRIANGLES, self.indices.len() as i32, gl::UNSIGNED_INT, std::ptr::null());
 
             // reset stuff to default
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
             gl::ActiveTexture(gl::TEXTURE0);
             gl::BindVertexArray(0);
         }
     }
 }
 
+/// Sampler and "has map" uniforms of the pbr shader
+const PBR_MAPS: [(&str, &str); 5] = [
+    ("material.baseColorMap", "material.hasBaseColorMap"),
+    ("material.metallicRoughnessMap", "material.hasMetallicRoughnessMap"),
+    ("material.normalMap", "material.hasNormalMap"),
+    ("material.occlusionMap", "material.hasOcclusionMap"),
+    ("material.emissiveMap", "material.hasEmissiveMap"),
+];
+
+/// Index into `PBR_MAPS` for the texture types assimp uses for gltf materials
+pub(crate) fn pbr_map(typ: russimp::material::TextureType) -> Option<usize> {
+    use russimp::material::TextureType;
+
+    match typ {
+        TextureType::BaseColor | TextureType::Diffuse => Some(0),
+        // gltf's metallicRoughness texture is stored under all three
+        TextureType::Metalness | TextureType::Roughness | TextureType::Unknown => Some(1),
+        // obj's bump maps end up as height maps, the ones that are actual heights are turned into normal maps when they're loaded
+        TextureType::Normals | TextureType::Height => Some(2),
+        TextureType::AmbientOcclusion | TextureType::LightMap => Some(3),
+        TextureType::EmissionColor | TextureType::Emissive => Some(4),
+        _ => None,
+    }
+}
+
 #[derive(Clone, Debug)]
 #[repr(packed(2))]
 pub struct Vertex {
     pub position: glm::Vec3,
     pub normal: glm::Vec3,
     pub tex_coords: glm::Vec2,
+    pub tangent: glm::Vec3,
+    pub bitangent: glm::Vec3,
+    /// indices into the mesh's bones
+    pub bone_ids: [i32; MAX_BONE_INFLUENCE],
+    pub bone_weights: [f32; MAX_BONE_INFLUENCE],
 }
 
 impl Vertex {
@@ -222,57 +392,182 @@ impl Vertex {
         Vertex {
             position,
             normal,
-            tex_coords
+            tex_coords,
+            tangent: glm::vec3(0.0, 0.0, 0.0),
+            bitangent: glm::vec3(0.0, 0.0, 0.0),
+            bone_ids: [0; MAX_BONE_INFLUENCE],
+            bone_weights: [0.0; MAX_BONE_INFLUENCE],
+        }
+    }
+
+    /// assimp leaves the tangents of vertices it couldn't calculate them for as NaN
+    pub fn has_tangents(&self) -> bool {
+        let tangent = self.tangent;
+        let finite = tangent.x.is_finite() && tangent.y.is_finite() && tangent.z.is_finite();
+        finite && (tangent.x != 0.0 || tangent.y != 0.0 || tangent.z != 0.0)
+    }
+
+    /// Keeps the `MAX_BONE_INFLUENCE` strongest bones
+    pub fn add_bone_weight(&mut self, bone: i32, weight: f32) {
+        // copies because references to fields of packed structs aren't allowed
+        let mut ids = self.bone_ids;
+        let mut weights = self.bone_weights;
+
+        let mut weakest = 0;
+        for i in 1..MAX_BONE_INFLUENCE {
+            if weights[i] < weights[weakest] {
+                weakest = i;
+            }
+        }
+
+        if weight > weights[weakest] {
+            ids[weakest] = bone;
+            weights[weakest] = weight;
+        }
+
+        self.bone_ids = ids;
+        self.bone_weights = weights;
+    }
+
+    /// Makes the weights add up to 1 in case some were dropped
+    pub fn normalize_bone_weights(&mut self) {
+        let mut weights = self.bone_weights;
+        let total: f32 = weights.iter().sum();
+
+        if total > 0.0 {
+            for weight in weights.iter_mut() {
+                *weight /= total;
+            }
         }
+
+        self.bone_weights = weights;
     }
 }
 
+/// Blend shape stored as offsets from the mesh's vertices
+#[derive(Clone, Debug)]
+pub struct MorphTarget {
+    pub name: String,
+    pub position_deltas: Vec<glm::Vec3>,
+    /// empty if the target doesn't change the normals
+    pub normal_deltas: Vec<glm::Vec3>,
+    pub default_weight: f32,
+}
+
+/// A node of the model that deforms a skinned mesh
+#[derive(Clone, Debug)]
+pub struct Bone {
+    pub name: String,
+    /// index of the node that drives the bone, `None` if the skeleton doesn't have it
+    pub node: Option<usize>,
+    /// transforms from mesh space to the bone's space in bind pose
+    pub offset: glm::Mat4,
+}
+
+/// A texture as a material uses it, the gl texture is shared by every material that uses the same file
 #[derive(Clone, Debug)]
 pub struct Texture {
-    pub id: u32,
+    pub gl: Rc<GlTexture>,
     pub typ: russimp::material::TextureType,
     pub path: std::path::PathBuf,
 }
 
-impl Texture {
-    pub fn new(path: std::path::PathBuf, typ: russimp::material::TextureType) -> Result<Self, Box<dyn std::error::Error>> {
+/// A decoded image file, shared by every material that references its path
+pub struct ImageData {
+    pub path: std::path::PathBuf,
+    pub image: image::DynamicImage,
+    /// set when a material uses the image as a color map, so it's decoded to linear when sampled.
+    /// an image that's used both ways is there twice
+    pub srgb: bool,
+    /// the image is a normal map made from the file's grayscale bump map, not the file's pixels
+    pub from_heights: bool,
+}
+
+impl ImageData {
+    pub fn load(path: std::path::PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
         let path_str = match path.to_str() {
             Some(path) => path,
             None => return Err("Failed to convert texture path to string".into()),
         };
-        let id = utils::load_texture(path_str)?;
+        let image = utils::decode_texture(path_str)?;
 
-        Ok(Texture {
-            id,
-            typ,
+        Ok(ImageData {
             path,
+            image,
+            srgb: false,
+            from_heights: false,
         })
     }
-}
 
-#[derive(Debug)]
-pub struct Material {
-    pub name: String,
-    pub ambient: glm::Vec3,
-    pub diffuse: glm::Vec3,
-    pub specular: glm::Vec3,
-    pub shininess: f32,
+    /// Needs a gl context, the type is set by the materials that use the texture
+    pub fn upload(&self) -> Texture {
+        Texture {
+            gl: Rc::new(utils::upload_texture(&self.image, self.srgb)),
+            typ: russimp::material::TextureType::None,
+            path: self.path.clone(),
+        }
+    }
 }
 
-impl Material {
-    pub fn new(name: String, ambient: glm::Vec3, diffuse: glm::Vec3, specular: glm::Vec3, shininess: f32) -> Self {
-        Material {
-            name,
-            ambient,
-            diffuse,
-            specular,
-            shininess,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_vec_eq(a: glm::Vec3, b: glm::Vec3) {
+        assert!(glm::length(a - b) < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    /// A unit quad facing +z with its uvs along x and y, `mirror` flips the u coordinates
+    fn quad(mirror: bool) -> Vec<Vertex> {
+        let normal = glm::vec3(0.0, 0.0, 1.0);
+        [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)].iter()
+            .map(|&(x, y)| Vertex::new(glm::vec3(x, y, 0.0), normal, glm::vec2(if mirror { 1.0 - x } else { x }, y)))
+            .collect()
+    }
+
+    #[test]
+    fn tangents_follow_the_uvs() {
+        let mut vertices = quad(false);
+        calculate_tangents(&mut vertices, &[0, 1, 2, 0, 2, 3]);
+
+        for vertex in &vertices {
+            let (tangent, bitangent) = (vertex.tangent, vertex.bitangent);
+            assert_vec_eq(tangent, glm::vec3(1.0, 0.0, 0.0));
+            assert_vec_eq(bitangent, glm::vec3(0.0, 1.0, 0.0));
         }
     }
-}
 
-impl std::fmt::Display for Material {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Ambient: {:?}\nDiffuse: {:?}\nSpecular: {:?}\nShininess: {}", self.ambient, self.diffuse, self.specular, self.shininess)
+    #[test]
+    fn mirrored_uvs_flip_the_tangent() {
+        let mut vertices = quad(true);
+        calculate_tangents(&mut vertices, &[0, 1, 2, 0, 2, 3]);
+
+        for vertex in &vertices {
+            let (tangent, bitangent) = (vertex.tangent, vertex.bitangent);
+            assert_vec_eq(tangent, glm::vec3(-1.0, 0.0, 0.0));
+            assert_vec_eq(bitangent, glm::vec3(0.0, 1.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn existing_tangents_are_kept() {
+        let mut vertices = quad(false);
+        vertices[0].tangent = glm::vec3(0.0, 1.0, 0.0);
+        calculate_tangents(&mut vertices, &[0, 1, 2, 0, 2, 3]);
+
+        let tangent = vertices[0].tangent;
+        assert_vec_eq(tangent, glm::vec3(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn images_decode_without_a_gl_context() {
+        let path = std::env::temp_dir().join("images_decode_without_a_gl_context.png");
+        image::RgbaImage::from_pixel(2, 3, image::Rgba([255, 0, 0, 255])).save(&path).unwrap();
+
+        let data = ImageData::load(path.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(data.path, path);
+        assert_eq!((data.image.width(), data.image.height()), (2, 3));
     }
 }
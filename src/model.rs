@@ -1,70 +1,78 @@
-use crate::{mesh::{Mesh, Vertex, Texture, Material}, shader::Shader, utils, ui::ui, log};
+use crate::{mesh::{self, ImageData, Mesh, MeshData, Vertex, Texture, Bone, MorphTarget}, material::{self, ImageRef, Material, MaterialData}, node::{Node, Pivot}, transform::{Quat, Transform}, animation::{Animator, Channel, Clip, Keyframe, MorphChannel}, light::Light, import::{AxisConversion, ImportSettings, Progress}, shader::Shader, utils, log::{Log, LogLevel, LogMessage}};
+use glm::GenSquareMat;
 use russimp;
 use anyhow::{Result, anyhow};
+use std::{collections::HashMap, path::PathBuf};
 
-const SUPPORTED_TEXTURE_TYPES: [russimp::material::TextureType; 2] = [
+const SUPPORTED_TEXTURE_TYPES: [russimp::material::TextureType; 12] = [
     russimp::material::TextureType::Diffuse,
     russimp::material::TextureType::Specular,
+    russimp::material::TextureType::BaseColor,
+    russimp::material::TextureType::Metalness,
+    russimp::material::TextureType::Roughness,
+    russimp::material::TextureType::Unknown,
+    russimp::material::TextureType::Normals,
+    russimp::material::TextureType::Height,
+    russimp::material::TextureType::LightMap,
+    russimp::material::TextureType::AmbientOcclusion,
+    russimp::material::TextureType::Emissive,
+    russimp::material::TextureType::EmissionColor,
 ];
 
 #[derive(Debug)]
 pub struct Model {
     pub name: String,
+    pub nodes: Vec<Node>,
     pub meshes: Vec<Mesh>,
+    pub clips: Vec<Clip>,
+    pub animator: Animator,
+    /// applied above the root node, can be changed in the ui when the file's metadata is wrong
+    pub axis_conversion: AxisConversion,
+    pub imported_axis_conversion: AxisConversion,
+    /// lights from the file in world space, they're moved into the scene's lights once the model is added
+    pub imported_lights: Vec<Light>,
+    /// file the model was imported from and how, so projects can import it again
+    pub path: PathBuf,
+    pub import_settings: ImportSettings,
 }
 
-fn process_node<'a>(
-    node: &russimp::node::Node,
-    scene: &'a russimp::scene::Scene,
-    meshes: &mut Vec<Mesh>,
-    dir: &std::path::PathBuf,
-    loaded_textures: &mut Vec<Texture>,
-    init_trans: &glm::Mat4,
-) -> Vec<Box<dyn std::error::Error>> {
-    let mut errors = vec![];
+/// Parent of a node, its position among the parent's children and its local transform
+pub type Placement = (usize, usize, Transform);
 
-    let node_trans = glm::mat4(
-        node.transformation.a1, node.transformation.a2, node.transformation.a3, node.transformation.a4,
-        node.transformation.b1, node.transformation.b2, node.transformation.b3, node.transformation.b4,
-        node.transformation.c1, node.transformation.c2, node.transformation.c3, node.transformation.c4,
-        node.transformation.d1, node.transformation.d2, node.transformation.d3, node.transformation.d4,
-    );
-    let mut new_trans = *init_trans * node_trans;
+fn process_node(
+    node: &russimp::node::Node,
+    parent: Option<usize>,
+    nodes: &mut Vec<Node>,
+) -> usize {
+    let idx = nodes.len();
 
     println!("node: {}", node.name);
-    // println!("{:#?}", node_trans);
     // println!("{:#?}", node.metadata);
     println!("{:#?}", node.transformation);
 
-    for i in 0..node.meshes.len() {
-        let mesh = &scene.meshes[node.meshes[i] as usize];
-        let (processed_mesh, mut errs) = process_mesh(mesh, scene, dir, loaded_textures, &mut new_trans);
-        errors.append(&mut errs);
-        meshes.push(processed_mesh);
-    }
+    let mut processed_node = Node::new(node.name.as_str(), Transform::from(&node.transformation), parent);
+    processed_node.mesh_indices = node.meshes.iter().map(|&i| i as usize).collect();
+    nodes.push(processed_node);
 
-    for child in node.children.borrow().clone().into_iter() {
-        let mut errs = process_node(&child, scene, meshes, dir, loaded_textures, &new_trans);
-        errors.append(&mut errs);
+    for child in node.children.borrow().iter() {
+        let child_idx = process_node(child, Some(idx), nodes);
+        nodes[idx].children.push(child_idx);
     }
 
-    return errors;
+    return idx;
 }
 
 fn process_mesh(
     mesh: &russimp::mesh::Mesh,
-    scene: &russimp::scene::Scene,
-    dir: &std::path::PathBuf,
-    loaded_textures: &mut Vec<Texture>,
-    transformation: &mut glm::Mat4,
-) -> (Mesh, Vec<Box<dyn std::error::Error>>) {
+    nodes: &[Node],
+    morph_targets: Vec<MorphTarget>,
+) -> (MeshData, Vec<Box<dyn std::error::Error>>) {
     let mut vertices = vec![];
     let mut indices = vec![];
-    let mut textures = vec![];
+    let mut errs = vec![];
 
     for i in 0..mesh.vertices.len() {
-        let pos = glm::vec4(mesh.vertices[i].x, mesh.vertices[i].y, mesh.vertices[i].z, 1.0);
-        // let pos = *transformation * pos;
+        let pos = glm::vec3(mesh.vertices[i].x, mesh.vertices[i].y, mesh.vertices[i].z);
 
         let norm = match mesh.normals.len() {
             0 => glm::vec3(0.0, 0.0, 0.0),
@@ -79,7 +87,13 @@ fn process_mesh(
             None => glm::vec2(0.0, 0.0)
         };
 
-        vertices.push(Vertex::new(pos.truncate(3), norm, tex_coords));
+        let mut vertex = Vertex::new(pos, norm, tex_coords);
+        if let (Some(tangent), Some(bitangent)) = (mesh.tangents.get(i), mesh.bitangents.get(i)) {
+            vertex.tangent = glm::vec3(tangent.x, tangent.y, tangent.z);
+            vertex.bitangent = glm::vec3(bitangent.x, bitangent.y, bitangent.z);
+        }
+
+        vertices.push(vertex);
     }
 
     println!("mesh: {}", mesh.name);
@@ -90,134 +104,529 @@ fn process_mesh(
         }
     }
 
-    // println!("material count: {}", scene.materials.len());
-    // println!("material index: {}", mesh.material_index);
-    let mat = &scene.materials[mesh.material_index as usize];
-    // println!("{:?}", mat);
+    // assimp can't calculate tangents for meshes without normals or uvs and skips degenerate triangles
+    if vertices.iter().any(|vertex| !vertex.has_tangents()) {
+        mesh::calculate_tangents(&mut vertices, &indices);
+    }
 
-    let material = process_material(mat);
+    let bones = process_bones(mesh, nodes, &mut vertices, &mut errs);
 
-    let (mut found_textures, errs) = load_material_textures(mat, dir, loaded_textures);
-    textures.append(&mut found_textures);
+    if morph_targets.len() > mesh::MAX_MORPH_TARGETS {
+        errs.push(anyhow!("Mesh {} has {} morph targets, only the first {} are used", mesh.name, morph_targets.len(), mesh::MAX_MORPH_TARGETS).into());
+    }
 
-    let mesh = Mesh::new(mesh.name.as_str(), vertices, indices, textures, material, transformation);
+    let mesh = MeshData {
+        name: mesh.name.to_owned(),
+        vertices,
+        indices,
+        material: mesh.material_index as usize,
+        bones,
+        morph_targets,
+    };
     return (mesh, errs);
 }
 
-fn process_material(mat: &russimp::material::Material) -> Material {
-    let mut mat_name = String::from("Default_Mat");
-    let mut ambient = glm::vec3(0.2, 0.2, 0.2);
-    let mut diffuse = glm::vec3(0.7, 0.7, 0.7);
-    let mut specular = glm::vec3(0.1, 0.1, 0.1);
-    let mut shininess = 32.0;
+/// Resolves the mesh's bones to the nodes that drive them and stores the weights in the vertices
+fn process_bones(
+    mesh: &russimp::mesh::Mesh,
+    nodes: &[Node],
+    vertices: &mut [Vertex],
+    errors: &mut Vec<Box<dyn std::error::Error>>,
+) -> Vec<Bone> {
+    if mesh.bones.len() > mesh::MAX_BONES {
+        errors.push(anyhow!("Mesh {} has {} bones, only the first {} are used", mesh.name, mesh.bones.len(), mesh::MAX_BONES).into());
+    }
 
-    // TODO: better way of mapping properties
-    for property in mat.properties.iter() {
-        match property.key.as_str() {
-            "$clr.ambient" => {
-                ambient = match &property.data {
-                    russimp::material::PropertyTypeInfo::FloatArray(a) => {
-                        glm::vec3(a[0], a[1], a[2])
-                    },
-                    _ => panic!("Property should not be this type: {}", property.key)
-                };
-            },
-            "$clr.diffuse" => {
-                diffuse = match &property.data {
-                    russimp::material::PropertyTypeInfo::FloatArray(a) => {
-                        glm::vec3(a[0], a[1], a[2])
-                    },
-                    _ => panic!("Property should not be this type: {}", property.key)
-                };
+    let mut bones = vec![];
+
+    for (i, bone) in mesh.bones.iter().take(mesh::MAX_BONES).enumerate() {
+        let node = nodes.iter().position(|node| node.name == bone.name);
+        if node.is_none() {
+            errors.push(anyhow!("Bone {} of mesh {} has no node in the skeleton", bone.name, mesh.name).into());
+        }
+
+        for weight in &bone.weights {
+            if let Some(vertex) = vertices.get_mut(weight.vertex_id as usize) {
+                vertex.add_bone_weight(i as i32, weight.weight);
+            }
+        }
+
+        bones.push(Bone {
+            name: bone.name.to_owned(),
+            node,
+            offset: utils::mat4_from_assimp(&bone.offset_matrix),
+        });
+    }
+
+    if !bones.is_empty() {
+        for vertex in vertices.iter_mut() {
+            vertex.normalize_bone_weights();
+        }
+    }
+
+    bones
+}
+
+fn process_animation(
+    anim: &russimp::animation::Animation,
+    nodes: &[Node],
+    meshes: &[MeshData],
+) -> (Clip, Vec<Box<dyn std::error::Error>>) {
+    let mut errors: Vec<Box<dyn std::error::Error>> = vec![];
+    // assimp leaves it at 0 when the file doesn't say, 25 is what assimp's own viewer uses then
+    let ticks_per_second = if anim.ticks_per_second > 0.0 { anim.ticks_per_second } else { 25.0 };
+    let seconds = |ticks: f64| (ticks / ticks_per_second) as f32;
+
+    let mut channels = vec![];
+
+    for channel in &anim.channels {
+        let node = match nodes.iter().position(|node| node.name == channel.name) {
+            Some(node) => node,
+            None => {
+                errors.push(anyhow!("Animation {} targets node {} which doesn't exist", anim.name, channel.name).into());
+                continue;
             },
-            "$clr.specular" => {
-                specular = match &property.data {
-                    russimp::material::PropertyTypeInfo::FloatArray(a) => {
-                        glm::vec3(a[0], a[1], a[2])
-                    },
-                    _ => panic!("Property should not be this type: {}", property.key)
+        };
+
+        channels.push(Channel {
+            node,
+            translations: channel.position_keys.iter()
+                .map(|key| Keyframe { time: seconds(key.time), value: glm::vec3(key.value.x, key.value.y, key.value.z) })
+                .collect(),
+            rotations: channel.rotation_keys.iter()
+                .map(|key| Keyframe { time: seconds(key.time), value: Quat::new(key.value.x, key.value.y, key.value.z, key.value.w) })
+                .collect(),
+            scales: channel.scaling_keys.iter()
+                .map(|key| Keyframe { time: seconds(key.time), value: glm::vec3(key.value.x, key.value.y, key.value.z) })
+                .collect(),
+        });
+    }
+
+    let mut morph_channels = vec![];
+
+    for channel in &anim.morph_mesh_channels {
+        // gltf animates the weights of a node, other formats might name the mesh instead
+        let targets = match nodes.iter().find(|node| node.name == channel.name) {
+            Some(node) => node.mesh_indices.clone(),
+            None => (0..meshes.len()).filter(|&i| meshes[i].name == channel.name).collect(),
+        };
+
+        let target_count = targets.iter().map(|&i| meshes[i].morph_targets.len()).max().unwrap_or(0);
+        if target_count == 0 {
+            errors.push(anyhow!("Animation {} targets morph targets of {} which has none", anim.name, channel.name).into());
+            continue;
+        }
+
+        // keys only have the targets that are weighted, the rest are 0
+        let weights = channel.keys.iter().map(|key| {
+            let mut weights = vec![0.0; target_count];
+            for (&target, &weight) in key.values.iter().zip(key.weights.iter()) {
+                if let Some(w) = weights.get_mut(target as usize) {
+                    *w = weight as f32;
                 }
             }
-            "?mat.name" => {
-                mat_name = match &property.data {
-                    russimp::material::PropertyTypeInfo::String(s) => {
-                        s.to_string()
-                    },
-                    _ => panic!("Property should not be this type: {}", property.key)
-                };
-            }
-            "$mat.shininess" => {
-                shininess = match &property.data {
-                    russimp::material::PropertyTypeInfo::FloatArray(a) => {
-                        a[0]
-                    },
-                    _ => panic!("Property should not be this type: {}", property.key)
-                };
-            }
-            _ => {},
+
+            Keyframe { time: seconds(key.time), value: weights }
+        }).collect();
+
+        morph_channels.push(MorphChannel {
+            meshes: targets,
+            weights,
+        });
+    }
+
+    let clip = Clip {
+        name: if anim.name.is_empty() { String::from("Unnamed") } else { anim.name.to_owned() },
+        duration: seconds(anim.duration),
+        channels,
+        morph_channels,
+    };
+
+    (clip, errors)
+}
+
+fn process_material(mat: &russimp::material::Material) -> (Material, Vec<Box<dyn std::error::Error>>) {
+    let mut material = Material::default();
+    let mut mistyped = vec![];
+    let mut unsupported = vec![];
+    let mut has_base_color = false;
+
+    for property in mat.properties.iter() {
+        let mapping = match material::find_mapping(&property.key, property.semantic) {
+            Some(mapping) => mapping,
+            None => {
+                unsupported.push(property.key.as_str());
+                continue;
+            },
+        };
+
+        match material::convert(&property.data, mapping.kind) {
+            Some(value) => {
+                (mapping.apply)(&mut material, value, property.semantic);
+                has_base_color |= material::BASE_COLOR_KEYS.contains(&mapping.key);
+            },
+            None => mistyped.push(format!(
+                "{} is a {} and can't be used as {:?}",
+                property.key, material::type_name(&property.data), mapping.kind
+            )),
+        }
+    }
+
+    // the name is only known after all the properties are read
+    let mut errors: Vec<Box<dyn std::error::Error>> = mistyped.into_iter()
+        .map(|e| anyhow!("Material {}: property {}", material.name, e).into())
+        .collect();
+
+    if !unsupported.is_empty() {
+        unsupported.sort();
+        unsupported.dedup();
+        errors.push(anyhow!("Material {}: unsupported properties: {}", material.name, unsupported.join(", ")).into());
+    }
+
+    // non pbr formats (obj, fbx, etc..) only have the phong properties so approximate them
+    if !has_base_color {
+        material.base_color = glm::vec4(material.diffuse.x, material.diffuse.y, material.diffuse.z, 1.0);
+        // source: http://graphicrants.blogspot.com/2013/08/specular-brdf-reference.html
+        material.roughness = (2.0 / (material.shininess.max(0.0) + 2.0)).sqrt();
+        // they also don't have an alpha mode, only an opacity
+        if material.opacity < 1.0 {
+            material.alpha_mode = material::AlphaMode::Blend;
         }
     }
 
-    Material::new(mat_name, ambient, diffuse, specular, shininess)
+    (material, errors)
 }
 
+/// Slope scale of the normal maps made from bump maps
+const BUMP_STRENGTH: f32 = 2.0;
+
 fn load_material_textures(
     mat: &russimp::material::Material,
+    material: &Material,
+    raw: &mut RawScene,
     dir: &std::path::PathBuf,
-    loaded_textures: &mut Vec<Texture>
-) -> (Vec<Texture>, Vec<Box<dyn std::error::Error>>) {
+    images: &mut Vec<ImageData>,
+) -> (Vec<ImageRef>, Vec<Box<dyn std::error::Error>>) {
 
     let mut textures = vec![];
     let mut errors = vec![];
 
-    // println!("there exists '{}' textures in this material", mat.textures.len());
+    for (typ, tex_filename) in material.texture_files.iter() {
+        let typ = *typ;
+        if !SUPPORTED_TEXTURE_TYPES.contains(&typ) || tex_filename.is_empty() {
+            continue;
+        }
+        println!("texture filename: {}", tex_filename);
+        // embedded textures get a path too so they can be shared like the ones on disk
+        let path = dir.join(tex_filename);
 
-    for (typ, tex) in mat.textures.iter() {
-        if SUPPORTED_TEXTURE_TYPES.contains(typ) {
-            let texture = tex.borrow();
-            let mut skip = false;
-            let tex_filename = &texture.filename;
-            // HACK: fix this
-            if tex_filename.is_empty() {
-                continue;
+        // grayscale bump maps are turned into normal maps, so an image is only shared with references that see the same pixels
+        let srgb = material::is_srgb_texture(typ);
+        let same_pixels = |image: &ImageData| match typ {
+            russimp::material::TextureType::Height => image.from_heights || !utils::is_grayscale(&image.image),
+            _ => !image.from_heights,
+        };
+
+        // the same file is usually referenced by more than one type (diffuse and base color),
+        // share the image but keep the type it was referenced as
+        if let Some(image) = images.iter().position(|image| image.path == path && image.srgb == srgb && same_pixels(image)) {
+            textures.push((image, typ));
+            continue;
+        }
+        // a color map that's also used as a data map (metallic roughness, normals, etc..) is uploaded
+        // a second time so the data isn't decoded as srgb, same for a bump map that's used as something else.
+        // it's copied from the file's pixels, the file is read again if there's only a converted bump map
+        if let Some(image) = images.iter().find(|image| image.path == path && !image.from_heights) {
+            let mut image = ImageData { path, image: image.image.clone(), srgb, from_heights: false };
+            convert_bump_map(&mut image, typ);
+            textures.push((images.len(), typ));
+            images.push(image);
+            continue;
+        }
+
+        // russimp keeps a single embedded texture per type and loses their indices, they're looked up in assimp's
+        // scene instead. only done if the file has embedded textures at all so models with textures on disk
+        // aren't imported twice
+        let embedded = if tex_filename.starts_with('*') || !mat.textures.is_empty() {
+            match raw.get() {
+                Ok(scene) => embedded_texture(scene, tex_filename),
+                Err(e) => {
+                    errors.push(e);
+                    None
+                },
             }
-            println!("texture filename: {}", tex_filename);
-            let path = dir.join(tex_filename);
-
-            for loaded_tex in &mut *loaded_textures {
-                if loaded_tex.path == path {
-                    textures.push(loaded_tex.clone());
-                    skip = true;
-                    break;
-                }
+        } else {
+            None
+        };
+
+        let image = match embedded {
+            Some(embedded) => load_embedded_texture(embedded)
+                .map(|image| ImageData { path, image, srgb: false, from_heights: false }),
+            None if tex_filename.starts_with('*') => {
+                Err(format!("Embedded texture {} is missing from the model file", tex_filename).into())
+            },
+            None => ImageData::load(path),
+        };
+
+        match image {
+            Ok(mut image) => {
+                convert_bump_map(&mut image, typ);
+                image.srgb = srgb;
+                textures.push((images.len(), typ));
+                images.push(image);
+            },
+            Err(e) => {
+                let err = anyhow!("Error loading texture: {}", e);
+                println!("{}", err);
+                errors.push(err.into());
+            },
+        }
+    }
+
+    return (textures, errors);
+}
+
+/// Bump maps are often normal maps anyway, grayscale ones are heights and are turned into normals
+fn convert_bump_map(image: &mut ImageData, typ: russimp::material::TextureType) {
+    if typ == russimp::material::TextureType::Height && utils::is_grayscale(&image.image) {
+        image.image = utils::normal_map_from_heights(&image.image, BUMP_STRENGTH);
+        image.from_heights = true;
+    }
+}
+
+/// assimp's own scene for what russimp doesn't copy, the file is imported again the first time it's needed.
+/// it's post processed the same way as russimp's scene so their meshes line up
+struct RawScene<'a> {
+    path: &'a str,
+    flags: u32,
+    /// `None` until it's imported, null if that failed
+    scene: Option<*const russimp::sys::aiScene>,
+}
+
+impl<'a> RawScene<'a> {
+    fn new(path: &'a str, settings: &ImportSettings) -> Self {
+        Self {
+            path,
+            flags: settings.post_process().into_iter().fold(0, |acc, flag| acc | flag as u32),
+            scene: None,
+        }
+    }
+
+    fn get(&mut self) -> Result<&russimp::sys::aiScene, Box<dyn std::error::Error>> {
+        if self.scene.is_none() {
+            let c_path = std::ffi::CString::new(self.path)?;
+            self.scene = Some(unsafe { russimp::sys::aiImportFile(c_path.as_ptr(), self.flags) });
+        }
+
+        match self.scene.and_then(|scene| unsafe { scene.as_ref() }) {
+            Some(scene) => Ok(scene),
+            None => Err(format!("Failed to read \"{}\" with assimp", self.path).into()),
+        }
+    }
+}
+
+impl Drop for RawScene<'_> {
+    fn drop(&mut self) {
+        if let Some(scene) = self.scene.filter(|scene| !scene.is_null()) {
+            unsafe { russimp::sys::aiReleaseImport(scene) };
+        }
+    }
+}
+
+/// Numeric metadata values by key, converted to f64 whatever their type is.
+/// assimp puts the metadata on the scene, older versions on the root node
+fn metadata_numbers(scene: &russimp::sys::aiScene) -> HashMap<String, f64> {
+    let mut numbers = HashMap::new();
+    let metadata = unsafe {
+        scene.mMetaData.as_ref().or_else(|| scene.mRootNode.as_ref().and_then(|root| root.mMetaData.as_ref()))
+    };
+    let metadata = match metadata {
+        Some(metadata) => metadata,
+        None => return numbers,
+    };
+
+    let keys = unsafe { raw_slice(metadata.mKeys, metadata.mNumProperties) };
+    let values = unsafe { raw_slice(metadata.mValues, metadata.mNumProperties) };
+    for (key, entry) in keys.iter().zip(values) {
+        if entry.mData.is_null() {
+            continue;
+        }
+
+        let value = unsafe {
+            match entry.mType {
+                russimp::sys::aiMetadataType_AI_BOOL => *(entry.mData as *const bool) as u8 as f64,
+                russimp::sys::aiMetadataType_AI_INT32 => *(entry.mData as *const i32) as f64,
+                russimp::sys::aiMetadataType_AI_UINT64 => *(entry.mData as *const u64) as f64,
+                russimp::sys::aiMetadataType_AI_FLOAT => *(entry.mData as *const f32) as f64,
+                russimp::sys::aiMetadataType_AI_DOUBLE => *(entry.mData as *const f64),
+                // strings and vectors
+                _ => continue,
             }
+        };
+        numbers.insert(String::from(key), value);
+    }
 
-            if !skip {
-                match Texture::new(path, *typ) {
-                    Ok(texture) => {
-                        loaded_textures.push(texture.clone());
-                        textures.push(texture);
-                    },
-                    Err(e) => {
-                        let err = anyhow!("Error loading texture: {}", e);
-                        println!("{}", err);
-                        errors.push(err.into());
-                    },
-                }
+    numbers
+}
+
+/// Embedded textures are referenced as "*<index>" or by the name of the file they were embedded from
+fn embedded_texture<'a>(scene: &'a russimp::sys::aiScene, reference: &str) -> Option<&'a russimp::sys::aiTexture> {
+    let textures = unsafe { raw_slice(scene.mTextures, scene.mNumTextures) };
+
+    let texture = match reference.strip_prefix('*') {
+        Some(index) => index.parse::<usize>().ok().and_then(|i| textures.get(i)),
+        None => textures.iter().find(|&&texture| {
+            let filename: String = unsafe { (&(*texture).mFilename).into() };
+            std::path::Path::new(&filename).file_name() == std::path::Path::new(reference).file_name()
+        }),
+    };
+
+    texture.and_then(|&texture| unsafe { texture.as_ref() })
+}
+
+fn load_embedded_texture(texture: &russimp::sys::aiTexture) -> Result<image::DynamicImage, Box<dyn std::error::Error>> {
+    // a height of 0 means the data is a compressed image file (png, jpeg, etc..) that's `width` bytes long,
+    // otherwise it's `width * height` uncompressed bgra texels
+    if texture.mHeight == 0 {
+        let bytes = unsafe { raw_slice(texture.pcData as *mut u8, texture.mWidth) };
+        return Ok(utils::decode_texture_from_memory(bytes)?);
+    }
+
+    let texels = unsafe { raw_slice(texture.pcData, texture.mWidth * texture.mHeight) };
+    let pixels = texels.iter().flat_map(|t| [t.r, t.g, t.b, t.a]).collect();
+
+    Ok(utils::texture_from_rgba(texture.mWidth, texture.mHeight, pixels)?)
+}
+
+/// russimp only copies the bitangents of anim meshes, so the morph targets of every mesh are read
+/// from assimp's scene. assimp stores the absolute positions and normals of the targets, they're
+/// turned into offsets from the base mesh here. targets that don't match `meshes` are skipped
+fn read_morph_targets(
+    scene: &russimp::sys::aiScene,
+    meshes: &[russimp::mesh::Mesh],
+    errors: &mut Vec<Box<dyn std::error::Error>>,
+) -> Vec<Vec<MorphTarget>> {
+    let raw_meshes = unsafe { raw_slice(scene.mMeshes, scene.mNumMeshes) };
+    if raw_meshes.len() != meshes.len() {
+        errors.push(anyhow!("Morph targets are skipped, the file has {} meshes instead of {}", raw_meshes.len(), meshes.len()).into());
+        return vec![];
+    }
+
+    let mut targets = vec![];
+    for (&raw_mesh, mesh) in raw_meshes.iter().zip(meshes) {
+        let raw_mesh = unsafe { &*raw_mesh };
+        let base_positions = unsafe { raw_slice(raw_mesh.mVertices, raw_mesh.mNumVertices) };
+        let base_normals = unsafe { raw_slice(raw_mesh.mNormals, raw_mesh.mNumVertices) };
+        let anim_meshes = unsafe { raw_slice(raw_mesh.mAnimMeshes, raw_mesh.mNumAnimMeshes) };
+
+        let mut mesh_targets = vec![];
+        for (i, &anim) in anim_meshes.iter().enumerate() {
+            let anim = unsafe { &*anim };
+            let name: String = (&anim.mName).into();
+            let name = if name.is_empty() { format!("Target {}", i) } else { name };
+
+            // the deltas are per vertex, they're meaningless if the vertices don't line up
+            let vertex_count = mesh.vertices.len();
+            if anim.mNumVertices as usize != vertex_count || base_positions.len() != vertex_count {
+                errors.push(anyhow!("Morph target {} of mesh {} has {} vertices instead of {}, it's skipped", name, mesh.name, anim.mNumVertices, vertex_count).into());
+                continue;
             }
+
+            let positions = unsafe { raw_slice(anim.mVertices, anim.mNumVertices) };
+            let normals = unsafe { raw_slice(anim.mNormals, anim.mNumVertices) };
+            mesh_targets.push(MorphTarget {
+                name,
+                position_deltas: positions.iter().zip(base_positions)
+                    .map(|(p, base)| glm::vec3(p.x - base.x, p.y - base.y, p.z - base.z))
+                    .collect(),
+                normal_deltas: normals.iter().zip(base_normals)
+                    .map(|(n, base)| glm::vec3(n.x - base.x, n.y - base.y, n.z - base.z))
+                    .collect(),
+                default_weight: anim.mWeight,
+            });
         }
+        targets.push(mesh_targets);
     }
 
-    return (textures, errors);
+    targets
 }
 
-impl Model {
-    pub fn new(path: &str, state: &mut ui::State) -> Result<Self, Box<dyn std::error::Error>>  {
-        let scene = russimp::scene::Scene::from_file(path,
-            vec![
-            russimp::scene::PostProcess::Triangulate,
-            russimp::scene::PostProcess::FlipUVs,
-            ])
+/// assimp leaves the arrays it doesn't have as null
+unsafe fn raw_slice<'a, T>(ptr: *mut T, len: u32) -> &'a [T] {
+    if ptr.is_null() || len == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(ptr, len as usize)
+    }
+}
+
+/// Axes and units from the file's metadata (fbx only), overridden by the import settings
+fn axis_conversion(
+    scene: &russimp::scene::Scene,
+    root_node: &russimp::node::Node,
+    raw: &mut RawScene,
+    settings: &ImportSettings,
+    path: &str,
+    messages: &mut Vec<LogMessage>,
+) -> AxisConversion {
+    // russimp only has the keys of the metadata, the values are read from assimp's scene if the ones
+    // that are needed are there
+    let has_axes = scene.metadata.as_ref()
+        .or(root_node.metadata.as_ref())
+        .is_some_and(|metadata| metadata.keys.iter().any(|key| AxisConversion::METADATA_KEYS.contains(&key.as_str())));
+
+    let from_file = match has_axes {
+        true => match raw.get() {
+            Ok(scene) => AxisConversion::from_metadata(&metadata_numbers(scene)),
+            Err(e) => {
+                messages.push(LogMessage::new(LogLevel::Warning, &format!("Axes of \"{}\" couldn't be read: {}", path, e)));
+                None
+            },
+        },
+        false => None,
+    };
+
+    let mut conversion = from_file.unwrap_or_default();
+    if let Some((up, front, right)) = settings.up_axis.axes() {
+        conversion.up = up;
+        conversion.front = front;
+        conversion.right = right;
+    }
+    conversion.unit_scale *= settings.scale;
+
+    if !conversion.is_valid() {
+        messages.push(LogMessage::new(LogLevel::Warning, &format!("Model \"{}\" has invalid axes ({}), they're ignored", path, conversion)));
+    } else if conversion != AxisConversion::default() {
+        let source = if from_file.is_some() { "file metadata" } else { "import settings" };
+        messages.push(LogMessage::new(LogLevel::Info, &format!("Model \"{}\" converted from {} ({})", path, conversion, source)));
+    }
+
+    conversion
+}
+
+/// Everything read from a model file, built without a gl context so it can be loaded on another thread
+pub struct ModelData {
+    pub name: String,
+    pub nodes: Vec<Node>,
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<MaterialData>,
+    pub images: Vec<ImageData>,
+    pub clips: Vec<Clip>,
+    /// lights from the file and the nodes they're attached to
+    pub lights: Vec<(usize, russimp::light::Light)>,
+    pub axis_conversion: AxisConversion,
+    /// warnings and info for the console
+    pub messages: Vec<LogMessage>,
+    pub path: PathBuf,
+    pub import_settings: ImportSettings,
+}
+
+impl ModelData {
+    /// Returns early with an error once `progress` is cancelled
+    pub fn load(path: &str, settings: &ImportSettings, progress: &Progress) -> Result<Self, Box<dyn std::error::Error>> {
+        progress.set(0.0, "Parsing");
+
+        let scene = russimp::scene::Scene::from_file(path, settings.post_process())
             .map_err(|e| {
                 let e = match e {
                     russimp::RussimpError::TextureNotFound => anyhow!("Texture not found"),
@@ -240,29 +649,562 @@ impl Model {
             None => return Err("Model path has no parent directory".into()),
         }.to_path_buf();
 
-        // TODO: handle fbx metadata that contains the indices and values of the proper axes
-        // reference: https://github.com/assimp/assimp/issues/849#issuecomment-538982013
-        // println!("scene metadata {:#?}", scene.metadata);
-        // println!("root metadata {:#?}", root_node.metadata);
+        let mut raw = RawScene::new(path, settings);
 
-        let mut loaded_textures = vec![];
+        let mut messages = vec![];
+        let axis_conversion = axis_conversion(&scene, root_node, &mut raw, settings, path, &mut messages);
+
+        let mut images = vec![];
+        let mut materials = vec![];
         let mut meshes = vec![];
-        let init_trans_mat = utils::mat_ident();
-        let errors = process_node(&root_node, &scene, &mut meshes, &directory, &mut loaded_textures, &init_trans_mat);
+        let mut errors = vec![];
+
+        // materials are shared between meshes, process them once so their warnings only show up once
+        for (i, mat) in scene.materials.iter().enumerate() {
+            if progress.is_cancelled() {
+                return Err("Import cancelled".into());
+            }
+            // decoding the textures is most of the work
+            progress.set(0.1 + 0.6 * i as f32 / scene.materials.len() as f32, &format!("Material {}/{}", i + 1, scene.materials.len()));
+
+            let (material, mut errs) = process_material(mat);
+            errors.append(&mut errs);
 
-        for err in errors {
-            state.log.history.push(log::LogMessage::new(log::LogLevel::Warning, &err.to_string()));
+            let (textures, mut errs) = load_material_textures(mat, &material, &mut raw, &directory, &mut images);
+            errors.append(&mut errs);
+
+            materials.push(MaterialData { material, textures });
+        }
+
+        // nodes come first since bones and animations reference them by name
+        let mut nodes = vec![];
+        process_node(root_node, None, &mut nodes);
+
+        let mut morph_targets = vec![];
+        if scene.meshes.iter().any(|mesh| !mesh.anim_meshes.is_empty()) {
+            progress.set(0.7, "Morph targets");
+            match raw.get() {
+                Ok(raw_scene) => morph_targets = read_morph_targets(raw_scene, &scene.meshes, &mut errors),
+                Err(e) => errors.push(e),
+            }
         }
 
-        Ok(Model {
+        // meshes are stored once in the same order as the scene so nodes can reference them by index
+        for (i, mesh) in scene.meshes.iter().enumerate() {
+            if progress.is_cancelled() {
+                return Err("Import cancelled".into());
+            }
+            progress.set(0.75 + 0.2 * i as f32 / scene.meshes.len() as f32, &format!("Mesh {}/{}", i + 1, scene.meshes.len()));
+
+            let targets = morph_targets.get_mut(i).map(std::mem::take).unwrap_or_default();
+            let (processed_mesh, mut errs) = process_mesh(mesh, &nodes, targets);
+            errors.append(&mut errs);
+            meshes.push(processed_mesh);
+        }
+
+        progress.set(0.95, "Animations");
+
+        let mut clips = vec![];
+        for anim in &scene.animations {
+            let (clip, mut errs) = process_animation(anim, &nodes, &meshes);
+            errors.append(&mut errs);
+            clips.push(clip);
+        }
+
+        // lights are positioned relative to the node with the same name
+        let mut lights = vec![];
+        for light in scene.lights {
+            match nodes.iter().position(|node| node.name == light.name) {
+                Some(node) => lights.push((node, light)),
+                None => errors.push(anyhow!("Light {} has no node", light.name).into()),
+            }
+        }
+
+        messages.extend(errors.into_iter().map(|err| LogMessage::new(LogLevel::Warning, &err.to_string())));
+
+        Ok(ModelData {
             name: root_node.name.to_owned(),
+            nodes,
             meshes,
+            materials,
+            images,
+            clips,
+            lights,
+            axis_conversion,
+            messages,
+            path: PathBuf::from(path),
+            import_settings: settings.clone(),
         })
     }
+}
+
+impl ModelData {
+    /// Uploads the whole model at once, needs a gl context
+    pub fn upload(self, log: &mut Log) -> Model {
+        let mut upload = ModelUpload::new(self);
+        while !upload.step(std::time::Duration::MAX) {}
+
+        upload.finish(log)
+    }
+}
+
+/// Uploads a model to the gpu a few meshes at a time so big models don't stall the render loop
+pub struct ModelUpload {
+    name: String,
+    nodes: Vec<Node>,
+    clips: Vec<Clip>,
+    lights: Vec<(usize, russimp::light::Light)>,
+    axis_conversion: AxisConversion,
+    messages: Vec<LogMessage>,
+    path: PathBuf,
+    import_settings: ImportSettings,
+    materials: Vec<MaterialData>,
+    images: std::vec::IntoIter<ImageData>,
+    mesh_data: std::vec::IntoIter<MeshData>,
+    /// same order as the images
+    textures: Vec<Texture>,
+    meshes: Vec<Mesh>,
+    total: usize,
+}
+
+impl ModelUpload {
+    pub fn new(data: ModelData) -> Self {
+        let total = data.images.len() + data.meshes.len();
+
+        ModelUpload {
+            name: data.name,
+            nodes: data.nodes,
+            clips: data.clips,
+            lights: data.lights,
+            axis_conversion: data.axis_conversion,
+            messages: data.messages,
+            path: data.path,
+            import_settings: data.import_settings,
+            materials: data.materials,
+            images: data.images.into_iter(),
+            mesh_data: data.meshes.into_iter(),
+            textures: vec![],
+            meshes: vec![],
+            total,
+        }
+    }
+
+    /// Uploads images and then meshes until `budget` runs out, at least one per call.
+    /// Returns true once everything is uploaded
+    pub fn step(&mut self, budget: std::time::Duration) -> bool {
+        let start = std::time::Instant::now();
+
+        loop {
+            if let Some(image) = self.images.next() {
+                self.textures.push(image.upload());
+            } else if let Some(data) = self.mesh_data.next() {
+                let mesh = match self.materials.get(data.material) {
+                    Some(material) => data.upload(material, &self.textures),
+                    None => data.upload(&MaterialData::default(), &self.textures),
+                };
+                self.meshes.push(mesh);
+            } else {
+                return true;
+            }
+
+            if start.elapsed() >= budget {
+                return false;
+            }
+        }
+    }
+
+    pub fn progress(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+
+        (self.textures.len() + self.meshes.len()) as f32 / self.total as f32
+    }
+
+    /// Only call once `step` returned true, the upload is empty afterwards
+    pub fn finish(&mut self, log: &mut Log) -> Model {
+        log.history.append(&mut self.messages);
+
+        let mut model = Model {
+            name: std::mem::take(&mut self.name),
+            nodes: std::mem::take(&mut self.nodes),
+            meshes: std::mem::take(&mut self.meshes),
+            clips: std::mem::take(&mut self.clips),
+            animator: Animator::default(),
+            axis_conversion: self.axis_conversion,
+            imported_axis_conversion: self.axis_conversion,
+            imported_lights: vec![],
+            path: std::mem::take(&mut self.path),
+            import_settings: self.import_settings.clone(),
+        };
+
+        // area and ambient lights aren't supported
+        let world = model.world_matrices();
+        model.imported_lights = self.lights.drain(..)
+            .filter_map(|(node, light)| Light::from_assimp(&light, &world[node]))
+            .collect();
+
+        model
+    }
+}
+
+impl Model {
+    /// Loads and uploads the whole model at once
+    pub fn new(path: &str, settings: &ImportSettings, log: &mut Log) -> Result<Self, Box<dyn std::error::Error>> {
+        let data = ModelData::load(path, settings, &Progress::default())?;
+
+        Ok(data.upload(log))
+    }
+
+    /// Index of the root node, it's always the first one
+    pub fn root(&self) -> usize {
+        0
+    }
+
+    pub fn world_transform(&self, idx: usize) -> glm::Mat4 {
+        match self.nodes[idx].parent {
+            Some(parent) => self.world_transform(parent) * self.local_matrix(idx),
+            None => self.axis_conversion.matrix() * self.local_matrix(idx),
+        }
+    }
+
+    pub fn local_matrix(&self, idx: usize) -> glm::Mat4 {
+        match &self.nodes[idx].pose {
+            // animations are relative to the node's origin
+            Some(pose) => pose.to_mat4(),
+            None => self.nodes[idx].local_transform.to_mat4_with_pivot(self.pivot_point(idx)),
+        }
+    }
+
+    /// Whether `node` is `ancestor` or somewhere below it
+    pub fn is_descendant(&self, node: usize, ancestor: usize) -> bool {
+        let mut current = Some(node);
+        while let Some(idx) = current {
+            if idx == ancestor {
+                return true;
+            }
+            current = self.nodes[idx].parent;
+        }
+
+        false
+    }
+
+    /// World matrices of all nodes, walks down from the root since reparenting can store children before their parents
+    pub fn world_matrices(&self) -> Vec<glm::Mat4> {
+        let mut world = vec![utils::mat_ident(); self.nodes.len()];
+        let conversion = self.axis_conversion.matrix();
+        let mut stack: Vec<(usize, glm::Mat4)> = self.nodes.iter().enumerate()
+            .filter(|(_, node)| node.parent.is_none())
+            .map(|(idx, _)| (idx, conversion))
+            .collect();
+
+        while let Some((idx, parent)) = stack.pop() {
+            world[idx] = parent * self.local_matrix(idx);
+            for &child in &self.nodes[idx].children {
+                stack.push((child, world[idx]));
+            }
+        }
+
+        world
+    }
+
+    /// Whether `node` can be moved under `parent`, the root stays where it is and nodes can't go below themselves
+    pub fn can_reparent(&self, node: usize, parent: usize) -> bool {
+        self.nodes[node].parent.is_some() && self.nodes[node].parent != Some(parent) && !self.is_descendant(parent, node)
+    }
+
+    /// Moves `node` to its new place in the hierarchy, returns where it was before
+    pub fn reparent(&mut self, node: usize, (parent, position, transform): Placement) -> Placement {
+        let old_parent = self.nodes[node].parent.expect("The root node can't be reparented");
+        let old_position = self.nodes[old_parent].children.iter().position(|&child| child == node).unwrap_or(0);
+        let old_transform = self.nodes[node].local_transform;
+
+        self.nodes[old_parent].children.retain(|&child| child != node);
+        let children = &mut self.nodes[parent].children;
+        children.insert(position.min(children.len()), node);
+        self.nodes[node].parent = Some(parent);
+        self.nodes[node].local_transform = transform;
+
+        (old_parent, old_position, old_transform)
+    }
+
+    /// Local transform that keeps `node` where it is in the world if it's moved under `parent`
+    pub fn transform_under(&self, node: usize, parent: usize) -> Transform {
+        let world = self.world_transform(node);
+        let parent_world = self.world_transform(parent);
+        let local = parent_world.inverse().unwrap_or(utils::mat_ident()) * world;
+
+        // the decomposed transform is around the origin, move it back to the node's pivot
+        let mut transform = Transform::from_mat4(&local);
+        transform.move_pivot(glm::vec3(0.0, 0.0, 0.0), self.pivot_point(node));
+        transform
+    }
+
+    /// Advances the current clip and poses the nodes it animates
+    pub fn update_animation(&mut self, delta_time: f32) {
+        for node in self.nodes.iter_mut() {
+            node.pose = None;
+        }
+        for mesh in self.meshes.iter_mut() {
+            mesh.morph_pose = None;
+        }
+
+        let clip = match self.animator.clip.and_then(|clip| self.clips.get(clip)) {
+            Some(clip) => clip,
+            None => return,
+        };
+
+        self.animator.advance(delta_time, clip.duration);
+
+        for channel in &clip.channels {
+            // the pose is drawn around the node's origin, so the parts without keys have to be too
+            let mut rest = self.nodes[channel.node].local_transform;
+            rest.move_pivot(self.pivot_point(channel.node), glm::vec3(0.0, 0.0, 0.0));
+            self.nodes[channel.node].pose = Some(channel.sample(self.animator.time, self.animator.interpolation, &rest));
+        }
+
+        for channel in &clip.morph_channels {
+            let weights = channel.sample(self.animator.time, self.animator.interpolation);
+            for &mesh in &channel.meshes {
+                self.meshes[mesh].morph_pose = weights.clone();
+            }
+        }
+    }
+
+    /// The pivot of a node in its own space
+    pub fn pivot_point(&self, idx: usize) -> glm::Vec3 {
+        match self.nodes[idx].pivot {
+            Pivot::Origin => glm::vec3(0.0, 0.0, 0.0),
+            Pivot::BoundingBoxCenter(point) | Pivot::Custom(point) => point,
+        }
+    }
+
+    /// Center of the current bounds of a node in its own space, the origin if it has no meshes
+    pub fn bounding_box_center(&self, idx: usize) -> glm::Vec3 {
+        match self.local_bounds(idx) {
+            Some((min, max)) => (min + max) * 0.5,
+            None => glm::vec3(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn set_pivot(&mut self, idx: usize, pivot: Pivot) {
+        let old = self.pivot_point(idx);
+        self.nodes[idx].pivot = pivot;
+        let new = self.pivot_point(idx);
+
+        self.nodes[idx].local_transform.move_pivot(old, new);
+    }
+
+    /// Bounding box of every mesh in world space, `None` if the model has no meshes
+    pub fn world_bounds(&self) -> Option<(glm::Vec3, glm::Vec3)> {
+        let world = self.world_matrices();
+        let mut bounds: Option<(glm::Vec3, glm::Vec3)> = None;
+
+        for (idx, node) in self.nodes.iter().enumerate() {
+            for &mesh_idx in &node.mesh_indices {
+                let mesh = &self.meshes[mesh_idx];
+                for i in 0..8 {
+                    let corner = glm::vec4(
+                        if i & 1 == 0 { mesh.aabb_min.x } else { mesh.aabb_max.x },
+                        if i & 2 == 0 { mesh.aabb_min.y } else { mesh.aabb_max.y },
+                        if i & 4 == 0 { mesh.aabb_min.z } else { mesh.aabb_max.z },
+                        1.0,
+                    );
+                    let point = (world[idx] * corner).truncate(3);
+                    bounds = match bounds {
+                        Some((min, max)) => Some((glm::min(min, point), glm::max(max, point))),
+                        None => Some((point, point)),
+                    };
+                }
+            }
+        }
+
+        bounds
+    }
+
+    /// Bounding box of a node's meshes and all of its children in the node's own space
+    pub fn local_bounds(&self, idx: usize) -> Option<(glm::Vec3, glm::Vec3)> {
+        let node = &self.nodes[idx];
+        let mut bounds: Option<(glm::Vec3, glm::Vec3)> = None;
+
+        let mut extend = |point: glm::Vec3| {
+            bounds = match bounds {
+                Some((min, max)) => Some((glm::min(min, point), glm::max(max, point))),
+                None => Some((point, point)),
+            };
+        };
+
+        for &mesh_idx in &node.mesh_indices {
+            let mesh = &self.meshes[mesh_idx];
+            extend(mesh.aabb_min);
+            extend(mesh.aabb_max);
+        }
+
+        for &child in &node.children {
+            if let Some((min, max)) = self.local_bounds(child) {
+                let child_mat = self.local_matrix(child);
+                // all 8 corners since the child can be rotated
+                for i in 0..8 {
+                    let corner = glm::vec4(
+                        if i & 1 == 0 { min.x } else { max.x },
+                        if i & 2 == 0 { min.y } else { max.y },
+                        if i & 4 == 0 { min.z } else { max.z },
+                        1.0,
+                    );
+                    extend((child_mat * corner).truncate(3));
+                }
+            }
+        }
+
+        bounds
+    }
 
     pub fn draw(&self, shader: &Shader) {
-        for mesh in &self.meshes {
-            mesh.draw(shader);
+        self.draw_filtered(shader, |_, _| true);
+    }
+
+    /// Calls `prepare` with the node and mesh index before drawing every mesh, meshes are skipped if it returns false
+    pub fn draw_filtered(&self, shader: &Shader, mut prepare: impl FnMut(usize, usize) -> bool) {
+        let world = self.world_matrices();
+
+        for (idx, node) in self.nodes.iter().enumerate() {
+            for &mesh_idx in &node.mesh_indices {
+                if !prepare(idx, mesh_idx) {
+                    continue;
+                }
+
+                let mesh = &self.meshes[mesh_idx];
+                let bone_matrices = self.bone_matrices(mesh, &world[idx], &world);
+                mesh.draw(shader, &world[idx], &bone_matrices);
+            }
+        }
+    }
+
+    /// Skinning matrices relative to the mesh's node, the model matrix takes care of the rest
+    fn bone_matrices(&self, mesh: &Mesh, mesh_world: &glm::Mat4, world: &[glm::Mat4]) -> Vec<glm::Mat4> {
+        if mesh.bones.is_empty() {
+            return vec![];
+        }
+
+        // a node can be scaled to 0 by an animation
+        let inverse_mesh_world = mesh_world.inverse().unwrap_or(utils::mat_ident());
+
+        mesh.bones.iter().map(|bone| match bone.node {
+            Some(node) => inverse_mesh_world * world[node] * bone.offset,
+            None => utils::mat_ident(),
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use russimp::material::{MaterialProperty, PropertyTypeInfo, TextureType};
+
+    fn russimp_material(properties: Vec<(&str, PropertyTypeInfo)>) -> russimp::material::Material {
+        russimp::material::Material {
+            properties: properties.into_iter().map(|(key, data)| MaterialProperty {
+                key: key.to_string(),
+                data,
+                index: 0,
+                semantic: TextureType::None,
+            }).collect(),
+            textures: Default::default(),
         }
     }
+
+    #[test]
+    fn phong_materials_are_approximated() {
+        let (material, errors) = process_material(&russimp_material(vec![
+            ("?mat.name", PropertyTypeInfo::String(String::from("phong"))),
+            ("$clr.diffuse", PropertyTypeInfo::FloatArray(vec![0.8, 0.4, 0.2])),
+            ("$mat.shininess", PropertyTypeInfo::FloatArray(vec![30.0])),
+            ("$mat.opacity", PropertyTypeInfo::FloatArray(vec![0.5])),
+        ]));
+
+        assert!(errors.is_empty());
+        assert_eq!(material.name, "phong");
+        assert_eq!(material.base_color, glm::vec4(0.8, 0.4, 0.2, 1.0));
+        assert!((material.roughness - 0.25).abs() < 1e-6);
+        assert_eq!(material.alpha_mode, material::AlphaMode::Blend);
+    }
+
+    #[test]
+    fn pbr_materials_keep_their_values() {
+        let (material, errors) = process_material(&russimp_material(vec![
+            ("$clr.diffuse", PropertyTypeInfo::FloatArray(vec![1.0, 0.0, 0.0])),
+            ("$clr.base", PropertyTypeInfo::FloatArray(vec![0.1, 0.2, 0.3, 0.4])),
+            ("$mat.roughnessFactor", PropertyTypeInfo::FloatArray(vec![0.7])),
+            ("$mat.gltf.alphaMode", PropertyTypeInfo::String(String::from("MASK"))),
+            ("$mat.unknown", PropertyTypeInfo::FloatArray(vec![1.0])),
+        ]));
+
+        assert_eq!(material.base_color, glm::vec4(0.1, 0.2, 0.3, 0.4));
+        assert!((material.roughness - 0.7).abs() < 1e-6);
+        assert_eq!(material.alpha_mode, material::AlphaMode::Mask);
+        // the unsupported property is reported
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn animating_a_node_with_a_pivot_starts_where_it_was() {
+        let rotation = Quat::from_axis_angle(glm::vec3(0.0, 1.0, 0.0), 0.5);
+        let mut node = Node::new("node", Transform::new(glm::vec3(1.0, 2.0, 3.0), rotation, glm::vec3(2.0, 2.0, 2.0)), None);
+        node.pivot = Pivot::Custom(glm::vec3(0.5, 0.0, -1.0));
+        let before = node.local_transform.to_mat4_with_pivot(glm::vec3(0.5, 0.0, -1.0));
+
+        // only rotation keys, the translation comes from the rest transform
+        let channel = Channel {
+            node: 0,
+            translations: vec![],
+            rotations: vec![
+                Keyframe { time: 0.0, value: rotation },
+                Keyframe { time: 1.0, value: Quat::identity() },
+            ],
+            scales: vec![],
+        };
+        let mut model = Model {
+            name: String::from("model"),
+            nodes: vec![node],
+            meshes: vec![],
+            clips: vec![Clip { name: String::from("clip"), duration: 1.0, channels: vec![channel], morph_channels: vec![] }],
+            animator: Animator { clip: Some(0), ..Animator::default() },
+            axis_conversion: AxisConversion::default(),
+            imported_axis_conversion: AxisConversion::default(),
+            imported_lights: vec![],
+            path: PathBuf::new(),
+            import_settings: ImportSettings::default(),
+        };
+        model.update_animation(0.0);
+
+        assert!(model.nodes[0].pose.is_some());
+        let after = model.local_matrix(0);
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((after[i][j] - before[i][j]).abs() < 1e-4, "{:?} != {:?}", after, before);
+            }
+        }
+    }
+
+    #[test]
+    fn bump_maps_are_only_shared_once_converted() {
+        let dir = std::env::temp_dir();
+        let file = "bump_maps_are_only_shared_once_converted.png";
+        image::GrayImage::from_fn(4, 4, |x, _| image::Luma([x as u8 * 60])).save(dir.join(file)).unwrap();
+
+        let mut material = Material::default();
+        for typ in [TextureType::Height, TextureType::Normals, TextureType::Diffuse, TextureType::Height] {
+            material.texture_files.push((typ, String::from(file)));
+        }
+        let mut raw = RawScene::new("", &ImportSettings::default());
+        let mut images = vec![];
+        let (textures, errors) = load_material_textures(&russimp_material(vec![]), &material, &mut raw, &dir, &mut images);
+        std::fs::remove_file(dir.join(file)).unwrap();
+
+        assert!(errors.is_empty());
+        assert_eq!(textures.iter().map(|&(image, _)| image).collect::<Vec<_>>(), [0, 1, 2, 0]);
+        assert!(images[0].from_heights);
+        assert!(!images[1].from_heights && !images[1].srgb && utils::is_grayscale(&images[1].image));
+        assert!(!images[2].from_heights && images[2].srgb);
+    }
 }
@@ -0,0 +1,62 @@
+use crate::transform::Transform;
+
+/// Point that rotation and scale happen around
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pivot {
+    /// the node's own origin from the hierarchy
+    Origin,
+    /// center of the bounding box of the node's meshes and children, computed when it's chosen
+    /// so editing the children afterwards doesn't move the node
+    BoundingBoxCenter(glm::Vec3),
+    /// a point in the node's space
+    Custom(glm::Vec3),
+}
+
+impl Pivot {
+    pub const LABELS: [&'static str; 3] = ["Origin", "Bounding Box Center", "Custom"];
+
+    pub fn index(&self) -> usize {
+        match self {
+            Pivot::Origin => 0,
+            Pivot::BoundingBoxCenter(..) => 1,
+            Pivot::Custom(..) => 2,
+        }
+    }
+}
+
+impl Default for Pivot {
+    fn default() -> Self {
+        Pivot::Origin
+    }
+}
+
+#[derive(Debug)]
+pub struct Node {
+    pub name: String,
+    pub local_transform: Transform,
+    pub children: Vec<usize>,
+    pub mesh_indices: Vec<usize>,
+    pub parent: Option<usize>,
+    pub pivot: Pivot,
+    /// transform from the current animation frame, used instead of `local_transform` while set
+    pub pose: Option<Transform>,
+    /// as they were in the file, projects only save the nodes that were changed since
+    pub imported_transform: Transform,
+    pub imported_parent: Option<usize>,
+}
+
+impl Node {
+    pub fn new(name: &str, local_transform: Transform, parent: Option<usize>) -> Self {
+        Node {
+            name: name.to_string(),
+            local_transform,
+            children: vec![],
+            mesh_indices: vec![],
+            parent,
+            pivot: Pivot::default(),
+            pose: None,
+            imported_transform: local_transform,
+            imported_parent: parent,
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use glad_gl::gl;
+use serde::{Deserialize, Serialize};
+
+use crate::{gl_object::{GlTexture, VertexArray}, shader::Shader};
+
+/// Curve that maps hdr scene colors into the displayable range
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ToneMapping {
+    None,
+    Reinhard,
+    Aces,
+    Agx,
+}
+
+impl ToneMapping {
+    pub const LABELS: [&'static str; 4] = ["None", "Reinhard", "ACES Filmic", "AgX"];
+
+    pub fn index(&self) -> usize {
+        match self {
+            ToneMapping::None => 0,
+            ToneMapping::Reinhard => 1,
+            ToneMapping::Aces => 2,
+            ToneMapping::Agx => 3,
+        }
+    }
+
+    pub fn from_index(idx: usize) -> Self {
+        match idx {
+            0 => ToneMapping::None,
+            1 => ToneMapping::Reinhard,
+            2 => ToneMapping::Aces,
+            _ => ToneMapping::Agx,
+        }
+    }
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping::Aces
+    }
+}
+
+/// Resolves the hdr scene into an 8 bit srgb image with exposure and tone mapping applied
+pub struct PostProcess {
+    shader: Shader,
+    // core profile won't draw without a vao, even if there are no attributes
+    vao: VertexArray,
+}
+
+impl PostProcess {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            shader: Shader::new("shaders/post_v.glsl", "shaders/post_f.glsl")?,
+            vao: VertexArray::new(),
+        })
+    }
+
+    /// Draws into whatever framebuffer is bound
+    pub fn draw(&self, hdr_scene: &GlTexture, tone_mapping: ToneMapping, exposure: f32) {
+        self.shader.use_shader();
+        self.shader.set_int("hdrScene", 0);
+        self.shader.set_int("toneMapping", tone_mapping.index() as i32);
+        self.shader.set_float("exposure", exposure);
+
+        unsafe {
+            gl::Disable(gl::DEPTH_TEST);
+            gl::Disable(gl::BLEND);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, hdr_scene.id());
+            gl::BindVertexArray(self.vao.id());
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            gl::BindVertexArray(0);
+        }
+    }
+}
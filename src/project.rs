@@ -0,0 +1,446 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{camera::Camera, environment::EnvironmentSettings, import::{AxisConversion, ImportSettings}, light::{Light, LightKind}, log::{Log, LogLevel}, material::{AlphaMode, Material, ShadingModel}, model::Model, node::Pivot, post::ToneMapping, shadow::ShadowSettings, transform::{Quat, Transform}};
+
+/// Version of the projects that are written, older ones are migrated up to it when they're opened
+pub const VERSION: u32 = 1;
+
+/// `MIGRATIONS[i]` upgrades a project from version i + 1 to i + 2, add one whenever the format changes
+/// in a way serde's defaults can't cover (renamed or restructured fields) and bump `VERSION`
+const MIGRATIONS: &[fn(&mut serde_json::Value)] = &[];
+
+const _: () = assert!(MIGRATIONS.len() + 1 == VERSION as usize);
+
+/// Everything needed to rebuild a scene, models are imported again from their files and the changes made to them are applied on top
+#[derive(Serialize, Deserialize)]
+pub struct Project {
+    pub version: u32,
+    pub camera: CameraEntry,
+    pub viewport: ViewportEntry,
+    pub ambient_light: [f32; 3],
+    /// hdri relative to the project
+    pub environment: Option<PathBuf>,
+    pub environment_settings: EnvironmentSettings,
+    pub lights: Vec<LightEntry>,
+    pub objects: Vec<ObjectEntry>,
+}
+
+impl Project {
+    /// Reads a project and migrates it to the current version
+    pub fn read(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let mut value: serde_json::Value = serde_json::from_str(&text)?;
+
+        let version = match value.get("version").and_then(|version| version.as_u64()) {
+            Some(version) if version >= 1 => version as u32,
+            _ => return Err("Project has no valid version".into()),
+        };
+        if version > VERSION {
+            return Err(format!("Project version {} is newer than the supported version {}", version, VERSION).into());
+        }
+
+        for migrate in &MIGRATIONS[version as usize - 1..] {
+            migrate(&mut value);
+        }
+        value["version"] = VERSION.into();
+
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+}
+
+/// Directory that paths in the project are relative to
+pub fn directory(project: &Path) -> PathBuf {
+    let directory = project.parent().unwrap_or(Path::new("."));
+    directory.canonicalize().unwrap_or_else(|_| directory.to_path_buf())
+}
+
+/// Relative to `directory` so projects can be moved along with their assets
+pub fn relative_path(path: &Path, directory: &Path) -> PathBuf {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let common = path.components().zip(directory.components()).take_while(|(a, b)| a == b).count();
+    // nothing in common, like different drives on windows
+    if common == 0 {
+        return path;
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in directory.components().skip(common) {
+        relative.push("..");
+    }
+    relative.extend(path.components().skip(common));
+
+    relative
+}
+
+pub fn resolve_path(path: &Path, directory: &Path) -> PathBuf {
+    if path.is_relative() {
+        directory.join(path)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+fn vec3(v: [f32; 3]) -> glm::Vec3 {
+    glm::vec3(v[0], v[1], v[2])
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CameraEntry {
+    pub position: [f32; 3],
+    /// in degrees
+    pub pitch: f32,
+    pub yaw: f32,
+    pub fov: f32,
+    pub speed: f32,
+}
+
+impl CameraEntry {
+    pub fn new(camera: &Camera) -> Self {
+        Self {
+            position: *camera.position.as_array(),
+            pitch: camera.pitch,
+            yaw: camera.yaw,
+            fov: camera.fov,
+            speed: camera.speed,
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.position = vec3(self.position);
+        camera.set_orientation(self.pitch, self.yaw);
+        camera.fov = self.fov;
+        camera.speed = self.speed;
+    }
+}
+
+/// Toggles and settings of the scene panel
+#[derive(Serialize, Deserialize)]
+pub struct ViewportEntry {
+    pub draw_grid: bool,
+    pub wireframe: bool,
+    pub msaa_samples: i32,
+    pub shading_model: ShadingModel,
+    pub tone_mapping: ToneMapping,
+    pub exposure: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LightEntry {
+    pub name: String,
+    pub kind: LightKind,
+    pub position: [f32; 3],
+    pub direction: [f32; 3],
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub range: f32,
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+    pub follow_camera: bool,
+    pub shadow: ShadowSettings,
+}
+
+impl LightEntry {
+    pub fn new(light: &Light) -> Self {
+        Self {
+            name: light.name.clone(),
+            kind: light.kind,
+            position: *light.position.as_array(),
+            direction: *light.direction.as_array(),
+            color: *light.color.as_array(),
+            intensity: light.intensity,
+            range: light.range,
+            inner_angle: light.inner_angle,
+            outer_angle: light.outer_angle,
+            follow_camera: light.follow_camera,
+            shadow: light.shadow,
+        }
+    }
+
+    pub fn light(&self) -> Light {
+        Light {
+            name: self.name.clone(),
+            kind: self.kind,
+            position: vec3(self.position),
+            direction: vec3(self.direction),
+            color: vec3(self.color),
+            intensity: self.intensity,
+            range: self.range,
+            inner_angle: self.inner_angle,
+            outer_angle: self.outer_angle,
+            follow_camera: self.follow_camera,
+            shadow: self.shadow,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TransformEntry {
+    pub translation: [f32; 3],
+    /// quaternion as x, y, z, w
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+    pub shear: Option<[f32; 3]>,
+}
+
+impl TransformEntry {
+    fn new(transform: &Transform) -> Self {
+        let rotation = transform.rotation;
+        Self {
+            translation: *transform.translation.as_array(),
+            rotation: [rotation.x, rotation.y, rotation.z, rotation.w],
+            scale: *transform.scale.as_array(),
+            shear: transform.shear.map(|shear| *shear.as_array()),
+        }
+    }
+
+    fn transform(&self) -> Transform {
+        let [x, y, z, w] = self.rotation;
+        Transform {
+            translation: vec3(self.translation),
+            rotation: Quat::new(x, y, z, w).normalize(),
+            scale: vec3(self.scale),
+            shear: self.shear.map(vec3),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum PivotEntry {
+    Origin,
+    BoundingBoxCenter([f32; 3]),
+    Custom([f32; 3]),
+}
+
+impl PivotEntry {
+    fn new(pivot: &Pivot) -> Self {
+        match pivot {
+            Pivot::Origin => PivotEntry::Origin,
+            Pivot::BoundingBoxCenter(point) => PivotEntry::BoundingBoxCenter(*point.as_array()),
+            Pivot::Custom(point) => PivotEntry::Custom(*point.as_array()),
+        }
+    }
+
+    fn pivot(&self) -> Pivot {
+        match self {
+            PivotEntry::Origin => Pivot::Origin,
+            PivotEntry::BoundingBoxCenter(point) => Pivot::BoundingBoxCenter(vec3(*point)),
+            PivotEntry::Custom(point) => Pivot::Custom(vec3(*point)),
+        }
+    }
+}
+
+/// A node that was moved, given a different pivot or reparented since it was imported
+#[derive(Serialize, Deserialize)]
+pub struct NodeEntry {
+    pub index: usize,
+    /// to find the node again if the file changed
+    pub name: String,
+    pub parent: Option<usize>,
+    pub parent_name: Option<String>,
+    pub transform: TransformEntry,
+    pub pivot: PivotEntry,
+}
+
+/// The material of a mesh that was edited since it was imported, the textures always come from the file
+#[derive(Serialize, Deserialize)]
+pub struct MaterialEntry {
+    pub mesh: usize,
+    pub name: String,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: [f32; 3],
+    pub emissive_strength: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    pub opacity: f32,
+    pub two_sided: bool,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+}
+
+impl MaterialEntry {
+    fn new(mesh: usize, material: &Material) -> Self {
+        Self {
+            mesh,
+            name: material.name.clone(),
+            ambient: *material.ambient.as_array(),
+            diffuse: *material.diffuse.as_array(),
+            specular: *material.specular.as_array(),
+            shininess: material.shininess,
+            base_color: *material.base_color.as_array(),
+            metallic: material.metallic,
+            roughness: material.roughness,
+            emissive: *material.emissive.as_array(),
+            emissive_strength: material.emissive_strength,
+            normal_scale: material.normal_scale,
+            occlusion_strength: material.occlusion_strength,
+            opacity: material.opacity,
+            two_sided: material.two_sided,
+            alpha_mode: material.alpha_mode,
+            alpha_cutoff: material.alpha_cutoff,
+        }
+    }
+
+    fn apply(&self, material: &mut Material) {
+        let [r, g, b, a] = self.base_color;
+        material.ambient = vec3(self.ambient);
+        material.diffuse = vec3(self.diffuse);
+        material.specular = vec3(self.specular);
+        material.shininess = self.shininess;
+        material.base_color = glm::vec4(r, g, b, a);
+        material.metallic = self.metallic;
+        material.roughness = self.roughness;
+        material.emissive = vec3(self.emissive);
+        material.emissive_strength = self.emissive_strength;
+        material.normal_scale = self.normal_scale;
+        material.occlusion_strength = self.occlusion_strength;
+        material.opacity = self.opacity;
+        material.two_sided = self.two_sided;
+        material.alpha_mode = self.alpha_mode;
+        material.alpha_cutoff = self.alpha_cutoff;
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ObjectEntry {
+    /// model file relative to the project
+    pub path: PathBuf,
+    pub import_settings: ImportSettings,
+    pub axis_conversion: AxisConversion,
+    pub nodes: Vec<NodeEntry>,
+    pub materials: Vec<MaterialEntry>,
+}
+
+impl ObjectEntry {
+    /// Only keeps what changed since the model was imported
+    pub fn new(model: &Model, directory: &Path) -> Self {
+        let nodes = model.nodes.iter().enumerate()
+            .filter(|(_, node)| node.local_transform != node.imported_transform || node.parent != node.imported_parent || node.pivot != Pivot::default())
+            .map(|(index, node)| NodeEntry {
+                index,
+                name: node.name.clone(),
+                parent: node.parent,
+                parent_name: node.parent.map(|parent| model.nodes[parent].name.clone()),
+                transform: TransformEntry::new(&node.local_transform),
+                pivot: PivotEntry::new(&node.pivot),
+            })
+            .collect();
+
+        let materials = model.meshes.iter().enumerate()
+            .filter(|(_, mesh)| mesh.material != mesh.imported_material)
+            .map(|(index, mesh)| MaterialEntry::new(index, &mesh.material))
+            .collect();
+
+        Self {
+            path: relative_path(&model.path, directory),
+            import_settings: model.import_settings.clone(),
+            axis_conversion: model.axis_conversion,
+            nodes,
+            materials,
+        }
+    }
+
+    /// Applies the saved changes to the freshly imported model, skipping the ones that don't match the file anymore
+    pub fn apply(&self, model: &mut Model, log: &mut Log) {
+        model.axis_conversion = self.axis_conversion;
+
+        // the hierarchy is restored first so the transforms end up relative to the right parents
+        let nodes: Vec<Option<usize>> = self.nodes.iter().map(|entry| find_node(model, entry.index, &entry.name)).collect();
+        for (entry, node) in self.nodes.iter().zip(&nodes) {
+            let (node, parent) = match (node, entry.parent, &entry.parent_name) {
+                (Some(node), Some(parent), Some(parent_name)) => (*node, find_node(model, parent, parent_name)),
+                _ => continue,
+            };
+            match parent {
+                Some(parent) => if model.can_reparent(node, parent) {
+                    model.reparent(node, (parent, usize::MAX, model.nodes[node].local_transform));
+                },
+                None => log.log(&format!("Parent of node \"{}\" of {} doesn't exist anymore, it's left where it is in the file", entry.name, model.name), LogLevel::Warning),
+            }
+        }
+
+        for (entry, node) in self.nodes.iter().zip(nodes) {
+            let node = match node {
+                Some(node) => node,
+                None => {
+                    log.log(&format!("Node \"{}\" of {} doesn't exist anymore, its changes are skipped", entry.name, model.name), LogLevel::Warning);
+                    continue;
+                },
+            };
+            model.nodes[node].local_transform = entry.transform.transform();
+            model.nodes[node].pivot = entry.pivot.pivot();
+        }
+
+        for entry in &self.materials {
+            match model.meshes.get_mut(entry.mesh).filter(|mesh| mesh.material.name == entry.name) {
+                Some(mesh) => entry.apply(&mut mesh.material),
+                None => log.log(&format!("Material \"{}\" of {} doesn't exist anymore, its changes are skipped", entry.name, model.name), LogLevel::Warning),
+            }
+        }
+    }
+}
+
+/// By index if the name still matches, otherwise by name in case nodes were added or removed from the file
+fn find_node(model: &Model, index: usize, name: &str) -> Option<usize> {
+    match model.nodes.get(index) {
+        Some(node) if node.name == name => Some(index),
+        _ => model.nodes.iter().position(|node| node.name == name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{animation::Animator, node::Node};
+
+    /// A root with the named nodes as its children
+    fn model(children: &[&str]) -> Model {
+        let mut nodes = vec![Node::new("root", Transform::default(), None)];
+        for name in children {
+            let idx = nodes.len();
+            nodes[0].children.push(idx);
+            nodes.push(Node::new(name, Transform::default(), Some(0)));
+        }
+
+        Model {
+            name: String::from("model"),
+            nodes,
+            meshes: vec![],
+            clips: vec![],
+            animator: Animator::default(),
+            axis_conversion: AxisConversion::default(),
+            imported_axis_conversion: AxisConversion::default(),
+            imported_lights: vec![],
+            path: PathBuf::from("model.gltf"),
+            import_settings: ImportSettings::default(),
+        }
+    }
+
+    #[test]
+    fn parents_are_found_by_name_when_the_file_changed() {
+        let mut saved = model(&["a", "b"]);
+        let transform = saved.transform_under(2, 1);
+        saved.reparent(2, (1, 0, transform));
+        let entry = ObjectEntry::new(&saved, Path::new("."));
+
+        // a node was added before "a" since the project was saved
+        let mut opened = model(&["added", "a", "b"]);
+        entry.apply(&mut opened, &mut Log::default());
+
+        assert_eq!(opened.nodes[3].parent, Some(2));
+        assert_eq!(opened.nodes[2].children, [3]);
+    }
+}
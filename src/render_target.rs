@@ -0,0 +1,224 @@
+use glad_gl::gl;
+
+use crate::gl_object::{Framebuffer, GlTexture};
+
+/// Storage format of a render target attachment
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    R8,
+    Rgba8,
+    Rgba16F,
+    /// integer ids, these can't be filtered or cleared with `gl::Clear`
+    Rgba32UI,
+    Depth24Stencil8,
+    Depth32F,
+}
+
+impl Format {
+    pub fn is_depth(&self) -> bool {
+        matches!(self, Format::Depth24Stencil8 | Format::Depth32F)
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Format::Rgba32UI)
+    }
+
+    /// (internal format, pixel format, pixel type)
+    fn gl_formats(&self) -> (u32, u32, u32) {
+        match self {
+            Format::R8 => (gl::R8, gl::RED, gl::UNSIGNED_BYTE),
+            Format::Rgba8 => (gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE),
+            Format::Rgba16F => (gl::RGBA16F, gl::RGBA, gl::FLOAT),
+            Format::Rgba32UI => (gl::RGBA32UI, gl::RGBA_INTEGER, gl::UNSIGNED_INT),
+            Format::Depth24Stencil8 => (gl::DEPTH24_STENCIL8, gl::DEPTH_STENCIL, gl::UNSIGNED_INT_24_8),
+            Format::Depth32F => (gl::DEPTH_COMPONENT32F, gl::DEPTH_COMPONENT, gl::FLOAT),
+        }
+    }
+
+    fn attachment(&self) -> u32 {
+        match self {
+            Format::Depth24Stencil8 => gl::DEPTH_STENCIL_ATTACHMENT,
+            Format::Depth32F => gl::DEPTH_ATTACHMENT,
+            _ => panic!("{:?} is a color format", self),
+        }
+    }
+}
+
+/// Framebuffer that owns its attachments and only reallocates them when its size changes
+pub struct RenderTarget {
+    fb: Framebuffer,
+    color_formats: Vec<Format>,
+    depth_format: Option<Format>,
+    colors: Vec<GlTexture>,
+    depth: Option<GlTexture>,
+    width: i32,
+    height: i32,
+    /// 1 means the attachments aren't multisampled
+    samples: i32,
+}
+
+/// Highest sample count the driver supports for multisampled attachments
+pub fn max_samples() -> i32 {
+    let mut samples = 1;
+    unsafe {
+        gl::GetIntegerv(gl::MAX_SAMPLES, &mut samples);
+    }
+
+    samples.max(1)
+}
+
+impl RenderTarget {
+    /// Attachments aren't allocated until the first `resize`
+    pub fn new(color_formats: &[Format], depth_format: Option<Format>) -> Self {
+        assert!(color_formats.iter().all(|f| !f.is_depth()), "Depth format used as a color attachment");
+        assert!(depth_format.iter().all(|f| f.is_depth()), "Color format used as a depth attachment");
+
+        Self {
+            fb: Framebuffer::new(),
+            color_formats: color_formats.to_vec(),
+            depth_format,
+            colors: vec![],
+            depth: None,
+            width: 0,
+            height: 0,
+            samples: 1,
+        }
+    }
+
+    /// Changes the sample count, clamped to what the driver supports.
+    /// The attachments are reallocated on the next `resize`
+    pub fn set_samples(&mut self, samples: i32) {
+        let samples = samples.clamp(1, max_samples());
+        if samples != self.samples {
+            self.samples = samples;
+            // force a reallocation
+            self.width = 0;
+            self.height = 0;
+        }
+    }
+
+    pub fn samples(&self) -> i32 {
+        self.samples
+    }
+
+    /// Reallocates the attachments if the size changed, returns whether it did
+    pub fn resize(&mut self, width: i32, height: i32) -> bool {
+        // imgui reports a zero size for collapsed or not yet laid out windows
+        let (width, height) = (width.max(1), height.max(1));
+        if width == self.width && height == self.height {
+            return false;
+        }
+
+        self.width = width;
+        self.height = height;
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.fb.id());
+
+            let target = self.texture_target();
+
+            self.colors = self.color_formats.iter().enumerate().map(|(i, format)| {
+                let texture = allocate_texture(*format, width, height, self.samples);
+                gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0 + i as u32, target, texture.id(), 0);
+                texture
+            }).collect();
+
+            self.depth = self.depth_format.map(|format| {
+                let texture = allocate_texture(format, width, height, self.samples);
+                gl::FramebufferTexture2D(gl::FRAMEBUFFER, format.attachment(), target, texture.id(), 0);
+                texture
+            });
+
+            let draw_buffers: Vec<u32> = (0..self.colors.len() as u32).map(|i| gl::COLOR_ATTACHMENT0 + i).collect();
+            if draw_buffers.is_empty() {
+                gl::DrawBuffer(gl::NONE);
+                gl::ReadBuffer(gl::NONE);
+            } else {
+                gl::DrawBuffers(draw_buffers.len() as i32, draw_buffers.as_ptr());
+            }
+
+            if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+                panic!("ERROR::FRAMEBUFFER:: Framebuffer is not complete!");
+            }
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+
+        true
+    }
+
+    /// Binds the framebuffer and sets the viewport to cover it
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.fb.id());
+            gl::Viewport(0, 0, self.width, self.height);
+        }
+    }
+
+    /// Blits every color attachment into the matching attachment of `dst`, resolving multisampled ones.
+    /// Both targets need the same size and number of color attachments
+    pub fn resolve(&self, dst: &RenderTarget) {
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.fb.id());
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, dst.fb.id());
+
+            for i in 0..self.colors.len() as u32 {
+                gl::ReadBuffer(gl::COLOR_ATTACHMENT0 + i);
+                gl::DrawBuffer(gl::COLOR_ATTACHMENT0 + i);
+                gl::BlitFramebuffer(
+                    0, 0, self.width, self.height,
+                    0, 0, dst.width, dst.height,
+                    gl::COLOR_BUFFER_BIT, gl::NEAREST);
+            }
+
+            // restore the draw buffers that were set up in `resize`
+            let draw_buffers: Vec<u32> = (0..dst.colors.len() as u32).map(|i| gl::COLOR_ATTACHMENT0 + i).collect();
+            gl::DrawBuffers(draw_buffers.len() as i32, draw_buffers.as_ptr());
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    fn texture_target(&self) -> u32 {
+        if self.samples > 1 { gl::TEXTURE_2D_MULTISAMPLE } else { gl::TEXTURE_2D }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.fb.id()
+    }
+
+    pub fn color(&self, i: usize) -> &GlTexture {
+        &self.colors[i]
+    }
+
+    pub fn depth(&self) -> Option<&GlTexture> {
+        self.depth.as_ref()
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+}
+
+unsafe fn allocate_texture(format: Format, width: i32, height: i32, samples: i32) -> GlTexture {
+    let texture = GlTexture::new();
+    let (internal, pixel_format, typ) = format.gl_formats();
+
+    if samples > 1 {
+        // multisampled textures can't be filtered, they're only read by resolving them
+        gl::BindTexture(gl::TEXTURE_2D_MULTISAMPLE, texture.id());
+        gl::TexImage2DMultisample(gl::TEXTURE_2D_MULTISAMPLE, samples, internal, width, height, gl::TRUE);
+        return texture;
+    }
+
+    let filter = if format.is_depth() || format.is_integer() { gl::NEAREST } else { gl::LINEAR };
+
+    gl::BindTexture(gl::TEXTURE_2D, texture.id());
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+    gl::TexImage2D(gl::TEXTURE_2D, 0, internal as i32, width, height, 0, pixel_format, typ, std::ptr::null());
+
+    texture
+}
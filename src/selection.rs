@@ -0,0 +1,178 @@
+use glad_gl::gl;
+
+use crate::{gl_object::VertexArray, model::Model, render_target::{Format, RenderTarget}, shader::Shader};
+
+/// Screen space color of the outline around the selection, drawn after tone mapping
+const OUTLINE_COLOR: [f32; 3] = [1.0, 0.55, 0.1];
+/// In pixels
+const OUTLINE_WIDTH: i32 = 2;
+
+/// A node of an object, or a single mesh of that node
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Selection {
+    pub object: usize,
+    pub node: usize,
+    pub mesh: Option<usize>,
+}
+
+impl Selection {
+    pub fn node(object: usize, node: usize) -> Self {
+        Self {
+            object,
+            node,
+            mesh: None,
+        }
+    }
+
+    /// Whether the mesh drawn by `node` is selected, a selected node selects everything below it
+    pub fn contains(&self, model: &Model, node: usize, mesh: usize) -> bool {
+        match self.mesh {
+            Some(selected) => node == self.node && mesh == selected,
+            None => model.is_descendant(node, self.node),
+        }
+    }
+
+    /// Whether `node` has to be expanded in the objects tree for the selection to show
+    pub fn is_below(&self, model: &Model, node: usize) -> bool {
+        model.is_descendant(self.node, node) && (node != self.node || self.mesh.is_some())
+    }
+
+    /// Fixes up the object index after an object was removed, returns None if the selected one was
+    pub fn object_removed(self, removed: usize) -> Option<Self> {
+        if self.object == removed {
+            None
+        } else if self.object > removed {
+            Some(Self { object: self.object - 1, ..self })
+        } else {
+            Some(self)
+        }
+    }
+
+    /// Fixes up the object index after an object was inserted before the end
+    pub fn object_inserted(self, inserted: usize) -> Self {
+        if self.object >= inserted {
+            Self { object: self.object + 1, ..self }
+        } else {
+            self
+        }
+    }
+}
+
+/// Finds what's under the cursor by drawing the ids of every mesh and reading back a single pixel
+pub struct Picker {
+    shader: Shader,
+    target: RenderTarget,
+}
+
+impl Picker {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            shader: Shader::new("shaders/vertex.glsl", "shaders/pick_f.glsl")?,
+            target: RenderTarget::new(&[Format::Rgba32UI], Some(Format::Depth24Stencil8)),
+        })
+    }
+
+    /// `pixel` is in the scene's framebuffer coordinates, with the origin in the bottom left
+    pub fn pick(&mut self, objects: &[Model], pixel: [i32; 2], size: (i32, i32), view: &glm::Mat4, projection: &glm::Mat4) -> Option<Selection> {
+        self.target.resize(size.0, size.1);
+        let (width, height) = self.target.size();
+        if pixel[0] < 0 || pixel[1] < 0 || pixel[0] >= width || pixel[1] >= height {
+            return None;
+        }
+
+        self.target.bind();
+        self.shader.use_shader();
+        self.shader.set_mat4fv("view", view);
+        self.shader.set_mat4fv("projection", projection);
+
+        let mut id = [0u32; 4];
+        unsafe {
+            gl::Enable(gl::DEPTH_TEST);
+            gl::Disable(gl::BLEND);
+            gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+            // 0 is the background, the ids are offset by one
+            gl::ClearBufferuiv(gl::COLOR, 0, id.as_ptr());
+            gl::Clear(gl::DEPTH_BUFFER_BIT);
+
+            for (object_idx, object) in objects.iter().enumerate() {
+                object.draw_filtered(&self.shader, |node, mesh| {
+                    self.shader.set_int("objectId", object_idx as i32 + 1);
+                    self.shader.set_int("nodeId", node as i32);
+                    self.shader.set_int("meshId", mesh as i32);
+                    true
+                });
+            }
+
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+            gl::ReadPixels(pixel[0], pixel[1], 1, 1, gl::RGBA_INTEGER, gl::UNSIGNED_INT, id.as_mut_ptr() as *mut std::ffi::c_void);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+
+        if id[0] == 0 {
+            return None;
+        }
+
+        Some(Selection {
+            object: id[0] as usize - 1,
+            node: id[1] as usize,
+            mesh: Some(id[2] as usize),
+        })
+    }
+}
+
+/// Draws a screen space outline around the silhouette of the selection
+pub struct Outline {
+    mask_shader: Shader,
+    outline_shader: Shader,
+    mask: RenderTarget,
+    // core profile won't draw without a vao, even if there are no attributes
+    vao: VertexArray,
+}
+
+impl Outline {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            mask_shader: Shader::new("shaders/vertex.glsl", "shaders/mask_f.glsl")?,
+            outline_shader: Shader::new("shaders/post_v.glsl", "shaders/outline_f.glsl")?,
+            mask: RenderTarget::new(&[Format::R8], None),
+            vao: VertexArray::new(),
+        })
+    }
+
+    /// Draws into `target`, the outline shows through everything in front of the selection
+    pub fn draw(&mut self, objects: &[Model], selection: &Selection, target: &RenderTarget, view: &glm::Mat4, projection: &glm::Mat4) {
+        let object = match objects.get(selection.object) {
+            Some(object) => object,
+            None => return,
+        };
+
+        let (width, height) = target.size();
+        self.mask.resize(width, height);
+        self.mask.bind();
+        self.mask_shader.use_shader();
+        self.mask_shader.set_mat4fv("view", view);
+        self.mask_shader.set_mat4fv("projection", projection);
+
+        unsafe {
+            gl::Disable(gl::DEPTH_TEST);
+            gl::Disable(gl::BLEND);
+            gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+            gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+
+            object.draw_filtered(&self.mask_shader, |node, mesh| selection.contains(object, node, mesh));
+
+            target.bind();
+            self.outline_shader.use_shader();
+            self.outline_shader.set_int("mask", 0);
+            self.outline_shader.set_int("width", OUTLINE_WIDTH);
+            self.outline_shader.set_3fv("color", glm::vec3(OUTLINE_COLOR[0], OUTLINE_COLOR[1], OUTLINE_COLOR[2]));
+
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, self.mask.color(0).id());
+            gl::BindVertexArray(self.vao.id());
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            gl::BindVertexArray(0);
+        }
+    }
+}
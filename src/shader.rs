@@ -1,8 +1,10 @@
 use glad_gl::gl;
 use anyhow::{Context, Result};
 
+use crate::gl_object::Program;
+
 pub struct Shader {
-    pub program_id: gl::GLuint,
+    pub program: Program,
 }
 
 impl Shader {
@@ -49,44 +51,44 @@ impl Shader {
                 println!("frag shader info: {}", std::str::from_utf8(&info_buf2).unwrap());
             }
 
-            let shader_program = gl::CreateProgram();
-            gl::AttachShader(shader_program, vertex_shader);
-            gl::AttachShader(shader_program, frag_shader);
-            gl::LinkProgram(shader_program);
+            let shader_program = Program::new();
+            gl::AttachShader(shader_program.id(), vertex_shader);
+            gl::AttachShader(shader_program.id(), frag_shader);
+            gl::LinkProgram(shader_program.id());
 
             gl::DeleteShader(vertex_shader);
             gl::DeleteShader(frag_shader);
 
             Ok(Self {
-                program_id: shader_program,
+                program: shader_program,
             })
         }
     }
 
     pub fn use_shader(&self) {
         unsafe {
-            gl::UseProgram(self.program_id);
+            gl::UseProgram(self.program.id());
         }
     }
 
     pub fn set_bool(&self, name: &str, value: bool) {
         unsafe {
             let c_str = std::ffi::CString::new(name).unwrap();
-            gl::Uniform1i(gl::GetUniformLocation(self.program_id, c_str.as_ptr()), value as i32);
+            gl::Uniform1i(gl::GetUniformLocation(self.program.id(), c_str.as_ptr()), value as i32);
         }
     }
 
     pub fn set_int(&self, name: &str, value: i32) {
         let c_str = std::ffi::CString::new(name).unwrap();
         unsafe {
-            gl::Uniform1i(gl::GetUniformLocation(self.program_id, c_str.as_ptr()), value);
+            gl::Uniform1i(gl::GetUniformLocation(self.program.id(), c_str.as_ptr()), value);
         }
     }
 
     pub fn set_float(&self, name: &str, value: f32) {
         let c_str = std::ffi::CString::new(name).unwrap();
         unsafe {
-            gl::Uniform1f(gl::GetUniformLocation(self.program_id, c_str.as_ptr()), value);
+            gl::Uniform1f(gl::GetUniformLocation(self.program.id(), c_str.as_ptr()), value);
         }
     }
 
@@ -95,7 +97,7 @@ impl Shader {
         let mut value = 0.0;
 
         unsafe {
-            gl::GetUniformfv(self.program_id, gl::GetUniformLocation(self.program_id, c_str.as_ptr()), &mut value);
+            gl::GetUniformfv(self.program.id(), gl::GetUniformLocation(self.program.id(), c_str.as_ptr()), &mut value);
             value
         }
     }
@@ -103,14 +105,35 @@ impl Shader {
     pub fn set_mat4fv(&self, name: &str, value: &glm::Mat4) {
         let c_str = std::ffi::CString::new(name).unwrap();
         unsafe {
-            gl::UniformMatrix4fv(gl::GetUniformLocation(self.program_id, c_str.as_ptr()), 1, gl::FALSE, value.as_array().as_ptr() as *const f32);
+            gl::UniformMatrix4fv(gl::GetUniformLocation(self.program.id(), c_str.as_ptr()), 1, gl::FALSE, value.as_array().as_ptr() as *const f32);
         }
     }
 
     pub fn set_3fv(&self, name: &str, value: glm::Vec3) {
         let c_str = std::ffi::CString::new(name).unwrap();
         unsafe {
-            gl::Uniform3fv(gl::GetUniformLocation(self.program_id, c_str.as_ptr()), 1, value.as_array() as *const f32);
+            gl::Uniform3fv(gl::GetUniformLocation(self.program.id(), c_str.as_ptr()), 1, value.as_array() as *const f32);
+        }
+    }
+
+    pub fn set_4fv(&self, name: &str, value: glm::Vec4) {
+        let c_str = std::ffi::CString::new(name).unwrap();
+        unsafe {
+            gl::Uniform4fv(gl::GetUniformLocation(self.program.id(), c_str.as_ptr()), 1, value.as_array() as *const f32);
+        }
+    }
+
+    pub fn set_mat4fv_array(&self, name: &str, values: &[glm::Mat4]) {
+        let c_str = std::ffi::CString::new(name).unwrap();
+        unsafe {
+            gl::UniformMatrix4fv(gl::GetUniformLocation(self.program.id(), c_str.as_ptr()), values.len() as i32, gl::FALSE, values.as_ptr() as *const f32);
+        }
+    }
+
+    pub fn set_float_array(&self, name: &str, values: &[f32]) {
+        let c_str = std::ffi::CString::new(name).unwrap();
+        unsafe {
+            gl::Uniform1fv(gl::GetUniformLocation(self.program.id(), c_str.as_ptr()), values.len() as i32, values.as_ptr());
         }
     }
 }
@@ -0,0 +1,370 @@
+use glad_gl::gl;
+use serde::{Deserialize, Serialize};
+
+use crate::{gl_object::{Framebuffer, GlTexture, VertexArray}, light::{Light, LightKind}, model::Model, render_target::{Format, RenderTarget}, shader::Shader, utils};
+
+/// Lights of each kind that can cast shadows at once, the rest are lit without them
+pub const MAX_DIR_SHADOWS: usize = 2;
+pub const MAX_SPOT_SHADOWS: usize = 4;
+pub const MAX_POINT_SHADOWS: usize = 4;
+
+const DIR_SHADOW_SIZE: i32 = 2048;
+const SPOT_SHADOW_SIZE: i32 = 1024;
+const POINT_SHADOW_SIZE: i32 = 512;
+const PREVIEW_SIZE: i32 = 256;
+
+const SHADOW_NEAR: f32 = 0.05;
+/// Used by spot lights without a range
+const SPOT_SHADOW_FAR: f32 = 50.0;
+/// Point shadow maps store the distance to the light divided by this
+const POINT_SHADOW_FAR: f32 = 25.0;
+
+/// Units after the material maps and before the morph targets.
+/// The directional maps, the spot maps, then the point maps
+const SHADOW_TEXTURE_UNIT: u32 = 24;
+
+/// Looking directions and up vectors of the cube map faces, in the order gl expects them
+pub(crate) const CUBE_FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, -1.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+];
+
+/// How a single light's shadows are filtered and biased
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    /// subtracted from the depth before comparing, in shadow map depth units
+    pub bias: f32,
+    /// offsets the receiving point along its normal, in world units
+    pub normal_bias: f32,
+    /// 0 takes a single sample, n takes (2n + 1)^2 samples
+    pub pcf_radius: i32,
+}
+
+impl ShadowSettings {
+    /// Perspective and distance maps need different biases than orthographic ones
+    pub fn new(kind: LightKind) -> Self {
+        Self {
+            enabled: true,
+            bias: match kind {
+                LightKind::Directional => 0.001,
+                LightKind::Spot => 0.0002,
+                LightKind::Point => 0.002,
+            },
+            normal_bias: 0.02,
+            pcf_radius: 1,
+        }
+    }
+}
+
+/// Depth maps of one kind of light, one layer per light
+struct ShadowArray {
+    texture: GlTexture,
+    /// TEXTURE_2D_ARRAY or TEXTURE_CUBE_MAP_ARRAY
+    target: u32,
+    size: i32,
+    layers: usize,
+}
+
+impl ShadowArray {
+    fn new(target: u32, size: i32) -> Self {
+        let mut array = Self {
+            texture: GlTexture::new(),
+            target,
+            size,
+            layers: 0,
+        };
+        // samplers need something to read from even when nothing casts shadows
+        array.resize(1);
+
+        array
+    }
+
+    fn resize(&mut self, layers: usize) {
+        let layers = layers.max(1);
+        if layers == self.layers {
+            return;
+        }
+        self.layers = layers;
+
+        // cube map arrays have a layer per face
+        let depth = if self.target == gl::TEXTURE_CUBE_MAP_ARRAY { layers * 6 } else { layers };
+
+        self.texture = GlTexture::new();
+        unsafe {
+            gl::BindTexture(self.target, self.texture.id());
+            gl::TexImage3D(self.target, 0, gl::DEPTH_COMPONENT32F as i32, self.size, self.size, depth as i32, 0, gl::DEPTH_COMPONENT, gl::FLOAT, std::ptr::null());
+            gl::TexParameteri(self.target, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(self.target, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(self.target, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(self.target, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(self.target, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+        }
+    }
+
+    /// Binds `fb` to render into a single layer, or a single face of a cube map layer
+    fn bind_layer(&self, fb: &Framebuffer, layer: usize) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fb.id());
+            gl::FramebufferTextureLayer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, self.texture.id(), 0, layer as i32);
+            gl::DrawBuffer(gl::NONE);
+            gl::ReadBuffer(gl::NONE);
+
+            if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+                panic!("ERROR::FRAMEBUFFER:: Framebuffer is not complete!");
+            }
+
+            gl::Viewport(0, 0, self.size, self.size);
+            gl::Clear(gl::DEPTH_BUFFER_BIT);
+        }
+    }
+}
+
+/// Shadow maps of every light that casts shadows
+pub struct ShadowMaps {
+    depth_shader: Shader,
+    distance_shader: Shader,
+    preview_shader: Shader,
+    preview_vao: VertexArray,
+    fb: Framebuffer,
+    dir: ShadowArray,
+    spot: ShadowArray,
+    point: ShadowArray,
+    /// layer in the array of the light's kind (-1 without shadows) and light space matrix of every light
+    assignments: Vec<(i32, glm::Mat4)>,
+    /// grayscale copies of the maps for the debug window, same order as the lights
+    previews: Vec<Option<RenderTarget>>,
+}
+
+impl ShadowMaps {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            // the regular vertex shader so skinning and morph targets cast the right shadows
+            depth_shader: Shader::new("shaders/vertex.glsl", "shaders/shadow_f.glsl")?,
+            distance_shader: Shader::new("shaders/vertex.glsl", "shaders/shadow_point_f.glsl")?,
+            preview_shader: Shader::new("shaders/post_v.glsl", "shaders/shadow_preview_f.glsl")?,
+            preview_vao: VertexArray::new(),
+            fb: Framebuffer::new(),
+            dir: ShadowArray::new(gl::TEXTURE_2D_ARRAY, DIR_SHADOW_SIZE),
+            spot: ShadowArray::new(gl::TEXTURE_2D_ARRAY, SPOT_SHADOW_SIZE),
+            point: ShadowArray::new(gl::TEXTURE_CUBE_MAP_ARRAY, POINT_SHADOW_SIZE),
+            assignments: vec![],
+            previews: vec![],
+        })
+    }
+
+    /// Renders the depth of every object from each light that has shadows enabled
+    pub fn render(&mut self, objects: &[Model], lights: &[Light]) {
+        self.assign(objects, lights);
+
+        unsafe {
+            gl::Enable(gl::DEPTH_TEST);
+            gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+        }
+
+        for (light, (layer, light_space)) in lights.iter().zip(&self.assignments) {
+            if *layer < 0 {
+                continue;
+            }
+            let layer = *layer as usize;
+
+            match light.kind {
+                LightKind::Directional => {
+                    self.dir.bind_layer(&self.fb, layer);
+                    draw_depth(&self.depth_shader, objects, &utils::mat_ident(), light_space);
+                },
+                LightKind::Spot => {
+                    self.spot.bind_layer(&self.fb, layer);
+                    draw_depth(&self.depth_shader, objects, &utils::mat_ident(), light_space);
+                },
+                LightKind::Point => {
+                    let projection = glm::ext::perspective(glm::radians(90.0), 1.0, SHADOW_NEAR, POINT_SHADOW_FAR);
+                    self.distance_shader.use_shader();
+                    self.distance_shader.set_float("farPlane", POINT_SHADOW_FAR);
+                    self.distance_shader.set_3fv("lightPos", light.position);
+
+                    for (face, (dir, up)) in CUBE_FACES.iter().enumerate() {
+                        let view = glm::ext::look_at(light.position, light.position + glm::vec3(dir[0], dir[1], dir[2]), glm::vec3(up[0], up[1], up[2]));
+                        self.point.bind_layer(&self.fb, layer * 6 + face);
+                        draw_depth(&self.distance_shader, objects, &view, &projection);
+                    }
+                },
+            }
+        }
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /// Gives every light that casts shadows a layer and its light space matrix
+    fn assign(&mut self, objects: &[Model], lights: &[Light]) {
+        // still needs a valid matrix when the scene is empty
+        let bounds = scene_bounds(objects).unwrap_or((glm::vec3(-1.0, -1.0, -1.0), glm::vec3(1.0, 1.0, 1.0)));
+        let mut counts = [0usize; 3];
+
+        self.assignments = lights.iter().map(|light| {
+            let max = match light.kind {
+                LightKind::Directional => MAX_DIR_SHADOWS,
+                LightKind::Point => MAX_POINT_SHADOWS,
+                LightKind::Spot => MAX_SPOT_SHADOWS,
+            };
+            let count = &mut counts[light.kind.index()];
+            if !light.shadow.enabled || *count >= max {
+                return (-1, utils::mat_ident());
+            }
+
+            let layer = *count as i32;
+            *count += 1;
+
+            let light_space = match light.kind {
+                LightKind::Directional => fit_dir_light(bounds, light.direction),
+                LightKind::Spot => {
+                    let view = glm::ext::look_at(light.position, light.position + light.direction, up_for(light.direction));
+                    glm::ext::perspective(glm::radians(light.outer_angle * 2.0), 1.0, SHADOW_NEAR, spot_far(light)) * view
+                },
+                // point lights are rendered face by face
+                LightKind::Point => utils::mat_ident(),
+            };
+
+            (layer, light_space)
+        }).collect();
+
+        self.dir.resize(counts[LightKind::Directional.index()]);
+        self.point.resize(counts[LightKind::Point.index()]);
+        self.spot.resize(counts[LightKind::Spot.index()]);
+    }
+
+    /// Shadow map layer and light space matrix of every light, same order as the lights passed to `render`
+    pub fn assignments(&self) -> &[(i32, glm::Mat4)] {
+        &self.assignments
+    }
+
+    /// Binds the maps and sets the shadow uniforms of a lighting shader
+    pub fn bind(&self, shader: &Shader) {
+        shader.use_shader();
+
+        shader.set_float("pointShadowFar", POINT_SHADOW_FAR);
+        shader.set_int("dirShadowMaps", SHADOW_TEXTURE_UNIT as i32);
+        shader.set_int("spotShadowMaps", SHADOW_TEXTURE_UNIT as i32 + 1);
+        shader.set_int("pointShadowMaps", SHADOW_TEXTURE_UNIT as i32 + 2);
+
+        unsafe {
+            for (i, array) in [&self.dir, &self.spot, &self.point].iter().enumerate() {
+                gl::ActiveTexture(gl::TEXTURE0 + SHADOW_TEXTURE_UNIT + i as u32);
+                gl::BindTexture(array.target, array.texture.id());
+            }
+
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+    }
+
+    /// Draws the map of every light that casts shadows as a grayscale image for the debug window,
+    /// cube maps are unfolded into a 3x2 grid
+    pub fn draw_previews(&mut self, lights: &[Light]) {
+        self.previews.resize_with(lights.len(), || None);
+
+        self.preview_shader.use_shader();
+        self.preview_shader.set_int("depthMaps", 0);
+        self.preview_shader.set_int("cubeMaps", 1);
+        self.preview_shader.set_float("near", SHADOW_NEAR);
+
+        unsafe {
+            gl::Disable(gl::DEPTH_TEST);
+            gl::Disable(gl::BLEND);
+            gl::BindVertexArray(self.preview_vao.id());
+        }
+
+        for (i, light) in lights.iter().enumerate() {
+            let layer = self.assignments.get(i).map_or(-1, |(layer, _)| *layer);
+            if layer < 0 {
+                self.previews[i] = None;
+                continue;
+            }
+
+            let width = if light.kind == LightKind::Point { PREVIEW_SIZE * 3 / 2 } else { PREVIEW_SIZE };
+            let preview = self.previews[i].get_or_insert_with(|| RenderTarget::new(&[Format::Rgba8], None));
+            preview.resize(width, PREVIEW_SIZE);
+
+            // 0 ortho depth, 1 perspective depth, 2 distance cube map
+            let (mode, far) = match light.kind {
+                LightKind::Directional => (0, 1.0),
+                LightKind::Spot => (1, spot_far(light)),
+                LightKind::Point => (2, POINT_SHADOW_FAR),
+            };
+            self.preview_shader.set_int("mode", mode);
+            self.preview_shader.set_int("layer", layer);
+            self.preview_shader.set_float("far", far);
+
+            let depth_maps = if light.kind == LightKind::Directional { &self.dir } else { &self.spot };
+            unsafe {
+                gl::ActiveTexture(gl::TEXTURE0);
+                gl::BindTexture(gl::TEXTURE_2D_ARRAY, depth_maps.texture.id());
+                gl::ActiveTexture(gl::TEXTURE1);
+                gl::BindTexture(gl::TEXTURE_CUBE_MAP_ARRAY, self.point.texture.id());
+
+                preview.bind();
+                gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            }
+        }
+
+        unsafe {
+            gl::BindVertexArray(0);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /// Preview texture of every light that casts shadows, empty until `draw_previews` is called
+    pub fn previews(&self) -> Vec<Option<u32>> {
+        self.previews.iter().map(|preview| preview.as_ref().map(|preview| preview.color(0).id())).collect()
+    }
+}
+
+fn draw_depth(shader: &Shader, objects: &[Model], view: &glm::Mat4, projection: &glm::Mat4) {
+    shader.use_shader();
+    shader.set_mat4fv("view", view);
+    shader.set_mat4fv("projection", projection);
+
+    for obj in objects {
+        obj.draw(shader);
+    }
+}
+
+fn spot_far(light: &Light) -> f32 {
+    if light.range > 0.0 { light.range } else { SPOT_SHADOW_FAR }
+}
+
+/// An up vector that isn't parallel to `dir`
+fn up_for(dir: glm::Vec3) -> glm::Vec3 {
+    if glm::abs(glm::normalize(dir).y) > 0.99 {
+        glm::vec3(0.0, 0.0, 1.0)
+    } else {
+        glm::vec3(0.0, 1.0, 0.0)
+    }
+}
+
+/// Bounds of every object in world space
+fn scene_bounds(objects: &[Model]) -> Option<(glm::Vec3, glm::Vec3)> {
+    objects.iter().filter_map(|obj| obj.world_bounds()).reduce(|(min_a, max_a), (min_b, max_b)| {
+        (glm::min(min_a, min_b), glm::max(max_a, max_b))
+    })
+}
+
+/// Orthographic light space matrix that tightly covers `bounds`
+fn fit_dir_light(bounds: (glm::Vec3, glm::Vec3), direction: glm::Vec3) -> glm::Mat4 {
+    let (min, max) = bounds;
+    let center = (min + max) * 0.5;
+    let radius = glm::length(max - min).max(0.01) * 0.5;
+
+    let direction = glm::normalize(direction);
+    let eye = center - direction * (radius * 2.0);
+    let view = glm::ext::look_at(eye, center, up_for(direction));
+
+    // a sphere around the box so the map doesn't change size as the light or objects rotate
+    utils::ortho(-radius, radius, -radius, radius, radius, radius * 3.0) * view
+}
@@ -0,0 +1,462 @@
+use crate::utils;
+
+const EPSILON: f32 = 1e-5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Quat {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Quat { x, y, z, w }
+    }
+
+    pub fn identity() -> Self {
+        Quat::new(0.0, 0.0, 0.0, 1.0)
+    }
+
+    pub fn from_axis_angle(axis: glm::Vec3, angle: f32) -> Self {
+        let axis = glm::normalize(axis);
+        let (s, c) = (angle * 0.5).sin_cos();
+        Quat::new(axis.x * s, axis.y * s, axis.z * s, c)
+    }
+
+    /// Builds a quaternion from a pure rotation matrix (orthonormal, determinant of 1)
+    pub fn from_mat3(m: &glm::Mat3) -> Self {
+        // r(row, col), glm matrices are indexed by column first
+        let r = |row: usize, col: usize| m[col][row];
+        let trace = r(0, 0) + r(1, 1) + r(2, 2);
+
+        // source: https://www.euclideanspace.com/maths/geometry/rotations/conversions/matrixToQuaternion/
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Quat::new(
+                (r(2, 1) - r(1, 2)) / s,
+                (r(0, 2) - r(2, 0)) / s,
+                (r(1, 0) - r(0, 1)) / s,
+                0.25 * s,
+            )
+        } else if r(0, 0) > r(1, 1) && r(0, 0) > r(2, 2) {
+            let s = (1.0 + r(0, 0) - r(1, 1) - r(2, 2)).sqrt() * 2.0;
+            Quat::new(
+                0.25 * s,
+                (r(0, 1) + r(1, 0)) / s,
+                (r(0, 2) + r(2, 0)) / s,
+                (r(2, 1) - r(1, 2)) / s,
+            )
+        } else if r(1, 1) > r(2, 2) {
+            let s = (1.0 + r(1, 1) - r(0, 0) - r(2, 2)).sqrt() * 2.0;
+            Quat::new(
+                (r(0, 1) + r(1, 0)) / s,
+                0.25 * s,
+                (r(1, 2) + r(2, 1)) / s,
+                (r(0, 2) - r(2, 0)) / s,
+            )
+        } else {
+            let s = (1.0 + r(2, 2) - r(0, 0) - r(1, 1)).sqrt() * 2.0;
+            Quat::new(
+                (r(0, 2) + r(2, 0)) / s,
+                (r(1, 2) + r(2, 1)) / s,
+                0.25 * s,
+                (r(1, 0) - r(0, 1)) / s,
+            )
+        };
+
+        q.normalize()
+    }
+
+    pub fn to_mat3(&self) -> glm::Mat3 {
+        let Quat { x, y, z, w } = *self;
+
+        glm::mat3(
+            1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y + w * z), 2.0 * (x * z - w * y),
+            2.0 * (x * y - w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z + w * x),
+            2.0 * (x * z + w * y), 2.0 * (y * z - w * x), 1.0 - 2.0 * (x * x + y * y),
+        )
+    }
+
+    /// Rotation from euler angles in degrees, applied as X * Y * Z like the editor shows them
+    pub fn from_euler(degrees: glm::Vec3) -> Self {
+        let qx = Quat::from_axis_angle(glm::vec3(1.0, 0.0, 0.0), degrees.x.to_radians());
+        let qy = Quat::from_axis_angle(glm::vec3(0.0, 1.0, 0.0), degrees.y.to_radians());
+        let qz = Quat::from_axis_angle(glm::vec3(0.0, 0.0, 1.0), degrees.z.to_radians());
+
+        qx * qy * qz
+    }
+
+    /// Euler angles in degrees, the inverse of `from_euler`
+    pub fn to_euler(&self) -> glm::Vec3 {
+        let m = self.to_mat3();
+        let r = |row: usize, col: usize| m[col][row];
+
+        // source: https://www.geometrictools.com/Documentation/EulerAngles.pdf
+        let (pitch, yaw, roll) = if r(0, 2) < 1.0 - EPSILON {
+            if r(0, 2) > -1.0 + EPSILON {
+                ((-r(1, 2)).atan2(r(2, 2)), r(0, 2).asin(), (-r(0, 1)).atan2(r(0, 0)))
+            } else {
+                (-(r(1, 0).atan2(r(1, 1))), -std::f32::consts::FRAC_PI_2, 0.0)
+            }
+        } else {
+            (r(1, 0).atan2(r(1, 1)), std::f32::consts::FRAC_PI_2, 0.0)
+        };
+
+        glm::vec3(pitch.to_degrees(), yaw.to_degrees(), roll.to_degrees())
+    }
+
+    pub fn dot(&self, other: &Quat) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    pub fn length(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Self {
+        let len = self.length();
+        if len < EPSILON {
+            return Quat::identity();
+        }
+
+        Quat::new(self.x / len, self.y / len, self.z / len, self.w / len)
+    }
+
+    pub fn conjugate(&self) -> Self {
+        Quat::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    pub fn rotate(&self, v: glm::Vec3) -> glm::Vec3 {
+        self.to_mat3() * v
+    }
+
+    /// Spherical interpolation along the shortest arc
+    pub fn slerp(&self, other: &Quat, t: f32) -> Self {
+        let mut cos = self.dot(other);
+        let mut other = *other;
+        // q and -q are the same rotation, flip one so we don't go the long way around
+        if cos < 0.0 {
+            cos = -cos;
+            other = Quat::new(-other.x, -other.y, -other.z, -other.w);
+        }
+
+        // nearly the same rotation, sin(theta) is too close to 0 so just lerp
+        let (a, b) = if cos > 1.0 - EPSILON {
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+
+        Quat::new(
+            self.x * a + other.x * b,
+            self.y * a + other.y * b,
+            self.z * a + other.z * b,
+            self.w * a + other.w * b,
+        ).normalize()
+    }
+}
+
+impl std::ops::Mul for Quat {
+    type Output = Quat;
+
+    fn mul(self, rhs: Quat) -> Quat {
+        Quat::new(
+            self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+            self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+        )
+    }
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Quat::identity()
+    }
+}
+
+/// Translation, rotation and scale of a node, composed as T * R * H * S
+/// where H is an optional shear that only shows up when a matrix from a file
+/// can't be represented by a plain TRS.
+/// A negative scale component means the transform is a reflection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: glm::Vec3,
+    pub rotation: Quat,
+    pub scale: glm::Vec3,
+    /// xy, xz and yz shear factors
+    pub shear: Option<glm::Vec3>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: glm::vec3(0.0, 0.0, 0.0),
+            rotation: Quat::identity(),
+            scale: glm::vec3(1.0, 1.0, 1.0),
+            shear: None,
+        }
+    }
+}
+
+impl Transform {
+    pub fn new(translation: glm::Vec3, rotation: Quat, scale: glm::Vec3) -> Self {
+        Transform {
+            translation,
+            rotation,
+            scale,
+            shear: None,
+        }
+    }
+
+    /// Exact decomposition of an affine matrix, the last row is assumed to be (0, 0, 0, 1)
+    pub fn from_mat4(m: &glm::Mat4) -> Self {
+        let translation = glm::vec3(m.c3.x, m.c3.y, m.c3.z);
+
+        let mut x = glm::vec3(m.c0.x, m.c0.y, m.c0.z);
+        let mut y = glm::vec3(m.c1.x, m.c1.y, m.c1.z);
+        let mut z = glm::vec3(m.c2.x, m.c2.y, m.c2.z);
+
+        // a negative determinant means one axis is mirrored, flip x so the rest of the
+        // decomposition works on a proper rotation and put the sign back into the scale
+        let reflected = glm::dot(x, glm::cross(y, z)) < 0.0;
+        if reflected {
+            x = x * -1.0;
+        }
+
+        // gram-schmidt, whatever isn't orthogonal after removing the scale is shear.
+        // an axis that's scaled to nothing has no direction, it's left at 0 and rebuilt from the others after
+        let scale_x = length_or_zero(x);
+        x = x * inverse_or_zero(scale_x);
+
+        let mut shear_xy = glm::dot(x, y);
+        y = y - x * shear_xy;
+
+        let scale_y = length_or_zero(y);
+        y = y * inverse_or_zero(scale_y);
+        shear_xy *= inverse_or_zero(scale_y);
+
+        let mut shear_xz = glm::dot(x, z);
+        z = z - x * shear_xz;
+        let mut shear_yz = glm::dot(y, z);
+        z = z - y * shear_yz;
+
+        let scale_z = length_or_zero(z);
+        z = z * inverse_or_zero(scale_z);
+        shear_xz *= inverse_or_zero(scale_z);
+        shear_yz *= inverse_or_zero(scale_z);
+
+        let (x, y, z) = complete_axes(x, y, z);
+        let rotation = Quat::from_mat3(&glm::mat3(
+            x.x, x.y, x.z,
+            y.x, y.y, y.z,
+            z.x, z.y, z.z,
+        ));
+
+        let scale = if reflected {
+            glm::vec3(-scale_x, scale_y, scale_z)
+        } else {
+            glm::vec3(scale_x, scale_y, scale_z)
+        };
+
+        let shear = if shear_xy.abs() > EPSILON || shear_xz.abs() > EPSILON || shear_yz.abs() > EPSILON {
+            Some(glm::vec3(shear_xy, shear_xz, shear_yz))
+        } else {
+            None
+        };
+
+        Transform {
+            translation,
+            rotation,
+            scale,
+            shear,
+        }
+    }
+
+    pub fn to_mat4(&self) -> glm::Mat4 {
+        let shear = self.shear.unwrap_or(glm::vec3(0.0, 0.0, 0.0));
+        let s = self.scale;
+
+        // columns of shear * scale
+        let hs = glm::mat3(
+            s.x, 0.0, 0.0,
+            shear.x * s.y, s.y, 0.0,
+            shear.y * s.z, shear.z * s.z, s.z,
+        );
+        let m = self.rotation.to_mat3() * hs;
+        let t = self.translation;
+
+        glm::mat4(
+            m.c0.x, m.c0.y, m.c0.z, 0.0,
+            m.c1.x, m.c1.y, m.c1.z, 0.0,
+            m.c2.x, m.c2.y, m.c2.z, 0.0,
+            t.x, t.y, t.z, 1.0,
+        )
+    }
+
+    /// Same as `to_mat4` but rotation, shear and scale happen around `pivot` (in the node's
+    /// space) instead of around its origin, T * P * R * H * S * P^-1
+    pub fn to_mat4_with_pivot(&self, pivot: glm::Vec3) -> glm::Mat4 {
+        let rhs = Transform {
+            translation: glm::vec3(0.0, 0.0, 0.0),
+            ..*self
+        }.to_mat4();
+
+        let to_pivot = glm::ext::translate(&utils::mat_ident(), self.translation + pivot);
+        let from_pivot = glm::ext::translate(&utils::mat_ident(), pivot * -1.0);
+
+        to_pivot * rhs * from_pivot
+    }
+
+    /// Adjusts the translation so moving the pivot from `old` to `new` doesn't move the object
+    pub fn move_pivot(&mut self, old: glm::Vec3, new: glm::Vec3) {
+        let before = self.to_mat4_with_pivot(old);
+        let after = self.to_mat4_with_pivot(new);
+
+        self.translation = self.translation + glm::vec3(
+            before.c3.x - after.c3.x,
+            before.c3.y - after.c3.y,
+            before.c3.z - after.c3.z,
+        );
+    }
+
+    pub fn is_reflection(&self) -> bool {
+        self.scale.x * self.scale.y * self.scale.z < 0.0
+    }
+
+    pub fn euler_degrees(&self) -> glm::Vec3 {
+        self.rotation.to_euler()
+    }
+
+    pub fn set_euler_degrees(&mut self, degrees: glm::Vec3) {
+        self.rotation = Quat::from_euler(degrees);
+    }
+}
+
+/// Length of an axis, 0 if it's too short to have a direction
+fn length_or_zero(v: glm::Vec3) -> f32 {
+    let length = glm::length(v);
+    if length < EPSILON { 0.0 } else { length }
+}
+
+fn inverse_or_zero(length: f32) -> f32 {
+    if length == 0.0 { 0.0 } else { 1.0 / length }
+}
+
+/// Replaces the zero axes of an orthonormal basis with ones perpendicular to the rest, identity if there's nothing left
+fn complete_axes(x: glm::Vec3, y: glm::Vec3, z: glm::Vec3) -> (glm::Vec3, glm::Vec3, glm::Vec3) {
+    let missing = |v: glm::Vec3| glm::dot(v, v) < 0.5;
+    // any unit vector perpendicular to `v`, crossed with the world axis it's least aligned with
+    let perpendicular = |v: glm::Vec3| {
+        let axis = if v.x.abs() < 0.9 { glm::vec3(1.0, 0.0, 0.0) } else { glm::vec3(0.0, 1.0, 0.0) };
+        glm::normalize(glm::cross(v, axis))
+    };
+
+    match (missing(x), missing(y), missing(z)) {
+        (false, false, false) => (x, y, z),
+        (true, false, false) => (glm::cross(y, z), y, z),
+        (false, true, false) => (x, glm::cross(z, x), z),
+        (false, false, true) => (x, y, glm::cross(x, y)),
+        (false, true, true) => {
+            let y = perpendicular(x);
+            (x, y, glm::cross(x, y))
+        },
+        (true, false, true) => {
+            let z = perpendicular(y);
+            (glm::cross(y, z), y, z)
+        },
+        (true, true, false) => {
+            let x = perpendicular(z);
+            (x, glm::cross(z, x), z)
+        },
+        (true, true, true) => (glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0), glm::vec3(0.0, 0.0, 1.0)),
+    }
+}
+
+impl From<&russimp::Matrix4x4> for Transform {
+    fn from(m: &russimp::Matrix4x4) -> Self {
+        Transform::from_mat4(&utils::mat4_from_assimp(m))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_mat_eq(a: &glm::Mat4, b: &glm::Mat4) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((a[i][j] - b[i][j]).abs() < 1e-4, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    fn assert_vec_eq(a: glm::Vec3, b: glm::Vec3) {
+        assert!(glm::length(a - b) < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn decompose_compose_round_trip() {
+        let transform = Transform::new(glm::vec3(1.0, -2.0, 3.0), Quat::from_axis_angle(glm::vec3(1.0, 1.0, 0.0), 0.7), glm::vec3(2.0, 0.5, 1.5));
+        let decomposed = Transform::from_mat4(&transform.to_mat4());
+
+        assert_mat_eq(&decomposed.to_mat4(), &transform.to_mat4());
+        assert_vec_eq(decomposed.translation, transform.translation);
+        assert_vec_eq(decomposed.scale, transform.scale);
+        assert!(decomposed.shear.is_none());
+    }
+
+    #[test]
+    fn decompose_keeps_reflection_and_shear() {
+        let transform = Transform {
+            translation: glm::vec3(0.5, 0.0, -1.0),
+            rotation: Quat::from_axis_angle(glm::vec3(0.0, 0.0, 1.0), 1.2),
+            scale: glm::vec3(-1.0, 2.0, 1.0),
+            shear: Some(glm::vec3(0.3, 0.0, -0.2)),
+        };
+        let decomposed = Transform::from_mat4(&transform.to_mat4());
+
+        assert_mat_eq(&decomposed.to_mat4(), &transform.to_mat4());
+        assert!(decomposed.is_reflection());
+        assert!(decomposed.shear.is_some());
+    }
+
+    #[test]
+    fn decompose_zero_scale() {
+        let rotation = Quat::from_axis_angle(glm::vec3(0.0, 1.0, 1.0), 0.9);
+        for scale in [glm::vec3(1.0, 0.0, 2.0), glm::vec3(0.0, 0.0, 3.0), glm::vec3(0.0, 0.0, 0.0)] {
+            let transform = Transform::new(glm::vec3(1.0, 2.0, 3.0), rotation, scale);
+            let decomposed = Transform::from_mat4(&transform.to_mat4());
+
+            let q = decomposed.rotation;
+            assert!([q.x, q.y, q.z, q.w].iter().all(|c| c.is_finite()), "{:?}", q);
+            assert!((q.length() - 1.0).abs() < 1e-4);
+            assert_vec_eq(decomposed.scale, scale);
+            assert_mat_eq(&decomposed.to_mat4(), &transform.to_mat4());
+        }
+    }
+
+    #[test]
+    fn pivot_stays_in_place() {
+        let transform = Transform::new(glm::vec3(1.0, 2.0, 3.0), Quat::from_axis_angle(glm::vec3(0.0, 1.0, 0.0), 2.0), glm::vec3(3.0, 3.0, 3.0));
+        let pivot = glm::vec3(0.5, 1.0, -2.0);
+
+        let moved = transform.to_mat4_with_pivot(pivot) * glm::vec4(pivot.x, pivot.y, pivot.z, 1.0);
+        assert_vec_eq(moved.truncate(3), transform.translation + pivot);
+    }
+
+    #[test]
+    fn moving_the_pivot_keeps_the_matrix() {
+        let mut transform = Transform::new(glm::vec3(1.0, 0.0, 0.0), Quat::from_axis_angle(glm::vec3(1.0, 0.0, 1.0), 0.4), glm::vec3(1.0, 2.0, 0.5));
+        let (old, new) = (glm::vec3(0.0, 0.0, 0.0), glm::vec3(2.0, -1.0, 0.5));
+
+        let before = transform.to_mat4_with_pivot(old);
+        transform.move_pivot(old, new);
+
+        assert_mat_eq(&transform.to_mat4_with_pivot(new), &before);
+    }
+}
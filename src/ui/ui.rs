@@ -1,33 +1,252 @@
 use glad_gl::gl;
 
-use crate::{camera::Camera, model, imgui_glfw_support, imgui_opengl_renderer, mesh, ui, log};
+use crate::{camera::Camera, gl_object, post, shadow, light, environment, selection, gizmo, history, project, export, model, node, transform, animation, import, imgui_glfw_support, imgui_opengl_renderer, mesh, material, ui, log};
 
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub struct State {
     pub camera_coords_shown: bool,
+    pub gl_objects_shown: bool,
+    pub shadow_maps_shown: bool,
     pub is_cursor_captured: bool,
     pub draw_grid: bool,
     pub wireframe: bool,
+    /// msaa sample count of the scene, 1 is off
+    pub msaa_samples: i32,
+    /// GL_MAX_SAMPLES, queried once the gl context exists
+    pub max_samples: i32,
+    pub tone_mapping: post::ToneMapping,
+    /// in stops, applied before tone mapping
+    pub exposure: f32,
+    pub lights: Vec<light::Light>,
+    /// added to every surface on top of the lights
+    pub ambient_light: glm::Vec3,
+    /// image based lighting and skybox, the ambient light is used without one
+    pub environment: Option<environment::Environment>,
+    pub environment_settings: environment::EnvironmentSettings,
+    /// environment being decoded in the background
+    pub environment_job: Option<environment::EnvironmentJob>,
+    /// shadow map preview textures in the same order as the lights, only filled while the shadow maps window is shown
+    pub shadow_previews: Vec<Option<u32>>,
+    pub shading_model: material::ShadingModel,
     pub first_frame_drawn: bool,
     pub camera: Camera,
     pub objects: Vec<model::Model>,
+    pub selection: Option<selection::Selection>,
+    /// pixel in the scene that was clicked, picked before the next frame is drawn
+    pub pick_request: Option<[i32; 2]>,
+    /// expands the objects tree down to the selection once after it was picked in the scene
+    pub reveal_selection: bool,
+    pub gizmo: gizmo::Gizmo,
+    /// the gizmo shortcuts only work while the mouse is over the scene
+    pub scene_hovered: bool,
+    pub history: history::History,
+    /// imgui handles undo itself while a text field is being edited
+    pub text_input_active: bool,
     pub viewport_size: [f32; 2],
     pub log: log::Log,
+    pub import_presets: import::ImportPresets,
+    /// files waiting for the import dialog, grouped by extension
+    pub pending_imports: Vec<PendingImport>,
+    /// models being loaded in the background
+    pub imports: Vec<import::ImportJob>,
+    /// project file the scene was last opened from or saved to
+    pub project_path: Option<std::path::PathBuf>,
+    pub export_settings: export::ExportSettings,
+}
+
+impl State {
+    /// The history and the parts of the scene it edits, borrowed separately so they can be used together
+    pub fn history_scene(&mut self) -> (&mut history::History, history::Scene<'_>) {
+        (&mut self.history, history::Scene {
+            objects: &mut self.objects,
+            lights: &mut self.lights,
+            selection: &mut self.selection,
+        })
+    }
+}
+
+pub fn undo(state: &mut State) {
+    let (history, mut scene) = state.history_scene();
+    if let Some(label) = history.undo(&mut scene) {
+        state.log.log(&format!("Undo {}", label), log::LogLevel::Info);
+    }
+}
+
+pub fn redo(state: &mut State) {
+    let (history, mut scene) = state.history_scene();
+    if let Some(label) = history.redo(&mut scene) {
+        state.log.log(&format!("Redo {}", label), log::LogLevel::Info);
+    }
+}
+
+const PROJECT_FILTER: (&str, &[&str]) = ("Scene (.json)", &["json"]);
+
+pub fn save_project(state: &mut State, path: &std::path::Path) {
+    let directory = project::directory(path);
+    let project = project::Project {
+        version: project::VERSION,
+        camera: project::CameraEntry::new(&state.camera),
+        viewport: project::ViewportEntry {
+            draw_grid: state.draw_grid,
+            wireframe: state.wireframe,
+            msaa_samples: state.msaa_samples,
+            shading_model: state.shading_model,
+            tone_mapping: state.tone_mapping,
+            exposure: state.exposure,
+        },
+        ambient_light: *state.ambient_light.as_array(),
+        environment: state.environment.as_ref().map(|env| project::relative_path(&env.path, &directory)),
+        environment_settings: state.environment_settings,
+        lights: state.lights.iter().map(project::LightEntry::new).collect(),
+        objects: state.objects.iter().map(|object| project::ObjectEntry::new(object, &directory)).collect(),
+    };
+
+    match project.write(path) {
+        Ok(()) => {
+            state.log.log(&format!("Saved scene to \"{}\"", path.display()), log::LogLevel::Info);
+            state.project_path = Some(path.to_path_buf());
+        },
+        Err(e) => {
+            let error = format!("Error saving scene \"{}\": {}", path.display(), e);
+            println!("{}", error);
+
+            state.log.log(&error, log::LogLevel::Error);
+        },
+    }
+}
+
+/// Replaces the scene, the models are imported again in the background and their changes applied once they're loaded
+pub fn open_project(state: &mut State, path: &std::path::Path) {
+    let project = match project::Project::read(path) {
+        Ok(project) => project,
+        Err(e) => {
+            let error = format!("Error opening scene \"{}\": {}", path.display(), e);
+            println!("{}", error);
+
+            state.log.log(&error, log::LogLevel::Error);
+            return;
+        },
+    };
+    let directory = project::directory(path);
+
+    // the worker threads of dropped imports notice on their own
+    state.imports.clear();
+    state.pending_imports.clear();
+    state.objects.clear();
+    state.selection = None;
+    state.history.clear();
+
+    project.camera.apply(&mut state.camera);
+    let viewport = &project.viewport;
+    state.draw_grid = viewport.draw_grid;
+    state.wireframe = viewport.wireframe;
+    state.msaa_samples = viewport.msaa_samples.clamp(1, state.max_samples);
+    state.shading_model = viewport.shading_model;
+    state.tone_mapping = viewport.tone_mapping;
+    state.exposure = viewport.exposure;
+    state.ambient_light = glm::vec3(project.ambient_light[0], project.ambient_light[1], project.ambient_light[2]);
+    state.lights = project.lights.iter().map(project::LightEntry::light).collect();
+
+    state.environment = None;
+    state.environment_settings = project.environment_settings;
+    state.environment_job = project.environment.map(|env| environment::EnvironmentJob::spawn(project::resolve_path(&env, &directory)));
+
+    for object in project.objects {
+        let mut job = import::ImportJob::spawn(project::resolve_path(&object.path, &directory), object.import_settings.clone());
+        job.restore = Some(object);
+        state.imports.push(job);
+    }
+
+    state.log.log(&format!("Opened scene \"{}\"", path.display()), log::LogLevel::Info);
+    state.project_path = Some(path.to_path_buf());
+}
+
+/// Saves to the file the scene came from, asks for one if there isn't any yet
+pub fn save_scene(state: &mut State) {
+    match state.project_path.clone() {
+        Some(path) => save_project(state, &path),
+        None => save_project_as(state),
+    }
+}
+
+fn save_project_as(state: &mut State) {
+    if let Some(path) = rfd::FileDialog::new()
+        .set_title("Save Scene")
+        .set_directory("./")
+        .add_filter(PROJECT_FILTER.0, PROJECT_FILTER.1)
+        .set_file_name("scene.json")
+        .save_file() {
+            save_project(state, &path);
+        }
+}
+
+/// `extension` picks the format, gltf with a .bin next to it or a single .glb
+fn export_scene(state: &mut State, filter: &str, extension: &str) {
+    let path = match rfd::FileDialog::new()
+        .set_title("Export Scene")
+        .set_directory("./")
+        .add_filter(filter, &[extension])
+        .set_file_name(&format!("scene.{}", extension))
+        .save_file() {
+            Some(path) => path,
+            None => return,
+        };
+
+    match export::export(&state.objects, &path, &state.export_settings) {
+        Ok(()) => state.log.log(&format!("Exported scene to \"{}\"", path.display()), log::LogLevel::Info),
+        Err(e) => {
+            let error = format!("Error exporting scene \"{}\": {}", path.display(), e);
+            println!("{}", error);
+
+            state.log.log(&error, log::LogLevel::Error);
+        },
+    }
+}
+
+pub struct PendingImport {
+    pub paths: Vec<std::path::PathBuf>,
+    pub settings: import::ImportSettings,
+    dialog_opened: bool,
 }
 
 impl Default for State {
     fn default() -> Self {
         Self {
             camera_coords_shown: false,
+            gl_objects_shown: false,
+            shadow_maps_shown: false,
             first_frame_drawn: false,
             is_cursor_captured: false,
             draw_grid: true,
             wireframe: false,
+            msaa_samples: 4,
+            max_samples: 1,
+            tone_mapping: post::ToneMapping::default(),
+            exposure: 0.0,
+            lights: light::Light::defaults(),
+            ambient_light: glm::vec3(0.05, 0.05, 0.05),
+            environment: None,
+            environment_settings: environment::EnvironmentSettings::default(),
+            environment_job: None,
+            shadow_previews: vec![],
+            shading_model: material::ShadingModel::default(),
             camera: Camera::new(),
             objects: vec![],
+            selection: None,
+            pick_request: None,
+            reveal_selection: false,
+            gizmo: gizmo::Gizmo::default(),
+            scene_hovered: false,
+            history: history::History::default(),
+            text_input_active: false,
             viewport_size: [0.0, 0.0],
             log: log::Log::default(),
+            import_presets: import::ImportPresets::default(),
+            pending_imports: vec![],
+            imports: vec![],
+            project_path: None,
+            export_settings: export::ExportSettings::default(),
         }
     }
 }
@@ -74,27 +293,70 @@ pub fn draw_main_menu_bar(ui: &imgui::Ui, state: &mut State, window: &mut glfw::
                         Some(m) => m,
                         None => return,
                     };
-                for model_path in &models {
-                    let model = model::Model::new(model_path.to_str().unwrap(), state);
-                    match model {
-                        Ok(m) => state.objects.push(m),
-                        Err(e) => {
-                            let error = format!("Error loading model \"{}\": {}", model_path.to_str().unwrap(), e);
-                            println!("{}", error);
-
-                            state.log.log(&error, log::LogLevel::Error);
-                        },
+                // one dialog per extension since each one starts from its own remembered settings
+                for model_path in models {
+                    let extension = import::extension(&model_path);
+                    match state.pending_imports.iter_mut().find(|pending| import::extension(&pending.paths[0]) == extension) {
+                        Some(pending) => pending.paths.push(model_path),
+                        None => state.pending_imports.push(PendingImport {
+                            settings: state.import_presets.get(&model_path),
+                            paths: vec![model_path],
+                            dialog_opened: false,
+                        }),
                     }
                 }
             }
+            ui.separator();
+            if ui.menu_item("Open Scene") {
+                if let Some(path) = rfd::FileDialog::new()
+                    .set_title("Open Scene")
+                    .set_directory("./")
+                    .add_filter(PROJECT_FILTER.0, PROJECT_FILTER.1)
+                    .pick_file() {
+                        open_project(state, &path);
+                    }
+            }
+            if ui.menu_item_config("Save Scene").shortcut("Ctrl+S").build() {
+                save_scene(state);
+            }
+            if ui.menu_item("Save Scene As") {
+                save_project_as(state);
+            }
+            ui.menu_with_enabled("Export", !state.objects.is_empty(), || {
+                if ui.menu_item("glTF (.gltf + .bin)") {
+                    export_scene(state, "glTF (.gltf)", "gltf");
+                }
+                if ui.menu_item("glTF Binary (.glb)") {
+                    export_scene(state, "glTF Binary (.glb)", "glb");
+                }
+                ui.separator();
+                ui.checkbox("Embed Images", &mut state.export_settings.embed_images);
+            });
+            ui.separator();
             if ui.menu_item_config("Quit").shortcut("Ctrl+Q").build() {
                 window.set_should_close(true);
             }
         });
+        ui.menu("Edit", || {
+            let undo_label = state.history.undo_labels().last().map_or(String::from("Undo"), |label| format!("Undo {}", label));
+            if ui.menu_item_config(undo_label).shortcut("Ctrl+Z").enabled(state.history.undo_count() > 0).build() {
+                undo(state);
+            }
+            let redo_label = state.history.redo_labels().next().map_or(String::from("Redo"), |label| format!("Redo {}", label));
+            if ui.menu_item_config(redo_label).shortcut("Ctrl+Shift+Z").enabled(state.history.redo_count() > 0).build() {
+                redo(state);
+            }
+        });
         ui.menu("View", || {
             if ui.menu_item_config("Show Camera Coords").selected(state.camera_coords_shown).build() {
                 state.camera_coords_shown = !state.camera_coords_shown;
             }
+            if ui.menu_item_config("Show GL Objects").selected(state.gl_objects_shown).build() {
+                state.gl_objects_shown = !state.gl_objects_shown;
+            }
+            if ui.menu_item_config("Show Shadow Maps").selected(state.shadow_maps_shown).build() {
+                state.shadow_maps_shown = !state.shadow_maps_shown;
+            }
             if ui.menu_item_config("Toggle grid").selected(state.draw_grid).build() {
                 state.draw_grid = !state.draw_grid;
             }
@@ -106,58 +368,470 @@ pub fn draw_main_menu_bar(ui: &imgui::Ui, state: &mut State, window: &mut glfw::
     });
 }
 
-fn draw_transformations(ui: &imgui::Ui, mesh: &mut mesh::Mesh) {
+fn import_models(state: &mut State, paths: &[std::path::PathBuf], settings: &import::ImportSettings) {
+    for model_path in paths {
+        state.imports.push(import::ImportJob::spawn(model_path.to_owned(), settings.clone()));
+    }
+}
+
+/// Moves the finished imports into the scene
+fn update_imports(state: &mut State) {
+    let mut i = 0;
+
+    while i < state.imports.len() {
+        // objects from a project are added in the order they were saved in
+        if state.imports[i].restore.is_some() && state.imports[..i].iter().any(|job| job.restore.is_some()) {
+            i += 1;
+            continue;
+        }
+
+        let outcome = match state.imports[i].poll(&mut state.log) {
+            Some(outcome) => outcome,
+            None => {
+                i += 1;
+                continue;
+            },
+        };
+
+        let job = state.imports.remove(i);
+        match (outcome, job.restore) {
+            (import::ImportOutcome::Loaded(mut m), Some(restore)) => {
+                // the project has its own copy of the lights
+                m.imported_lights.clear();
+                restore.apply(&mut m, &mut state.log);
+                state.objects.push(m);
+            },
+            (import::ImportOutcome::Loaded(m), None) => {
+                let label = format!("Import {}", m.name);
+                let (history, mut scene) = state.history_scene();
+                let command = history::Command::import(m, &scene);
+                history.execute(label, command, &mut scene);
+            },
+            (import::ImportOutcome::Failed(e), _) => {
+                let error = format!("Error loading model \"{}\": {}", job.path.display(), e);
+                println!("{}", error);
+
+                state.log.log(&error, log::LogLevel::Error);
+            },
+            (import::ImportOutcome::Cancelled, _) => {
+                state.log.log(&format!("Cancelled loading model \"{}\"", job.path.display()), log::LogLevel::Info);
+            },
+        }
+    }
+}
+
+fn draw_import_progress(ui: &imgui::Ui, state: &mut State) {
+    if state.imports.is_empty() {
+        return;
+    }
+
+    ui.window("Importing")
+        .always_auto_resize(true)
+        .collapsible(false)
+        .build(|| {
+            for (i, job) in state.imports.iter().enumerate() {
+                let _id = ui.push_id_usize(i);
+                let (fraction, stage) = job.status();
+
+                ui.text(job.name());
+                imgui::ProgressBar::new(fraction)
+                    .size([300.0, 0.0])
+                    .overlay_text(&stage)
+                    .build(ui);
+                ui.same_line();
+                // the worker notices on its own, the job is dropped next frame
+                if ui.button("Cancel") {
+                    job.progress.cancel();
+                }
+            }
+        });
+}
+
+fn draw_import_settings(ui: &imgui::Ui, settings: &mut import::ImportSettings) {
+    ui.checkbox("Flip UVs", &mut settings.flip_uvs);
+    ui.checkbox("Generate Smooth Normals", &mut settings.gen_smooth_normals);
+    if ui.is_item_hovered() {
+        ui.tooltip_text("Only for meshes that don't have normals");
+    }
+    ui.checkbox("Join Identical Vertices", &mut settings.join_identical_vertices);
+    ui.checkbox("Optimize Meshes", &mut settings.optimize_meshes);
+    ui.checkbox("Pre-Transform Vertices", &mut settings.pre_transform_vertices);
+    if ui.is_item_hovered() {
+        ui.tooltip_text("Bakes the node transforms into the meshes and flattens the hierarchy");
+    }
+    ui.checkbox("Flip Winding Order", &mut settings.flip_winding_order);
+    ui.checkbox("Merge Materials", &mut settings.merge_materials);
+
+    imgui::Drag::new("Scale")
+        .range(0.0001, 10000.0)
+        .speed(0.01)
+        .display_format("%.4f")
+        .build(ui, &mut settings.scale);
+
+    let mut up_axis = settings.up_axis.index();
+    if ui.combo_simple_string("Up Axis", &mut up_axis, &import::UpAxis::LABELS) {
+        settings.up_axis = import::UpAxis::from_index(up_axis);
+    }
+}
+
+fn draw_import_dialog(ui: &imgui::Ui, state: &mut State) {
+    let pending = match state.pending_imports.first_mut() {
+        Some(pending) => pending,
+        None => return,
+    };
+
+    let title = format!("Import .{} Files###ImportSettings", import::extension(&pending.paths[0]));
+    if !pending.dialog_opened {
+        ui.open_popup(&title);
+        pending.dialog_opened = true;
+    }
+
+    let mut confirmed = None;
+    ui.modal_popup_config(&title)
+        .always_auto_resize(true)
+        .build(|| {
+            for path in &pending.paths {
+                ui.text_disabled(path.file_name().and_then(|name| name.to_str()).unwrap_or_default());
+            }
+            ui.separator();
+
+            draw_import_settings(ui, &mut pending.settings);
+
+            ui.separator();
+            if ui.button("Import") {
+                confirmed = Some(true);
+            }
+            ui.same_line();
+            if ui.button("Reset") {
+                pending.settings = import::ImportSettings::default();
+            }
+            ui.same_line();
+            if ui.button("Cancel") {
+                confirmed = Some(false);
+            }
+
+            if confirmed.is_some() {
+                ui.close_current_popup();
+            }
+        });
+
+    if let Some(confirmed) = confirmed {
+        let pending = state.pending_imports.remove(0);
+        if confirmed {
+            state.import_presets.set(&pending.paths[0], pending.settings.clone());
+            import_models(state, &pending.paths, &pending.settings);
+        }
+    }
+}
+
+fn draw_transformations(ui: &imgui::Ui, transform: &mut transform::Transform) {
     imgui::Drag::new("###XPos")
         .range(f32::NEG_INFINITY, f32::INFINITY)
         .speed(0.1)
         .display_format("X: %.3f")
-        .build(ui, &mut mesh.position.x);
+        .build(ui, &mut transform.translation.x);
     imgui::Drag::new("###YPos")
         .range(f32::NEG_INFINITY, f32::INFINITY)
         .speed(0.1)
         .display_format("Y: %.3f")
-        .build(ui, &mut mesh.position.y);
+        .build(ui, &mut transform.translation.y);
     imgui::Drag::new("###ZPos")
         .range(f32::NEG_INFINITY, f32::INFINITY)
         .speed(0.1)
         .display_format("Z: %.3f")
-        .build(ui, &mut mesh.position.z);
+        .build(ui, &mut transform.translation.z);
     imgui::Drag::new("Scale")
         .range(f32::NEG_INFINITY, f32::INFINITY)
         .speed(0.1)
         .display_format("%.7f")
-        .build_array(ui, mesh.scale.as_array_mut());
-    imgui::Drag::new("Rotation")
+        .build_array(ui, transform.scale.as_array_mut());
+
+    // rotation is stored as a quaternion, euler angles are only for editing
+    let mut rotation = transform.euler_degrees();
+    if imgui::Drag::new("Rotation")
         .range(f32::NEG_INFINITY, f32::INFINITY)
         .speed(1.0)
         .display_format("%.2f")
-        .build_array(ui, mesh.rotation.as_array_mut());
+        .build_array(ui, rotation.as_array_mut()) {
+        transform.set_euler_degrees(rotation);
+    }
+
+    if let Some(shear) = transform.shear {
+        ui.text_disabled(format!("Shear: {:.3} {:.3} {:.3}", shear.x, shear.y, shear.z));
+    }
+    if transform.is_reflection() {
+        ui.text_disabled("Reflected (negative scale)");
+    }
+}
+
+fn draw_pivot(ui: &imgui::Ui, object: &mut model::Model, idx: usize) {
+    let mut mode = object.nodes[idx].pivot.index();
+    if ui.combo_simple_string("Pivot", &mut mode, &node::Pivot::LABELS) && mode != object.nodes[idx].pivot.index() {
+        let pivot = match mode {
+            0 => node::Pivot::Origin,
+            1 => node::Pivot::BoundingBoxCenter(object.bounding_box_center(idx)),
+            // start the custom pivot where the current one is so nothing jumps
+            _ => node::Pivot::Custom(object.pivot_point(idx)),
+        };
+        object.set_pivot(idx, pivot);
+    }
+
+    if let node::Pivot::Custom(mut point) = object.nodes[idx].pivot {
+        if imgui::Drag::new("Pivot Point")
+            .range(f32::NEG_INFINITY, f32::INFINITY)
+            .speed(0.1)
+            .display_format("%.3f")
+            .build_array(ui, point.as_array_mut()) {
+            object.set_pivot(idx, node::Pivot::Custom(point));
+        }
+    } else {
+        let point = object.pivot_point(idx);
+        ui.text_disabled(format!("Pivot Point: {:.3} {:.3} {:.3}", point.x, point.y, point.z));
+    }
+
+    if let node::Pivot::BoundingBoxCenter(..) = object.nodes[idx].pivot {
+        if ui.button("Recenter Pivot") {
+            object.set_pivot(idx, node::Pivot::BoundingBoxCenter(object.bounding_box_center(idx)));
+        }
+    }
+}
+
+fn draw_axis_conversion(ui: &imgui::Ui, object: &mut model::Model) {
+    ui.tree_node_config("Axis Conversion").build(|| {
+        let conversion = &mut object.axis_conversion;
+        for (label, axis) in [("Up", &mut conversion.up), ("Front", &mut conversion.front), ("Right", &mut conversion.right)] {
+            let mut idx = axis.index();
+            if ui.combo_simple_string(label, &mut idx, &import::SignedAxis::LABELS) {
+                *axis = import::SignedAxis::from_index(idx);
+            }
+        }
+
+        imgui::Drag::new("Unit Scale")
+            .range(0.0001, 10000.0)
+            .speed(0.001)
+            .display_format("%.4f")
+            .build(ui, &mut conversion.unit_scale);
+
+        if !conversion.is_valid() {
+            ui.text_colored([1.0, 0.64, 0.0, 1.0], "Axes have to be different, ignoring them");
+        }
+
+        ui.disabled(object.axis_conversion == object.imported_axis_conversion, || {
+            if ui.button("Reset") {
+                object.axis_conversion = object.imported_axis_conversion;
+            }
+        });
+    });
 }
 
-fn draw_mesh_hierarchy(ui: &imgui::Ui, mesh: &mut mesh::Mesh, i: usize) {
-    ui.tree_node_config(format!("{}###{}", mesh.name.as_str(), i)).build(|| {
+/// The selection and history while drawing the tree of a single object
+struct ObjectTree<'a> {
+    object: usize,
+    selection: &'a mut Option<selection::Selection>,
+    /// expand the tree down to the selection and scroll to it
+    reveal: bool,
+    history: &'a mut history::History,
+}
+
+impl ObjectTree<'_> {
+    /// Whether `node` needs to be expanded for the selection to show
+    fn is_below(&self, object: &model::Model, node: usize) -> bool {
+        self.reveal && self.selection.iter().any(|selection| selection.object == self.object && selection.is_below(object, node))
+    }
+}
+
+/// Tree node that selects `item` when its label is clicked, the arrow still expands it
+fn selectable_tree_node<'a>(ui: &'a imgui::Ui, label: String, item: selection::Selection, expand: bool, tree: &mut ObjectTree) -> Option<imgui::TreeNodeToken<'a>> {
+    let selected = *tree.selection == Some(item);
+    let mut node = ui.tree_node_config(label)
+        .open_on_arrow(true)
+        .selected(selected);
+    if expand {
+        node = node.opened(true, imgui::Condition::Always);
+    }
+
+    let token = node.push();
+    if ui.is_item_clicked() && !ui.is_item_toggled_open() {
+        *tree.selection = Some(item);
+    }
+    if selected && tree.reveal {
+        ui.set_scroll_here_y();
+    }
+
+    token
+}
+
+fn draw_color3(ui: &imgui::Ui, label: &str, color: &mut glm::Vec3) {
+    let mut edited = *color.as_array();
+    if ui.color_edit3(label, &mut edited) {
+        *color = glm::vec3(edited[0], edited[1], edited[2]);
+    }
+}
+
+fn draw_material(ui: &imgui::Ui, material: &mut material::Material) {
+    ui.tree_node_config("Phong").build(|| {
+        draw_color3(ui, "Ambient", &mut material.ambient);
+        draw_color3(ui, "Diffuse", &mut material.diffuse);
+        draw_color3(ui, "Specular", &mut material.specular);
+        imgui::Drag::new("Shininess")
+            .range(1.0, 1024.0)
+            .speed(0.5)
+            .display_format("%.1f")
+            .build(ui, &mut material.shininess);
+    });
+    ui.tree_node_config("PBR").build(|| {
+        let mut base_color = *material.base_color.as_array();
+        if ui.color_edit4("Base Color", &mut base_color) {
+            material.base_color = glm::vec4(base_color[0], base_color[1], base_color[2], base_color[3]);
+        }
+        ui.slider("Metallic", 0.0, 1.0, &mut material.metallic);
+        ui.slider("Roughness", 0.0, 1.0, &mut material.roughness);
+        draw_color3(ui, "Emissive", &mut material.emissive);
+        imgui::Drag::new("Emissive Strength")
+            .range(0.0, 10000.0)
+            .speed(0.05)
+            .display_format("%.2f")
+            .build(ui, &mut material.emissive_strength);
+        imgui::Drag::new("Normal Scale")
+            .range(0.0, 10.0)
+            .speed(0.01)
+            .display_format("%.2f")
+            .build(ui, &mut material.normal_scale);
+        ui.slider("Occlusion Strength", 0.0, 1.0, &mut material.occlusion_strength);
+    });
+
+    ui.slider("Opacity", 0.0, 1.0, &mut material.opacity);
+    ui.checkbox("Two Sided", &mut material.two_sided);
+    let mut alpha_mode = material.alpha_mode.index();
+    if ui.combo_simple_string("Alpha Mode", &mut alpha_mode, &material::AlphaMode::LABELS) {
+        material.alpha_mode = material::AlphaMode::from_index(alpha_mode);
+    }
+    if material.alpha_mode == material::AlphaMode::Mask {
+        ui.slider("Alpha Cutoff", 0.0, 1.0, &mut material.alpha_cutoff);
+    }
+}
+
+fn draw_mesh_hierarchy(ui: &imgui::Ui, mesh: &mut mesh::Mesh, i: usize, node: usize, tree: &mut ObjectTree) {
+    let item = selection::Selection { object: tree.object, node, mesh: Some(i) };
+    if let Some(_token) = selectable_tree_node(ui, format!("{}###mesh-{}-{}", mesh.name.as_str(), node, i), item, false, tree) {
         ui.text(format!("Vertices: {}", mesh.vertices.len()));
         ui.text(format!("Textures: {}", mesh.textures.len()));
-        ui.tree_node_config(mesh.material.name.as_str()).build(|| {
-            ui.text(format!("{}", mesh.material));
+        ui.tree_node_config(mesh.material.name.clone()).build(|| {
+            let before = mesh.material.clone();
+            draw_material(ui, &mut mesh.material);
+            if mesh.material != before {
+                tree.history.push(format!("Edit {}", mesh.material.name), history::Command::Material {
+                    object: tree.object,
+                    mesh: i,
+                    before: Box::new(before),
+                    after: Box::new(mesh.material.clone()),
+                });
+            }
         });
-        ui.tree_node_config("Transformations").build(|| {
-            draw_transformations(ui, mesh);
-        })
+        if !mesh.morph_targets.is_empty() {
+            ui.tree_node_config("Morph Targets").build(|| {
+                // an animation overrides the weights while it's playing
+                ui.disabled(mesh.morph_pose.is_some(), || {
+                    let before = mesh.morph_weights.clone();
+                    for (target, weight) in mesh.morph_targets.iter().zip(mesh.morph_weights.iter_mut()) {
+                        ui.slider(&target.name, 0.0, 1.0, weight);
+                    }
+                    if mesh.morph_weights != before {
+                        tree.history.push(format!("Morph {}", mesh.name), history::Command::MorphWeights {
+                            object: tree.object,
+                            mesh: i,
+                            before,
+                            after: mesh.morph_weights.clone(),
+                        });
+                    }
+                });
+            });
+        }
+    }
+}
+
+fn draw_node_contents(ui: &imgui::Ui, object: &mut model::Model, idx: usize, tree: &mut ObjectTree) {
+    ui.tree_node_config("Transformations").build(|| {
+        let before = history::NodeTransform::of(object, idx);
+        draw_transformations(ui, &mut object.nodes[idx].local_transform);
+        draw_pivot(ui, object, idx);
+        tree.history.record_transform(object, tree.object, idx, before);
     });
+
+    for mesh_idx in object.nodes[idx].mesh_indices.clone() {
+        draw_mesh_hierarchy(ui, &mut object.meshes[mesh_idx], mesh_idx, idx, tree);
+    }
+
+    for child in object.nodes[idx].children.clone() {
+        draw_node_hierarchy(ui, object, child, tree);
+    }
+}
+
+/// Nodes can be dragged onto other nodes of the same object to reparent them, the root can only be dropped on
+fn node_drag_drop(ui: &imgui::Ui, object: &mut model::Model, idx: usize, tree: &mut ObjectTree) {
+    if idx != object.root() {
+        if let Some(_tooltip) = ui.drag_drop_source_config("node").begin_payload([tree.object, idx]) {
+            ui.text(&object.nodes[idx].name);
+        }
+    }
+
+    let target = match ui.drag_drop_target() {
+        Some(target) => target,
+        None => return,
+    };
+    if let Some(Ok(payload)) = target.accept_payload::<[usize; 2], _>("node", imgui::DragDropFlags::empty()) {
+        let [payload_object, node] = payload.data;
+        if payload_object == tree.object && object.can_reparent(node, idx) {
+            let after = (idx, object.nodes[idx].children.len(), object.transform_under(node, idx));
+            let before = object.reparent(node, after);
+            tree.history.push(format!("Reparent {}", object.nodes[node].name), history::Command::Reparent {
+                object: tree.object,
+                node,
+                before,
+                after,
+            });
+            tree.history.end_merge();
+        }
+    }
+    target.pop();
+}
+
+fn draw_node_hierarchy(ui: &imgui::Ui, object: &mut model::Model, idx: usize, tree: &mut ObjectTree) {
+    let label = format!("{}###node-{}", object.nodes[idx].name.as_str(), idx);
+    let expand = tree.is_below(object, idx);
+    let token = selectable_tree_node(ui, label, selection::Selection::node(tree.object, idx), expand, tree);
+    node_drag_drop(ui, object, idx, tree);
+    if let Some(_token) = token {
+        draw_node_contents(ui, object, idx, tree);
+    }
 }
 
 fn draw_object_hierarchy(ui: &imgui::Ui, state: &mut State, idx: usize) -> bool {
     let object = &mut state.objects[idx];
+    let mut tree = ObjectTree {
+        object: idx,
+        selection: &mut state.selection,
+        reveal: state.reveal_selection,
+        history: &mut state.history,
+    };
     if let Some(..) = ui.begin_table_with_sizing("Objects Table", 2, imgui::TableFlags::SIZING_STRETCH_PROP, [0.0, 0.0], 0.0) {
         ui.table_next_row();
         ui.table_next_column();
-        ui.tree_node_config(format!("{}###{}", object.name.as_str(), idx))
-            .build(|| {
-                for (j, mesh) in &mut object.meshes.iter_mut().enumerate() {
-                    draw_mesh_hierarchy(ui, mesh, j);
-                }
-            });
+        let root = object.root();
+        let expand = tree.is_below(object, root);
+        // selecting the object selects its root node
+        let token = selectable_tree_node(ui, format!("{}###{}", object.name.as_str(), idx), selection::Selection::node(idx, root), expand, &mut tree);
+        if !object.nodes.is_empty() {
+            node_drag_drop(ui, object, root, &mut tree);
+        }
+        if let Some(_token) = token {
+            draw_axis_conversion(ui, object);
+
+            // the object itself is the root node so show its contents directly
+            if !object.nodes.is_empty() {
+                draw_node_contents(ui, object, root, &mut tree);
+            }
+        }
 
         ui.table_next_column();
         if ui.small_button(format!("X###{}-{}", object.name.as_str(), idx)) {
@@ -180,12 +854,120 @@ fn draw_objects_window(ui: &imgui::Ui, state: &mut State) {
 
             while i < state.objects.len() {
                 if draw_object_hierarchy(ui, state, i) {
-                    state.objects.remove(i);
+                    let label = format!("Remove {}", state.objects[i].name);
+                    let (history, mut scene) = state.history_scene();
+                    history.execute(label, history::Command::Delete { index: i, object: None }, &mut scene);
                     continue;
                 }
 
                 i = i + 1;
             }
+            state.reveal_selection = false;
+
+            // placeholders for the models that are still loading
+            for job in &state.imports {
+                let (fraction, _) = job.status();
+                ui.text_disabled(format!("{} (loading {:.0}%)", job.name(), fraction * 100.0));
+            }
+        });
+}
+
+fn draw_animation_controls(ui: &imgui::Ui, object: &mut model::Model) {
+    if object.clips.is_empty() {
+        ui.text_disabled("No animations");
+        return;
+    }
+
+    let mut labels = vec![String::from("Bind Pose")];
+    labels.extend(object.clips.iter().map(|clip| clip.name.to_owned()));
+
+    let mut selected = object.animator.clip.map_or(0, |clip| clip + 1);
+    if ui.combo_simple_string("Clip", &mut selected, &labels) {
+        object.animator.set_clip(selected.checked_sub(1));
+    }
+
+    let duration = match object.animator.clip {
+        Some(clip) => object.clips[clip].duration,
+        None => 0.0,
+    };
+
+    ui.disabled(object.animator.clip.is_none(), || {
+        if ui.button(if object.animator.playing { "Pause" } else { "Play" }) {
+            // start over if a clip that doesn't loop already finished
+            if !object.animator.playing && !object.animator.looping && object.animator.time >= duration {
+                object.animator.time = 0.0;
+            }
+            object.animator.playing = !object.animator.playing;
+        }
+        ui.same_line();
+        ui.checkbox("Loop", &mut object.animator.looping);
+
+        ui.slider_config("Time", 0.0, duration)
+            .display_format("%.2fs")
+            .build(&mut object.animator.time);
+
+        imgui::Drag::new("Speed")
+            .range(-10.0, 10.0)
+            .speed(0.01)
+            .display_format("%.2fx")
+            .build(ui, &mut object.animator.speed);
+
+        let mut interpolation = object.animator.interpolation.index();
+        if ui.combo_simple_string("Interpolation", &mut interpolation, &animation::Interpolation::LABELS) {
+            object.animator.interpolation = animation::Interpolation::from_index(interpolation);
+        }
+    });
+}
+
+fn draw_animation_window(ui: &imgui::Ui, state: &mut State) {
+    ui.window("Animation")
+        .size([500.0, 200.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            for (i, object) in state.objects.iter_mut().enumerate() {
+                let _id = ui.push_id_usize(i);
+                if ui.collapsing_header(&object.name, imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                    draw_animation_controls(ui, object);
+                }
+            }
+        });
+}
+
+fn draw_history_window(ui: &imgui::Ui, state: &mut State) {
+    ui.window("History")
+        .size([500.0, 200.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            let undo_count = state.history.undo_count();
+            // clicking a step undoes or redoes everything up to it
+            let mut target = None;
+
+            if ui.selectable_config("Initial State").selected(undo_count == 0).build() {
+                target = Some(0);
+            }
+            for (i, label) in state.history.undo_labels().enumerate() {
+                let _id = ui.push_id_usize(i);
+                if ui.selectable_config(label).selected(i + 1 == undo_count).build() {
+                    target = Some(i + 1);
+                }
+            }
+            let style = ui.push_style_color(imgui::StyleColor::Text, ui.style_color(imgui::StyleColor::TextDisabled));
+            for (i, label) in state.history.redo_labels().enumerate() {
+                let _id = ui.push_id_usize(undo_count + i);
+                if ui.selectable(label) {
+                    target = Some(undo_count + i + 1);
+                }
+            }
+            style.pop();
+
+            if let Some(target) = target {
+                let (history, mut scene) = state.history_scene();
+                while history.undo_count() > target && history.undo(&mut scene).is_some() {}
+                while history.undo_count() < target && history.redo(&mut scene).is_some() {}
+            }
+
+            ui.separator();
+            if ui.button("Clear") {
+                state.history.clear();
+            }
         });
 }
 
@@ -249,6 +1031,8 @@ fn create_initial_docking(ui: &imgui::Ui, state: &mut State) {
                             0.3,
                             |right| {
                                 right.dock_window("Objects");
+                                right.dock_window("Lights");
+                                right.dock_window("Environment");
                             },
                             |left| {
                                 left.dock_window("Scene");
@@ -256,7 +1040,17 @@ fn create_initial_docking(ui: &imgui::Ui, state: &mut State) {
                         );
                     },
                     |bottom| {
-                        bottom.dock_window("Console");
+                        bottom.split(
+                            imgui::Direction::Right,
+                            0.3,
+                            |right| {
+                                right.dock_window("Animation");
+                                right.dock_window("History");
+                            },
+                            |left| {
+                                left.dock_window("Console");
+                            },
+                        );
                     }
                 )
             }
@@ -266,15 +1060,271 @@ fn create_initial_docking(ui: &imgui::Ui, state: &mut State) {
     rounding.pop();
 }
 
+fn draw_shadow_settings(ui: &imgui::Ui, settings: &mut shadow::ShadowSettings) {
+    ui.checkbox("Cast Shadows", &mut settings.enabled);
+    if !settings.enabled {
+        return;
+    }
+
+    imgui::Drag::new("Bias")
+        .range(0.0, 0.05)
+        .speed(0.00005)
+        .display_format("%.5f")
+        .build(ui, &mut settings.bias);
+    imgui::Drag::new("Normal Bias")
+        .range(0.0, 1.0)
+        .speed(0.001)
+        .display_format("%.3f")
+        .build(ui, &mut settings.normal_bias);
+    ui.slider("PCF Radius", 0, 4, &mut settings.pcf_radius);
+}
+
+/// Returns true if the light should be removed
+fn draw_light(ui: &imgui::Ui, light: &mut light::Light, idx: usize) -> bool {
+    let mut remove = false;
+
+    ui.tree_node_config(format!("{}###light-{}", light.name, idx))
+        .build(|| {
+            ui.input_text("Name", &mut light.name).build();
+
+            let mut kind = light.kind.index();
+            if ui.combo_simple_string("Type", &mut kind, &light::LightKind::LABELS) {
+                light.kind = light::LightKind::from_index(kind);
+            }
+
+            let mut color = *light.color.as_array();
+            if ui.color_edit3("Color", &mut color) {
+                light.color = glm::vec3(color[0], color[1], color[2]);
+            }
+            imgui::Drag::new("Intensity")
+                .range(0.0, 10000.0)
+                .speed(0.05)
+                .display_format("%.2f")
+                .build(ui, &mut light.intensity);
+
+            if light.kind != light::LightKind::Directional {
+                ui.checkbox("Follow Camera", &mut light.follow_camera);
+            }
+
+            if light.kind != light::LightKind::Directional && !light.follow_camera {
+                let mut position = *light.position.as_array();
+                if imgui::Drag::new("Position").speed(0.05).build_array(ui, &mut position) {
+                    light.position = glm::vec3(position[0], position[1], position[2]);
+                }
+            }
+            if light.kind != light::LightKind::Point && !light.follow_camera {
+                let mut direction = *light.direction.as_array();
+                if imgui::Drag::new("Direction").speed(0.01).range(-1.0, 1.0).build_array(ui, &mut direction) {
+                    let direction = glm::vec3(direction[0], direction[1], direction[2]);
+                    // a zero direction can't be normalized
+                    if glm::length(direction) > 0.0 {
+                        light.direction = direction;
+                    }
+                }
+            }
+            if light.kind != light::LightKind::Directional {
+                imgui::Drag::new("Range")
+                    .range(0.0, 1000.0)
+                    .speed(0.1)
+                    .display_format("%.1f")
+                    .build(ui, &mut light.range);
+                if ui.is_item_hovered() {
+                    ui.tooltip_text("0 never fades out");
+                }
+            }
+            if light.kind == light::LightKind::Spot {
+                ui.slider("Inner Angle", 0.0, 89.0, &mut light.inner_angle);
+                ui.slider("Outer Angle", 0.0, 89.0, &mut light.outer_angle);
+            }
+
+            draw_shadow_settings(ui, &mut light.shadow);
+
+            if ui.button("Remove") {
+                remove = true;
+            }
+        });
+
+    remove
+}
+
+fn draw_lights_window(ui: &imgui::Ui, state: &mut State) {
+    ui.window("Lights")
+        .size([500.0, 200.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            let mut ambient = *state.ambient_light.as_array();
+            if ui.color_edit3("Ambient", &mut ambient) {
+                state.ambient_light = glm::vec3(ambient[0], ambient[1], ambient[2]);
+            }
+
+            if ui.button("Add Light") {
+                ui.open_popup("add_light");
+            }
+            ui.popup("add_light", || {
+                for (i, label) in light::LightKind::LABELS.iter().enumerate() {
+                    if ui.menu_item(label) {
+                        let light = light::Light::new(&format!("{} Light", label), light::LightKind::from_index(i));
+                        let command = history::Command::AddLight { index: state.lights.len(), light: Some(light) };
+                        let (history, mut scene) = state.history_scene();
+                        history.execute(format!("Add {} Light", label), command, &mut scene);
+                    }
+                }
+            });
+            ui.same_line();
+            ui.text_disabled(format!("(shadows: {} directional, {} spot, {} point at most)",
+                shadow::MAX_DIR_SHADOWS, shadow::MAX_SPOT_SHADOWS, shadow::MAX_POINT_SHADOWS));
+
+            let mut i = 0;
+            while i < state.lights.len() {
+                let before = state.lights[i].clone();
+                let remove = draw_light(ui, &mut state.lights[i], i);
+                if state.lights[i] != before {
+                    state.history.push(format!("Edit {}", state.lights[i].name), history::Command::Light {
+                        index: i,
+                        before: Box::new(before),
+                        after: Box::new(state.lights[i].clone()),
+                    });
+                }
+                if remove {
+                    let label = format!("Remove {}", state.lights[i].name);
+                    let (history, mut scene) = state.history_scene();
+                    history.execute(label, history::Command::RemoveLight { index: i, light: None }, &mut scene);
+                    continue;
+                }
+
+                i += 1;
+            }
+        });
+}
+
+fn draw_environment_window(ui: &imgui::Ui, state: &mut State) {
+    ui.window("Environment")
+        .size([500.0, 200.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            if ui.button("Load HDRI") {
+                if let Some(path) = rfd::FileDialog::new()
+                    .set_title("Load Environment")
+                    .set_directory("./")
+                    .add_filter("All supported files", &["hdr", "exr"])
+                    .add_filter("Radiance HDR (.hdr)", &["hdr"])
+                    .add_filter("OpenEXR (.exr)", &["exr"])
+                    .pick_file() {
+                        // replaces an environment that's still loading
+                        state.environment_job = Some(environment::EnvironmentJob::spawn(path));
+                    }
+            }
+            ui.same_line();
+            ui.disabled(state.environment.is_none(), || {
+                if ui.button("Clear") {
+                    state.environment = None;
+                }
+            });
+
+            if let Some(job) = &state.environment_job {
+                ui.text_disabled(format!("Loading {}...", job.name()));
+            } else if let Some(env) = &state.environment {
+                ui.text(env.name());
+            } else {
+                ui.text_disabled("No environment, the ambient light from the Lights window is used");
+            }
+
+            let settings = &mut state.environment_settings;
+            ui.checkbox("Show Skybox", &mut settings.show_skybox);
+            ui.slider("Rotation", -180.0, 180.0, &mut settings.rotation);
+            imgui::Drag::new("Intensity")
+                .range(0.0, f32::MAX)
+                .speed(0.01)
+                .build(ui, &mut settings.intensity);
+            ui.slider("Blur", 0.0, 1.0, &mut settings.blur);
+        });
+}
+
+fn draw_shadow_maps(ui: &imgui::Ui, state: &mut State) {
+    if !state.shadow_maps_shown {
+        state.shadow_previews.clear();
+        return;
+    }
+
+    let mut opened = state.shadow_maps_shown;
+    ui.window("Shadow Maps")
+        .size([420.0, 600.0], imgui::Condition::FirstUseEver)
+        .opened(&mut opened)
+        .build(|| {
+            let mut shown = 0;
+            for (i, light) in state.lights.iter().enumerate() {
+                let texture = match state.shadow_previews.get(i) {
+                    Some(Some(texture)) => *texture,
+                    _ => continue,
+                };
+                shown += 1;
+
+                let _id = ui.push_id_usize(i);
+                if ui.collapsing_header(&light.name, imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                    let mut w = 0;
+                    let mut h = 0;
+                    unsafe {
+                        gl::GetTextureLevelParameteriv(texture, 0, gl::TEXTURE_WIDTH, &mut w);
+                        gl::GetTextureLevelParameteriv(texture, 0, gl::TEXTURE_HEIGHT, &mut h);
+                    }
+                    imgui::Image::new(imgui::TextureId::new(texture as usize), [w as f32, h as f32])
+                        // flip the image vertically
+                        .uv0([0.0, 1.0])
+                        .uv1([1.0, 0.0])
+                        .build(ui);
+                }
+            }
+
+            if shown == 0 {
+                ui.text_disabled("No lights cast shadows");
+            }
+        });
+    state.shadow_maps_shown = opened;
+}
+
+fn draw_gizmo_toolbar(ui: &imgui::Ui, settings: &mut gizmo::GizmoSettings) {
+    for (i, label) in gizmo::GizmoMode::LABELS.iter().enumerate() {
+        if ui.radio_button_bool(label, settings.mode.index() == i) {
+            settings.mode = gizmo::GizmoMode::from_index(i);
+        }
+        ui.same_line();
+    }
+
+    ui.set_next_item_width(80.0);
+    let mut space = settings.space.index();
+    if ui.combo_simple_string("Space", &mut space, &gizmo::GizmoSpace::LABELS) {
+        settings.space = gizmo::GizmoSpace::from_index(space);
+    }
+    ui.same_line();
+    ui.checkbox("Snap", &mut settings.snap);
+    if ui.is_item_hovered() {
+        ui.tooltip_text("Hold Ctrl while dragging to toggle");
+    }
+
+    ui.disabled(!settings.snap, || {
+        for (label, value, format) in [
+            ("###TranslateSnap", &mut settings.translate_snap, "%.2f u"),
+            ("###RotateSnap", &mut settings.rotate_snap, "%.1f deg"),
+            ("###ScaleSnap", &mut settings.scale_snap, "%.2fx"),
+        ] {
+            ui.same_line();
+            ui.set_next_item_width(70.0);
+            imgui::Drag::new(label)
+                .range(0.001, f32::MAX)
+                .speed(0.01)
+                .display_format(format)
+                .build(ui, value);
+        }
+    });
+}
+
+const MSAA_SAMPLES: [i32; 4] = [1, 2, 4, 8];
+const MSAA_LABELS: [&str; 4] = ["Off", "2x", "4x", "8x"];
+
 fn draw_viewport(ui: &imgui::Ui, state: &mut State, texture: u32) {
     ui.window("Scene")
         .size(ui.content_region_avail(), imgui::Condition::FirstUseEver)
         .no_decoration()
         .resizable(true)
         .build(|| {
-            let size = ui.content_region_avail();
-            state.viewport_size = size;
-
             if ui.button("Reset Camera") {
                 state.camera.reset();
             }
@@ -303,7 +1353,6 @@ fn draw_viewport(ui: &imgui::Ui, state: &mut State, texture: u32) {
                 let capture = image::ImageBuffer::<image::Rgba<u8>, _>::from_raw(w as u32, h as u32, pixels).unwrap();
                 let capture = image::DynamicImage::ImageRgba8(capture);
                 let capture = capture.flipv();
-                let capture = capture.resize_exact(size[0] as u32, size[1] as u32, image::imageops::FilterType::Gaussian);
                 let _ = capture.save(save_path);
                 let elapsed = now.elapsed();
 
@@ -330,17 +1379,67 @@ fn draw_viewport(ui: &imgui::Ui, state: &mut State, texture: u32) {
             ui.same_line();
             ui.checkbox("Wireframe", &mut state.wireframe);
             ui.same_line();
+            ui.set_next_item_width(60.0);
+            // only offer sample counts the driver supports
+            let supported = MSAA_SAMPLES.iter().filter(|s| **s <= state.max_samples).count();
+            let mut msaa = MSAA_SAMPLES.iter().position(|s| *s == state.msaa_samples).unwrap_or(0);
+            if ui.combo_simple_string("MSAA", &mut msaa, &MSAA_LABELS[..supported]) {
+                state.msaa_samples = MSAA_SAMPLES[msaa];
+            }
+            ui.same_line();
+            ui.set_next_item_width(100.0);
+            let mut shading_model = state.shading_model.index();
+            if ui.combo_simple_string("Shading", &mut shading_model, &material::ShadingModel::LABELS) {
+                state.shading_model = material::ShadingModel::from_index(shading_model);
+            }
+            ui.same_line();
+            ui.set_next_item_width(120.0);
+            let mut tone_mapping = state.tone_mapping.index();
+            if ui.combo_simple_string("Tone Mapping", &mut tone_mapping, &post::ToneMapping::LABELS) {
+                state.tone_mapping = post::ToneMapping::from_index(tone_mapping);
+            }
+            ui.same_line();
+            ui.set_next_item_width(100.0);
+            ui.slider_config("Exposure", -5.0, 5.0)
+                .display_format("%.2f EV")
+                .build(&mut state.exposure);
+            ui.same_line();
             ui.set_next_item_width(200.0);
             imgui::Drag::new("Camera Speed")
                 .range(1.0, 10000.0)
                 .speed(1.0)
                 .display_format("%.3f")
                 .build(ui, &mut state.camera.speed);
+            draw_gizmo_toolbar(ui, &mut state.gizmo.settings);
+
+            let size = ui.content_region_avail();
+            state.viewport_size = size;
             imgui::Image::new(imgui::TextureId::new(texture.try_into().unwrap()), size)
                 // flip the image vertically
                 .uv0([0.0, 1.0])
                 .uv1([1.0, 0.0])
                 .build(ui);
+            let image_min = ui.item_rect_min();
+            let image_clicked = ui.is_item_clicked();
+            let image_hovered = ui.is_item_hovered() && !state.is_cursor_captured;
+            state.scene_hovered = ui.is_window_hovered();
+
+            let mut gizmo_used = false;
+            if let Some(selection) = state.selection {
+                if let Some(object) = state.objects.get_mut(selection.object) {
+                    let view = state.camera.view_matrix();
+                    let projection = state.camera.projection_matrix(size[0] / size[1]);
+                    let before = history::NodeTransform::of(object, selection.node);
+                    gizmo_used = state.gizmo.manipulate(ui, object, selection.node, &view, &projection, image_min, size, image_hovered);
+                    state.history.record_transform(object, selection.object, selection.node, before);
+                }
+            }
+
+            if image_clicked && !gizmo_used {
+                let [mouse_x, mouse_y] = ui.io().mouse_pos;
+                // the framebuffer starts at the bottom
+                state.pick_request = Some([(mouse_x - image_min[0]) as i32, size[1] as i32 - 1 - (mouse_y - image_min[1]) as i32]);
+            }
         });
 }
 
@@ -359,7 +1458,11 @@ pub fn draw_ui(
     let ui = imgui.new_frame();
     create_initial_docking(ui, state);
 
+    update_imports(state);
+
     draw_main_menu_bar(ui, state, window, delta_time);
+    draw_import_dialog(ui, state);
+    draw_import_progress(ui, state);
 
     if state.camera_coords_shown {
         ui.window("Camera Coordinates")
@@ -370,10 +1473,32 @@ pub fn draw_ui(
             });
     }
 
+    if state.gl_objects_shown {
+        ui.window("GL Objects")
+            .size([250.0, 180.0], imgui::Condition::FirstUseEver)
+            .opened(&mut state.gl_objects_shown)
+            .build(|| {
+                for kind in gl_object::Kind::ALL {
+                    ui.text(format!("{}: {}", gl_object::Kind::LABELS[kind.index()], kind.live_count()));
+                }
+            });
+    }
+
+    draw_shadow_maps(ui, state);
     draw_objects_window(ui, state);
+    draw_lights_window(ui, state);
+    draw_environment_window(ui, state);
+    draw_animation_window(ui, state);
+    draw_history_window(ui, state);
     draw_log(ui, state);
     draw_viewport(ui, state, scene_fb_texture);
 
+    // a drag or text edit is one step, the next edit after letting go starts a new one
+    if !ui.is_any_item_active() && !ui.is_mouse_down(imgui::MouseButton::Left) {
+        state.history.end_merge();
+    }
+    state.text_input_active = ui.io().want_text_input;
+
     ui.end_frame_early();
 
     if !state.is_cursor_captured {
@@ -2,13 +2,63 @@ use glad_gl::gl;
 use glm;
 use anyhow::{Result, Context};
 
-pub fn load_texture(path: &str) -> Result<u32> {
-    let tex = image::io::Reader::open(path)
+use crate::gl_object::GlTexture;
+
+/// Reads an image file without touching gl so it can run on any thread
+pub fn decode_texture(path: &str) -> Result<image::DynamicImage> {
+    image::io::Reader::open(path)
         .with_context(|| format!("Failed to open texture file: {}", path))?
         .decode()
-        .with_context(|| format!("Failed to decode texture: {}", path))?;
+        .with_context(|| format!("Failed to decode texture: {}", path))
+}
+
+/// Decodes a compressed image (png, jpeg, etc..) that's already in memory, e.g. embedded in a .glb
+pub fn decode_texture_from_memory(bytes: &[u8]) -> Result<image::DynamicImage> {
+    image::load_from_memory(bytes)
+        .context("Failed to decode embedded texture")
+}
+
+/// Wraps raw 8 bit rgba pixels
+pub fn texture_from_rgba(width: u32, height: u32, pixels: Vec<u8>) -> Result<image::DynamicImage> {
+    let tex = image::RgbaImage::from_raw(width, height, pixels)
+        .with_context(|| format!("Texture data doesn't match its size: {}x{}", width, height))?;
+
+    Ok(image::DynamicImage::ImageRgba8(tex))
+}
+
+/// Whether every pixel is a shade of gray, like the heights of a bump map
+pub fn is_grayscale(image: &image::DynamicImage) -> bool {
+    match image.color().channel_count() {
+        1 | 2 => true,
+        _ => image.to_rgb8().pixels().all(|p| p[0] == p[1] && p[1] == p[2]),
+    }
+}
+
+/// Turns a height map into a tangent space normal map with a sobel filter, the edges wrap around like the
+/// texture does. `strength` scales the slopes
+pub fn normal_map_from_heights(image: &image::DynamicImage, strength: f32) -> image::DynamicImage {
+    let heights = image.to_luma8();
+    let (w, h) = (heights.width() as i64, heights.height() as i64);
+    let height = |x: i64, y: i64| heights.get_pixel(x.rem_euclid(w) as u32, y.rem_euclid(h) as u32)[0] as f32 / 255.0;
+
+    let normals = image::RgbImage::from_fn(w as u32, h as u32, |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let dx = height(x + 1, y - 1) + 2.0 * height(x + 1, y) + height(x + 1, y + 1)
+            - height(x - 1, y - 1) - 2.0 * height(x - 1, y) - height(x - 1, y + 1);
+        let dy = height(x - 1, y + 1) + 2.0 * height(x, y + 1) + height(x + 1, y + 1)
+            - height(x - 1, y - 1) - 2.0 * height(x, y - 1) - height(x + 1, y - 1);
+
+        let normal = glm::normalize(glm::vec3(-dx * strength, -dy * strength, 1.0));
+        let encode = |n: f32| ((n * 0.5 + 0.5) * 255.0).round() as u8;
+        image::Rgb([encode(normal.x), encode(normal.y), encode(normal.z)])
+    });
+
+    image::DynamicImage::ImageRgb8(normals)
+}
 
-    let mut texture_id: u32 = 0;
+/// `srgb` textures are converted to linear by the gpu when they're sampled
+pub fn upload_texture(tex: &image::DynamicImage, srgb: bool) -> GlTexture {
+    let texture = GlTexture::new();
     let format = match tex.color().channel_count() {
         1 => gl::RED,
         2 => gl::RG,
@@ -16,10 +66,15 @@ pub fn load_texture(path: &str) -> Result<u32> {
         4 => gl::RGBA,
         _ => panic!("Unknown image format")
     };
+    // there are no srgb formats for 1 or 2 channels, those are never colors anyway
+    let internal_format = match (srgb, format) {
+        (true, gl::RGB) => gl::SRGB8,
+        (true, gl::RGBA) => gl::SRGB8_ALPHA8,
+        _ => format,
+    };
 
     unsafe {
-        gl::GenTextures(1, &mut texture_id);
-        gl::BindTexture(gl::TEXTURE_2D, texture_id);
+        gl::BindTexture(gl::TEXTURE_2D, texture.id());
 
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
@@ -27,11 +82,11 @@ pub fn load_texture(path: &str) -> Result<u32> {
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
 
-        gl::TexImage2D(gl::TEXTURE_2D, 0, format as i32, tex.width() as i32, tex.height() as i32, 0, format, gl::UNSIGNED_BYTE, tex.as_bytes().as_ptr() as *const std::ffi::c_void);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format as i32, tex.width() as i32, tex.height() as i32, 0, format, gl::UNSIGNED_BYTE, tex.as_bytes().as_ptr() as *const std::ffi::c_void);
         gl::GenerateMipmap(gl::TEXTURE_2D);
     }
 
-    Ok(texture_id)
+    texture
 }
 
 pub fn mat_ident() -> glm::Mat4 {
@@ -42,3 +97,33 @@ pub fn mat_ident() -> glm::Mat4 {
         0., 0., 0., 1.
     )
 }
+
+/// Column major, the way gl and gltf expect matrices
+pub fn mat4_to_array(m: &glm::Mat4) -> [f32; 16] {
+    let mut array = [0.0; 16];
+    for (i, column) in m.as_array().iter().enumerate() {
+        array[i * 4..i * 4 + 4].copy_from_slice(column.as_array());
+    }
+
+    array
+}
+
+/// Same as glOrtho
+pub fn ortho(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> glm::Mat4 {
+    glm::mat4(
+        2.0 / (right - left), 0., 0., 0.,
+        0., 2.0 / (top - bottom), 0., 0.,
+        0., 0., -2.0 / (far - near), 0.,
+        -(right + left) / (right - left), -(top + bottom) / (top - bottom), -(far + near) / (far - near), 1.
+    )
+}
+
+/// assimp matrices are row major, glm matrices are column major
+pub fn mat4_from_assimp(m: &russimp::Matrix4x4) -> glm::Mat4 {
+    glm::mat4(
+        m.a1, m.b1, m.c1, m.d1,
+        m.a2, m.b2, m.c2, m.d2,
+        m.a3, m.b3, m.c3, m.d3,
+        m.a4, m.b4, m.c4, m.d4,
+    )
+}